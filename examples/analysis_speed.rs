@@ -0,0 +1,45 @@
+//! Compares analyzing a large commit range on one thread and on rayon's thread pool.
+//!
+//!     cargo run --release --example analysis_speed [COMMITS]
+
+extern crate semantic_rs;
+
+use semantic_rs::commit_analyzer::{self, AnalyzerOptions};
+use std::env;
+use std::time::{Duration, Instant};
+
+const SUBJECTS: &'static [&'static str] = &[
+    "fix(parser): handle empty input",
+    "feat: add a quiet flag",
+    "docs: explain the release workflow",
+    "chore: update dependencies",
+    "Merge pull request #42 from user/branch",
+    "refactor(git)!: drop the old walker\n\nBREAKING CHANGE: commits_since is gone",
+];
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + (duration.subsec_nanos() / 1_000_000) as u64
+}
+
+fn main() {
+    let count = env::args().nth(1).and_then(|n| n.parse().ok()).unwrap_or(50_000);
+    let commits = (0..count)
+        .map(|i| format!("{:040x}\n{}\n\nDetails of change {}.", i, SUBJECTS[i % SUBJECTS.len()], i))
+        .collect::<Vec<_>>();
+    let options = AnalyzerOptions::default();
+
+    let start = Instant::now();
+    let sequential = commit_analyzer::analyze_each(&commits, &options);
+    let sequential_time = start.elapsed();
+
+    let start = Instant::now();
+    let parallel = commit_analyzer::analyze_each_parallel(&commits, &options);
+    let parallel_time = start.elapsed();
+
+    assert_eq!(sequential.len(), parallel.len());
+    assert!(sequential.iter().zip(&parallel).all(|(a, b)| a.commit_type == b.commit_type));
+
+    println!("{} commits", count);
+    println!("sequential: {} ms", millis(sequential_time));
+    println!("parallel:   {} ms", millis(parallel_time));
+}
@@ -0,0 +1,94 @@
+//! Compares reading a release range the way semantic-rs used to, with clog walking the history
+//! a second time for the changelog, with reading it once and rendering the changelog from the entries.
+//!
+//!     cargo run --release --example history_walk [COMMITS]
+
+extern crate clog;
+extern crate git2;
+extern crate semantic_rs;
+extern crate tempdir;
+
+use clog::Clog;
+use clog::fmt::MarkdownWriter;
+use git2::{Repository, Signature};
+use semantic_rs::changelog::{self, ChangelogOptions};
+use semantic_rs::commit_analyzer::CommitType;
+use std::env;
+use std::io::BufWriter;
+use std::time::{Duration, Instant};
+use tempdir::TempDir;
+
+const SUBJECTS: &'static [&'static str] = &[
+    "fix(parser): handle empty input",
+    "feat: add a quiet flag",
+    "docs: explain the release workflow",
+    "chore: update dependencies",
+    "Merge pull request #42 from user/branch",
+    "refactor(git)!: drop the old walker\n\nBREAKING CHANGE: commits_since is gone",
+];
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + (duration.subsec_nanos() / 1_000_000) as u64
+}
+
+/// A repository with `v1.0.0` tagged on the root commit and `count` commits on top.
+fn fixture(count: usize) -> TempDir {
+    let dir = TempDir::new("semantic-rs-history").unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    let signature = Signature::now("semantic-rs", "semantic-rs@example.com").unwrap();
+    let tree_id = repo.index().unwrap().write_tree().unwrap();
+    let tree = repo.find_tree(tree_id).unwrap();
+
+    let mut head = repo.commit(Some("HEAD"), &signature, &signature, "Initial commit", &tree, &[]).unwrap();
+    {
+        let root = repo.find_object(head, None).unwrap();
+        repo.tag_lightweight("v1.0.0", &root, false).unwrap();
+    }
+    for i in 0..count {
+        let parent = repo.find_commit(head).unwrap();
+        let message = format!("{}\n\nDetails of change {}.", SUBJECTS[i % SUBJECTS.len()], i);
+        head = repo.commit(Some("HEAD"), &signature, &signature, &message, &tree, &[&parent]).unwrap();
+    }
+    dir
+}
+
+/// The changelog as clog rendered it, reading the history itself.
+fn clog_changelog(path: &str) -> String {
+    let mut clog = Clog::with_dir(path).unwrap();
+    clog.from("v1.0.0").version("v1.1.0");
+
+    let mut out = BufWriter::new(Vec::new());
+    {
+        let mut writer = MarkdownWriter::new(&mut out);
+        clog.write_changelog_with(&mut writer).unwrap();
+    }
+    String::from_utf8(out.into_inner().unwrap()).unwrap()
+}
+
+fn main() {
+    let count = env::args().nth(1).and_then(|n| n.parse().ok()).unwrap_or(20_000);
+    let dir = fixture(count);
+    let path = dir.path().to_str().unwrap();
+    let options = ChangelogOptions::default();
+
+    // Before: the bump from one walk, the changelog from clog's own walk
+    let start = Instant::now();
+    let entries = changelog::entries_since(path, "v1.0.0", &options.analyzer, &options.walk).unwrap();
+    let before_bump = CommitType::max_of(entries.iter().map(|entry| entry.commit.commit_type));
+    let before = clog_changelog(path);
+    let before_time = start.elapsed();
+
+    // After: both from the same walk
+    let start = Instant::now();
+    let entries = changelog::entries_since(path, "v1.0.0", &options.analyzer, &options.walk).unwrap();
+    let after_bump = CommitType::max_of(entries.iter().map(|entry| entry.commit.commit_type));
+    let after = changelog::generate(path, "v1.1.0", &entries, &options).unwrap();
+    let after_time = start.elapsed();
+
+    assert_eq!(before_bump, after_bump);
+    assert!(!before.is_empty() && !after.is_empty());
+
+    println!("{} commits, {:?} bump", count, after_bump);
+    println!("walking twice (clog): {} ms", millis(before_time));
+    println!("walking once:         {} ms", millis(after_time));
+}
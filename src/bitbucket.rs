@@ -0,0 +1,94 @@
+use std::io::Read;
+use hyper::header::{Authorization, Headers, UserAgent};
+use rustc_serialize::base64::{ToBase64, STANDARD};
+use error::Error;
+use super::USERAGENT;
+use config::Config;
+use utils::https_client;
+
+const API_URL: &'static str = "https://api.bitbucket.org/2.0";
+const BOUNDARY: &'static str = "semantic-rs-release-notes";
+
+pub fn is_bitbucket_url(url: &str) -> bool {
+    url.contains("bitbucket.org")
+}
+
+/// `BITBUCKET_TOKEN` is either a repository access token or `username:app_password`.
+fn authorization(token: &str) -> String {
+    if token.contains(':') {
+        format!("Basic {}", token.as_bytes().to_base64(STANDARD))
+    } else {
+        format!("Bearer {}", token)
+    }
+}
+
+/// Username and password to push over HTTPS with `token`.
+pub fn push_credentials(token: &str) -> (String, String) {
+    let mut parts = token.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some(user), Some(password)) => (user.to_owned(), password.to_owned()),
+        _ => ("x-token-auth".to_owned(), token.to_owned()),
+    }
+}
+
+fn multipart_body(file_name: &str, content: &str) -> String {
+    format!("--{boundary}\r\n\
+             Content-Disposition: form-data; name=\"files\"; filename=\"{name}\"\r\n\
+             Content-Type: text/markdown\r\n\r\n\
+             {content}\r\n\
+             --{boundary}--\r\n",
+            boundary = BOUNDARY, name = file_name, content = content)
+}
+
+/// Bitbucket Cloud has no releases, the tag is pushed already.
+/// The release notes are uploaded to the repository's downloads instead.
+pub fn release(config: &Config, tag_name: &str, tag_message: &str) -> Result<(), Error> {
+    let user      = &config.user.as_ref().unwrap()[..];
+    let repo_name = &config.repository_name.as_ref().unwrap()[..];
+    let token     = config.bitbucket_token.as_ref().unwrap();
+
+    let url = format!("{}/repositories/{}/{}/downloads", API_URL, user, repo_name);
+    let file_name = format!("{}-release-notes.md", tag_name);
+    let body = multipart_body(&file_name, tag_message);
+
+    let mut headers = Headers::new();
+    headers.set(Authorization(authorization(token)));
+    headers.set(UserAgent(USERAGENT.to_owned()));
+    headers.set_raw("Content-Type", vec![format!("multipart/form-data; boundary={}", BOUNDARY).into_bytes()]);
+
+    let client = https_client();
+    let mut response = try!(client.post(&url)
+        .headers(headers)
+        .body(&body[..])
+        .send());
+
+    if response.status.is_success() {
+        Ok(())
+    } else {
+        let mut message = String::new();
+        let _ = response.read_to_string(&mut message);
+        Err(Error::Api(format!("Bitbucket responded with {}: {}", response.status, message)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn credentials_from_token() {
+        assert_eq!(("jan".to_owned(), "app-password".to_owned()), push_credentials("jan:app-password"));
+        assert_eq!(("x-token-auth".to_owned(), "secret".to_owned()), push_credentials("secret"));
+
+        assert_eq!("Basic amFuOnB3", authorization("jan:pw"));
+        assert_eq!("Bearer secret", authorization("secret"));
+    }
+
+    #[test]
+    fn release_notes_upload() {
+        let body = multipart_body("v1.0.0-release-notes.md", "## v1.0.0");
+        assert!(body.starts_with("--semantic-rs-release-notes\r\n"));
+        assert!(body.contains("filename=\"v1.0.0-release-notes.md\"\r\n"));
+        assert!(body.ends_with("## v1.0.0\r\n--semantic-rs-release-notes--\r\n"));
+    }
+}
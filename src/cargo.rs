@@ -1,36 +1,445 @@
-use std::process::Command;
+use hyper::header::UserAgent;
+use hyper::status::StatusCode;
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
-pub fn update_lockfile(repository_path: &str) -> bool {
-    let manifest_path = format!("{}/Cargo.toml", repository_path);
-    Command::new("cargo")
-        .arg("fetch")
-        .arg("--manifest-path")
-        .arg(manifest_path)
-        .status()
-        .map(|s| s.success())
-        .unwrap_or(false)
+use error::Error;
+use logger;
+use toml_file::WorkspacePackage;
+use super::USERAGENT;
+use utils::https_client;
+
+const CRATES_IO_API: &'static str = "https://crates.io/api/v1";
+
+const PUBLISH_POLL_INTERVAL_SECS: u64 = 5;
+
+/// Single packaged files bigger than this are pointed out.
+const LARGE_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Extensions of build output and archives, which rarely belong into a published crate.
+const ARTIFACT_EXTENSIONS: &'static [&'static str] = &[
+    ".exe", ".dll", ".so", ".dylib", ".a", ".lib", ".rlib", ".o", ".obj", ".pdb",
+    ".zip", ".tar", ".tar.gz", ".tgz", ".crate",
+];
+
+/// Extra flags passed through to `cargo package` and `cargo publish`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CargoOptions {
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+    /// Skip the build-check of the packaged crate.
+    pub no_verify: bool,
+}
+
+impl CargoOptions {
+    fn args(&self) -> Vec<String> {
+        let mut args = vec![];
+
+        if !self.features.is_empty() {
+            args.push("--features".into());
+            args.push(self.features.join(" "));
+        }
+        if self.all_features {
+            args.push("--all-features".into());
+        }
+        if self.no_default_features {
+            args.push("--no-default-features".into());
+        }
+        if self.no_verify {
+            args.push("--no-verify".into());
+        }
+
+        args
+    }
+}
+
+/// Runs cargo with `args` and collects everything it prints. In verbose mode each line
+/// is passed on as it comes, otherwise nothing is shown. On failure the output is the error.
+fn run_cargo(args: &[String]) -> Result<String, String> {
+    let mut child = try!(Command::new("cargo")
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("Could not run cargo: {}", err)));
+
+    // Both pipes are drained at once, otherwise cargo blocks as soon as one of them is full
+    let stdout = child.stdout.take().unwrap();
+    let stdout_reader = thread::spawn(move || forward_lines(stdout));
+    let mut output = forward_lines(child.stderr.take().unwrap());
+    output.push_str(&stdout_reader.join().unwrap_or_default());
+
+    let status = try!(child.wait().map_err(|err| format!("Could not run cargo: {}", err)));
+    if status.success() {
+        Ok(output)
+    } else {
+        Err(output)
+    }
+}
+
+fn forward_lines<R: Read>(reader: R) -> String {
+    let mut output = String::new();
+    for line in BufReader::new(reader).lines().filter_map(|line| line.ok()) {
+        logger::debug(&line);
+        output.push_str(&line);
+        output.push('\n');
+    }
+    output
+}
+
+pub fn update_lockfile(repository_path: &str) -> Result<(), String> {
+    let args = vec![
+        "fetch".into(),
+        "--manifest-path".into(),
+        format!("{}/Cargo.toml", repository_path),
+    ];
+    run_cargo(&args).map(|_| ())
+}
+
+fn package_args(repository_path: &str, options: &CargoOptions) -> Vec<String> {
+    let mut args = vec![
+        "package".into(),
+        "--manifest-path".into(),
+        format!("{}/Cargo.toml", repository_path),
+    ];
+    args.extend(options.args());
+    args
+}
+
+fn publish_args(repository_path: &str, token: &str, registry: Option<&str>, options: &CargoOptions) -> Vec<String> {
+    let mut args = vec![
+        "publish".into(),
+        "--manifest-path".into(),
+        format!("{}/Cargo.toml", repository_path),
+        "--token".into(),
+        token.into(),
+    ];
+
+    if let Some(registry) = registry {
+        args.push("--registry".into());
+        args.push(registry.into());
+    }
+
+    args.extend(options.args());
+    args
 }
 
-pub fn package(repository_path: &str) -> bool {
-    let manifest_path = format!("{}/Cargo.toml", repository_path);
-    Command::new("cargo")
+/// Runs `cargo package`, on failure the error is cargo's output.
+pub fn package(repository_path: &str, options: &CargoOptions) -> Result<(), String> {
+    run_cargo(&package_args(repository_path, options)).map(|_| ())
+}
+
+/// A file `cargo package` includes, with its size on disk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackagedFile {
+    pub path: String,
+    pub size: u64,
+}
+
+/// The files `cargo package --list` would put into the crate.
+/// Files cargo generates while packaging, like `Cargo.toml.orig`, count as empty.
+pub fn package_list(repository_path: &str) -> Result<Vec<PackagedFile>, String> {
+    let output = try!(Command::new("cargo")
         .arg("package")
+        .arg("--list")
         .arg("--manifest-path")
-        .arg(manifest_path)
-        .status()
-        .map(|s| s.success())
-        .unwrap_or(false)
+        .arg(format!("{}/Cargo.toml", repository_path))
+        .output()
+        .map_err(|err| format!("Could not run cargo: {}", err)));
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+
+    let files = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|path| {
+            let size = fs::metadata(Path::new(repository_path).join(path)).map(|m| m.len()).unwrap_or(0);
+            PackagedFile { path: path.to_owned(), size: size }
+        })
+        .collect();
+    Ok(files)
 }
 
-pub fn publish(repository_path: &str, token: &str) -> bool {
-    let manifest_path = format!("{}/Cargo.toml", repository_path);
-    Command::new("cargo")
-        .arg("publish")
-        .arg("--manifest-path")
-        .arg(manifest_path)
-        .arg("--token")
-        .arg(token)
-        .status()
-        .map(|s| s.success())
-        .unwrap_or(false)
+fn is_artifact(path: &str) -> bool {
+    let path = path.to_lowercase();
+    path.starts_with("target/") || path.contains("/target/") ||
+        ARTIFACT_EXTENSIONS.iter().any(|extension| path.ends_with(extension))
+}
+
+/// The `count` biggest files, biggest first.
+pub fn largest_files(files: &[PackagedFile], count: usize) -> Vec<&PackagedFile> {
+    let mut files = files.iter().collect::<Vec<_>>();
+    files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    files.truncate(count);
+    files
+}
+
+/// Problems with the packaged files: a crate bigger than `max_size`,
+/// build artifacts and single large files.
+pub fn package_warnings(files: &[PackagedFile], max_size: u64) -> Vec<String> {
+    let mut warnings = vec![];
+
+    let total = files.iter().map(|file| file.size).sum::<u64>();
+    if total > max_size {
+        warnings.push(format!("The crate is {} large, more than the allowed {}", format_size(total), format_size(max_size)));
+    }
+
+    for file in files {
+        if is_artifact(&file.path) {
+            warnings.push(format!("{} looks like a build artifact, exclude it in Cargo.toml", file.path));
+        } else if file.size > LARGE_FILE_SIZE {
+            warnings.push(format!("{} is {} large", file.path, format_size(file.size)));
+        }
+    }
+
+    warnings
+}
+
+pub fn format_size(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+/// Where `cargo package` puts the `.crate` of `name`, in the target directory of the workspace at `root`.
+pub fn package_path(root: &str, name: &str, version: &str) -> PathBuf {
+    let target_dir = env::var("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| Path::new(root).join("target"));
+    target_dir.join("package").join(format!("{}-{}.crate", name, version))
+}
+
+/// The environment variable holding the token for `registry`,
+/// following cargo's own `CARGO_REGISTRIES_<NAME>_TOKEN` convention.
+/// crates.io uses `CARGO_TOKEN`.
+pub fn token_env_var(registry: Option<&str>) -> String {
+    match registry {
+        Some(registry) => format!("CARGO_REGISTRIES_{}_TOKEN", registry.to_uppercase().replace('-', "_")),
+        None => "CARGO_TOKEN".into(),
+    }
+}
+
+/// Runs `cargo publish`. On failure the error carries cargo's output,
+/// so it can be checked with `is_transient_failure`.
+pub fn publish(repository_path: &str, token: &str, registry: Option<&str>, options: &CargoOptions) -> Result<(), Error> {
+    run_cargo(&publish_args(repository_path, token, registry, options))
+        .map(|_| ())
+        .map_err(|output| Error::Publish(output.trim().to_owned()))
+}
+
+/// Makes sure publishing to crates.io can work, before anything is tagged or pushed:
+/// none of the crates may already exist in `version`.
+/// The token itself can't be checked up front, crates.io only accepts API tokens for publishing.
+pub fn check_crates_io(crates: &[String], version: &str) -> Result<(), Error> {
+    let client = https_client();
+
+    for name in crates {
+        let response = try!(client.get(&format!("{}/crates/{}/{}", CRATES_IO_API, name, version))
+            .header(UserAgent(USERAGENT.to_owned()))
+            .send());
+        match response.status {
+            StatusCode::NotFound => {},
+            StatusCode::Ok => {
+                return Err(Error::Api(format!("{} {} is already published on crates.io", name, version)));
+            },
+            status => return Err(Error::Api(format!("Looking up {} {} on crates.io failed with status {}", name, version, status))),
+        }
+    }
+
+    Ok(())
+}
+
+/// Orders the packages so that every crate comes after the workspace members it depends on.
+/// Otherwise the order of `packages` is kept.
+pub fn publish_order(packages: &[WorkspacePackage]) -> Result<Vec<&WorkspacePackage>, Error> {
+    let mut ordered: Vec<&WorkspacePackage> = vec![];
+
+    while ordered.len() < packages.len() {
+        let next = packages.iter()
+            .filter(|package| !ordered.iter().any(|o| o.name == package.name))
+            .find(|package| {
+                package.dependencies.iter()
+                    .filter(|dependency| **dependency != package.name)
+                    .filter(|dependency| packages.iter().any(|p| p.name == **dependency))
+                    .all(|dependency| ordered.iter().any(|o| o.name == *dependency))
+            });
+
+        match next {
+            Some(package) => ordered.push(package),
+            None => {
+                let remaining = packages.iter()
+                    .filter(|package| !ordered.iter().any(|o| o.name == package.name))
+                    .map(|package| &package.name[..])
+                    .collect::<Vec<_>>();
+                return Err(Error::Publish(format!("The workspace members {} depend on each other", remaining.join(", "))));
+            }
+        }
+    }
+
+    Ok(ordered)
+}
+
+/// Polls crates.io until `name` is available in `version`, so crates depending on it can be published.
+/// Returns `false` if it didn't show up within `timeout`.
+pub fn wait_for_version_published(name: &str, version: &str, timeout: Duration) -> Result<bool, Error> {
+    let client = https_client();
+    let url = format!("{}/crates/{}/{}", CRATES_IO_API, name, version);
+    let started = Instant::now();
+
+    loop {
+        let response = try!(client.get(&url)
+            .header(UserAgent(USERAGENT.to_owned()))
+            .send());
+        if response.status == StatusCode::Ok {
+            return Ok(true);
+        }
+
+        if started.elapsed() >= timeout {
+            return Ok(false);
+        }
+        thread::sleep(Duration::from_secs(PUBLISH_POLL_INTERVAL_SECS));
+    }
+}
+
+/// Guesses from cargo's output whether a failed publish is worth retrying.
+pub fn is_transient_failure(output: &str) -> bool {
+    let output = output.to_lowercase();
+    let transient = [
+        "429", "too many requests",
+        "500 internal server error", "502 bad gateway", "503 service unavailable", "504 gateway",
+        "timed out", "timeout", "failed to connect", "couldn't resolve host",
+        "connection reset", "spurious network error",
+    ];
+    let permanent = ["401", "403", "unauthorized", "forbidden", "already uploaded", "already exists"];
+
+    !permanent.iter().any(|p| output.contains(p)) && transient.iter().any(|t| output.contains(t))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_package_args() {
+        let args = package_args("/repo", &CargoOptions::default());
+        assert_eq!(vec!["package", "--manifest-path", "/repo/Cargo.toml"], args);
+    }
+
+    #[test]
+    fn feature_args_are_passed_through() {
+        let options = CargoOptions {
+            features: vec!["serde".into(), "cli".into()],
+            all_features: false,
+            no_default_features: true,
+            no_verify: false,
+        };
+
+        let args = package_args("/repo", &options);
+        assert_eq!(vec!["package", "--manifest-path", "/repo/Cargo.toml",
+                        "--features", "serde cli", "--no-default-features"], args);
+
+        let args = publish_args("/repo", "secret", Some("internal"), &options);
+        assert_eq!(vec!["publish", "--manifest-path", "/repo/Cargo.toml", "--token", "secret",
+                        "--registry", "internal", "--features", "serde cli", "--no-default-features"], args);
+    }
+
+    #[test]
+    fn all_features() {
+        let options = CargoOptions { all_features: true, ..CargoOptions::default() };
+        let args = publish_args("/repo", "secret", None, &options);
+        assert_eq!(vec!["publish", "--manifest-path", "/repo/Cargo.toml", "--token", "secret",
+                        "--all-features"], args);
+    }
+
+    #[test]
+    fn no_verify() {
+        let options = CargoOptions { no_verify: true, ..CargoOptions::default() };
+
+        let args = package_args("/repo", &options);
+        assert_eq!(vec!["package", "--manifest-path", "/repo/Cargo.toml", "--no-verify"], args);
+
+        let args = publish_args("/repo", "secret", None, &options);
+        assert_eq!(vec!["publish", "--manifest-path", "/repo/Cargo.toml", "--token", "secret", "--no-verify"], args);
+    }
+
+    #[test]
+    fn detects_transient_publish_failures() {
+        assert!(is_transient_failure("error: failed to get a 200 OK response, got 503 Service Unavailable"));
+        assert!(is_transient_failure("error: [28] Timeout was reached"));
+        assert!(!is_transient_failure("error: api errors: crate version `1.0.0` is already uploaded"));
+        assert!(!is_transient_failure("error: failed to get a 200 OK response, got 403 Forbidden"));
+    }
+
+    fn file(path: &str, size: u64) -> PackagedFile {
+        PackagedFile { path: path.into(), size: size }
+    }
+
+    #[test]
+    fn warns_about_large_and_suspicious_files() {
+        let files = vec![
+            file("Cargo.toml", 400),
+            file("src/lib.rs", 20 * 1024),
+            file("target/release/fixture", 3 * 1024 * 1024),
+            file("assets/demo.gif", 2 * 1024 * 1024),
+            file("vendor/libfoo.a", 1024),
+        ];
+
+        let warnings = package_warnings(&files, 4 * 1024 * 1024);
+        assert_eq!(vec!["The crate is 5.0 MiB large, more than the allowed 4.0 MiB",
+                        "target/release/fixture looks like a build artifact, exclude it in Cargo.toml",
+                        "assets/demo.gif is 2.0 MiB large",
+                        "vendor/libfoo.a looks like a build artifact, exclude it in Cargo.toml"], warnings);
+
+        assert!(package_warnings(&files[..2], 4 * 1024 * 1024).is_empty());
+
+        let largest = largest_files(&files, 2).iter().map(|f| &f.path[..]).collect::<Vec<_>>();
+        assert_eq!(vec!["target/release/fixture", "assets/demo.gif"], largest);
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!("512 B", format_size(512));
+        assert_eq!("1.5 KiB", format_size(1536));
+        assert_eq!("10.0 MiB", format_size(10 * 1024 * 1024));
+    }
+
+    fn package(name: &str, dependencies: &[&str]) -> WorkspacePackage {
+        WorkspacePackage {
+            name: name.into(),
+            dir: name.into(),
+            dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+            publish: true,
+        }
+    }
+
+    #[test]
+    fn publishes_dependencies_first() {
+        let packages = vec![
+            package("cli", &["core", "serde"]),
+            package("core", &["macros"]),
+            package("macros", &[]),
+            package("extra", &[]),
+        ];
+
+        let order = publish_order(&packages).unwrap().iter().map(|p| &p.name[..]).collect::<Vec<_>>();
+        assert_eq!(vec!["macros", "core", "cli", "extra"], order);
+    }
+
+    #[test]
+    fn cyclic_dependencies_are_an_error() {
+        let packages = vec![package("a", &["b"]), package("b", &["a"]), package("c", &[])];
+        assert!(publish_order(&packages).is_err());
+    }
 }
@@ -1,42 +1,840 @@
-use std::io::BufWriter;
-use clog::Clog;
-use clog::fmt::MarkdownWriter;
+use std::cmp;
+use std::io::prelude::*;
+use git2::{ObjectType, Repository};
+use regex::Regex;
+use std::fs::{self, File, OpenOptions};
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-pub fn write(repository_path: &str, old_version: &str, new_version: &str) -> Result<(), String> {
-    let mut clog = try!(Clog::with_dir(repository_path).map_err(|_| "Clog failed".to_owned()));
+use commit_analyzer::{self, AnalyzedCommit, AnalyzerOptions, CommitType};
+use git;
+use utils::{iso_date, render_template};
 
+#[derive(Debug, Clone, Default)]
+pub struct ChangelogOptions {
+    /// Path to a template file, relative to the repository.
+    /// Without one, the commits are listed in `sections`.
+    pub template: Option<String>,
+    pub tag_prefix: String,
+    pub analyzer: AnalyzerOptions,
+    pub walk: WalkOptions,
+    /// Credit the author of each commit and list all contributors at the end.
+    pub show_authors: bool,
+    /// Web URL of the repository, used to link commits and issues.
+    pub repository_url: Option<String>,
+    /// Commit types and the title of the section they are listed under, in order.
+    /// Without any, clog's sections are used: features and bug fixes.
+    pub sections: Vec<(String, String)>,
+    /// List commit types without a section under "Other" instead of hiding them.
+    pub show_other: bool,
+    /// Issue tracker URL with an `{id}` placeholder, ticket references matching `issue_pattern` link to it.
+    pub issue_url_template: Option<String>,
+    pub issue_pattern: String,
+}
+
+/// Which commits of a range are read at all, see `entries_between`.
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    /// Commits only changing files matching these patterns are skipped, see `utils::path_matches`.
+    pub exclude_paths: Vec<String>,
+    /// If not empty, only commits changing a file matching one of these patterns are read.
+    pub include_paths: Vec<String>,
+}
+
+/// A single commit as it ends up in the changelog.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: String,
+    pub author: String,
+    pub email: String,
+    pub commit: AnalyzedCommit,
+}
+
+impl Entry {
+    /// The author's GitHub handle, if they committed with a GitHub noreply address
+    /// (`handle@users.noreply.github.com` or `12345+handle@users.noreply.github.com`).
+    pub fn github_handle(&self) -> Option<&str> {
+        let suffix = "@users.noreply.github.com";
+        if !self.email.ends_with(suffix) {
+            return None;
+        }
+
+        let user = &self.email[..self.email.len() - suffix.len()];
+        let handle = match user.find('+') {
+            Some(plus) => &user[plus + 1..],
+            None => user,
+        };
+
+        if handle.is_empty() { None } else { Some(handle) }
+    }
+
+    fn credit(&self) -> String {
+        match self.github_handle() {
+            Some(handle) => format!("{} (@{})", self.author, handle),
+            None => self.author.clone(),
+        }
+    }
+}
+
+/// Writes the changelog to `changelog_path`, relative to the repository.
+/// Missing parent directories are created.
+/// `entries` are the commits of the release, as returned by `entries_since`.
+pub fn write(repository_path: &str, changelog_path: &str, new_tag: &str, entries: &[Entry], options: &ChangelogOptions) -> Result<(), String> {
     let mut clog_file = PathBuf::from(repository_path);
-    clog_file.push("Changelog.md");
+    clog_file.push(changelog_path);
+
+    if let Some(parent) = clog_file.parent() {
+        try!(fs::create_dir_all(parent)
+             .map_err(|e| format!("Failed to create {}: {}", parent.display(), e)));
+    }
+
+    let date = iso_date(SystemTime::now());
+    let changelog = try!(render_changelog(repository_path, new_tag, &date, entries, options));
+    let version = version_from_tag(new_tag, &options.tag_prefix);
+    update_file(&clog_file, &changelog, version, &date)
+        .map_err(|e| format!("Failed to write {}: {}", changelog_path, e))
+}
+
+/// Replaces the changelog at `changelog_path` with a section for each of `tags`, see `render_history`.
+/// A title or preamble above the first release is kept.
+pub fn regenerate(repository_path: &str, changelog_path: &str, tags: &[String], options: &ChangelogOptions) -> Result<(), String> {
+    let mut clog_file = PathBuf::from(repository_path);
+    clog_file.push(changelog_path);
+
+    let mut existing = String::new();
+    if clog_file.exists() {
+        try!(File::open(&clog_file)
+             .and_then(|mut file| file.read_to_string(&mut existing))
+             .map_err(|e| format!("Failed to read {}: {}", changelog_path, e)));
+    }
+
+    let history = try!(render_history(repository_path, tags, options));
+    let changelog = format!("{}\n\n{}", preamble(&existing), history);
+    if let Some(parent) = clog_file.parent() {
+        try!(fs::create_dir_all(parent)
+             .map_err(|e| format!("Failed to create {}: {}", parent.display(), e)));
+    }
+    File::create(&clog_file)
+        .and_then(|mut file| file.write_all(changelog.as_bytes()))
+        .map_err(|e| format!("Failed to write {}: {}", changelog_path, e))
+}
 
-    // TODO: Make this configurable? Rely on clog's own configuration?
-    clog.changelog(clog_file.to_str().unwrap())
-        .from(format!("v{}", old_version))
-        .version(format!("v{}", new_version));
+/// Everything above the first release section, or just a title if there is nothing.
+fn preamble(existing: &str) -> String {
+    let headers = Headers::new();
+    let preamble = existing.lines()
+        .take_while(|line| !headers.is_section_start(line))
+        .collect::<Vec<_>>()
+        .join("\n");
 
-    clog.write_changelog().map_err(|_| "Failed to write Changelog.md".to_owned())
+    if preamble.trim().is_empty() {
+        CHANGELOG_TITLE.to_owned()
+    } else {
+        preamble.trim_right().to_owned()
+    }
 }
 
-pub fn generate(repository_path: &str, old_version: &str, new_version: &str) -> Result<String, String> {
-    let mut clog = try!(Clog::with_dir(repository_path).map_err(|_| "Clog failed".to_owned()));
+/// The sections semantic-rs always had, from back when clog rendered the changelog.
+const CLOG_SECTIONS: [(&'static str, &'static str); 2] = [("feat", "Features"), ("fix", "Bug Fixes")];
+
+/// Renders a section for each of `tags`, given oldest first, with the commits since the previous tag.
+/// The newest release ends up on top. Each release is dated by its tagged commit,
+/// so rendering the same history again gives the same changelog.
+pub fn render_history(repository_path: &str, tags: &[String], options: &ChangelogOptions) -> Result<String, String> {
+    let repo = try!(Repository::open(repository_path).map_err(|e| format!("Can't open repository: {}", e)));
 
-    clog
-        .from(format!("v{}", old_version))
-        .version(format!("v{}", new_version));
+    let mut sections = vec![];
+    for (i, tag) in tags.iter().enumerate() {
+        let old_tag = if i == 0 { "" } else { &tags[i - 1][..] };
+        let entries = try!(entries_between(repository_path, old_tag, tag, &options.analyzer, &options.walk));
+
+        let commit = try!(repo.revparse_single(tag)
+            .and_then(|object| object.peel(ObjectType::Commit))
+            .and_then(|object| repo.find_commit(object.id()))
+            .map_err(|e| format!("Can't find the commit of {}: {}", tag, e)));
+        let date = iso_date(UNIX_EPOCH + Duration::from_secs(commit.time().seconds() as u64));
+
+        let section = try!(render_changelog(repository_path, tag, &date, &entries, options));
+        sections.push(section.trim_right().to_owned());
+    }
 
-    let mut out_buf = BufWriter::new(Vec::new());
+    sections.reverse();
+    let mut history = sections.join("\n\n");
+    history.push('\n');
+    Ok(history)
+}
 
-    {
-        let mut writer = MarkdownWriter::new(&mut out_buf);
-        try!(clog.write_changelog_with(&mut writer)
-             .map_err(|_| "Genearting changelog failed"))
+fn version_from_tag<'a>(tag: &'a str, tag_prefix: &str) -> &'a str {
+    if tag.starts_with(tag_prefix) {
+        &tag[tag_prefix.len()..]
+    } else {
+        tag
     }
+}
 
-    let out_buf = out_buf.into_inner().unwrap();
-    let changelog = String::from_utf8(out_buf).unwrap();
+/// The changelog for the new release, as used for the tag message and the release notes.
+/// `entries` are the commits of the release, as returned by `entries_since`.
+pub fn generate(repository_path: &str, new_tag: &str, entries: &[Entry], options: &ChangelogOptions) -> Result<String, String> {
+    let changelog = try!(render_changelog(repository_path, new_tag, &iso_date(SystemTime::now()), entries, options));
+    if options.template.is_some() {
+        return Ok(changelog);
+    }
 
+    // Drop the anchor in front of the version
     match changelog.find('\n') {
         Some(newline_offset) => Ok(changelog[newline_offset+1..].into()),
         None => Ok(changelog)
     }
 }
+
+/// Renders the new changelog section, including everything that gets added after the fact.
+fn render_changelog(repository_path: &str, new_tag: &str, date: &str, entries: &[Entry], options: &ChangelogOptions) -> Result<String, String> {
+    let mut changelog = try!(render_raw(repository_path, new_tag, date, entries, options));
+
+    if let Some(ref url) = options.repository_url {
+        changelog = link_issues(&changelog, url);
+    }
+
+    if let Some(ref template) = options.issue_url_template {
+        let pattern = try!(Regex::new(&options.issue_pattern)
+            .map_err(|e| format!("The issue pattern `{}` is invalid: {}", options.issue_pattern, e)));
+        changelog = link_tickets(&changelog, &pattern, template);
+    }
+
+    if options.show_authors {
+        changelog = with_authors(&changelog, entries);
+    }
+
+    Ok(changelog)
+}
+
+/// Renders `entries` with the template or in sections, the history isn't read again.
+fn render_raw(repository_path: &str, new_tag: &str, date: &str, entries: &[Entry], options: &ChangelogOptions) -> Result<String, String> {
+    if let Some(ref template) = options.template {
+        let mut template_file = PathBuf::from(repository_path);
+        template_file.push(template);
+
+        let mut template = String::new();
+        try!(File::open(&template_file)
+             .and_then(|mut file| file.read_to_string(&mut template))
+             .map_err(|e| format!("Can't read changelog template {}: {}", template_file.display(), e)));
+
+        let version = version_from_tag(new_tag, &options.tag_prefix);
+        let repository_url = options.repository_url.as_ref().map(|url| &url[..]);
+        return Ok(render(&template, new_tag, version, date, entries, repository_url));
+    }
+
+    let sections = if options.sections.is_empty() {
+        CLOG_SECTIONS.iter().map(|&(kind, title)| (kind.to_owned(), title.to_owned())).collect()
+    } else {
+        options.sections.clone()
+    };
+    let repository_url = options.repository_url.as_ref().map(|url| &url[..]);
+    Ok(render_sections(new_tag, date, entries, &sections, options.show_other, repository_url))
+}
+
+/// All commits after `old_tag`, newest first.
+/// If the tag is empty or doesn't exist yet, the whole history is used.
+pub fn entries_since(repository_path: &str, old_tag: &str, analyzer: &AnalyzerOptions, walk: &WalkOptions) -> Result<Vec<Entry>, String> {
+    entries_between(repository_path, old_tag, "HEAD", analyzer, walk)
+}
+
+/// All commits after `old_tag` up to and including `new_tag`, newest first.
+/// If the old tag is empty or doesn't exist, the whole history up to `new_tag` is used.
+pub fn entries_between(repository_path: &str, old_tag: &str, new_tag: &str, analyzer: &AnalyzerOptions, walk: &WalkOptions) -> Result<Vec<Entry>, String> {
+    let repo = try!(Repository::open(repository_path).map_err(|e| format!("Can't open repository: {}", e)));
+    let mut walker = try!(repo.revwalk().map_err(|e| format!("Creating a revwalk failed: {}", e)));
+
+    let range = format!("{}..{}", old_tag, new_tag);
+    if old_tag.is_empty() || walker.push_range(&range).is_err() {
+        let new_commit = try!(repo.revparse_single(new_tag)
+            .and_then(|object| object.peel(ObjectType::Commit))
+            .map_err(|e| format!("Can't find {}: {}", new_tag, e)));
+        try!(walker.push(new_commit.id()).map_err(|e| format!("Can't walk from {}: {}", new_tag, e)));
+    }
+
+    // Walking has to happen on this thread, only the analysis may run in parallel
+    let mut messages = vec![];
+    let mut authors = vec![];
+    for oid in walker {
+        let oid = try!(oid.map_err(|e| format!("Not a valid commit: {}", e)));
+        let commit = try!(repo.find_commit(oid).map_err(|e| format!("No commit found: {}", e)));
+        if !walk.exclude_paths.is_empty() || !walk.include_paths.is_empty() {
+            let paths = try!(git::changed_paths(&repo, &commit)
+                .map_err(|e| format!("Can't diff commit {}: {}", commit.id(), e)));
+            // A file moved across the boundary is listed with both paths, so it counts for both sides
+            let included = walk.include_paths.is_empty() || git::touches_any(&paths, &walk.include_paths);
+            if !included || git::only_touches(&paths, &walk.exclude_paths) {
+                continue;
+            }
+        }
+        messages.push(format!("{}\n{}", commit.id(), commit.message().unwrap_or("")));
+        authors.push((commit.author().name().unwrap_or("").to_owned(),
+                      commit.author().email().unwrap_or("").to_owned()));
+    }
+
+    let analyzed = commit_analyzer::analyze_all(&messages, analyzer);
+    let entries = messages.iter().zip(authors).zip(analyzed)
+        .map(|((message, (author, email)), commit)| Entry {
+            id: message.lines().next().unwrap_or("").to_owned(),
+            author: author,
+            email: email,
+            commit: commit,
+        })
+        .collect();
+
+    Ok(entries)
+}
+
+fn format_entry(entry: &Entry, repository_url: Option<&str>) -> String {
+    let short_id = &entry.id[..cmp::min(7, entry.id.len())];
+    let short_id = match repository_url {
+        Some(url) => format!("[{}]({}/commit/{})", short_id, url, entry.id),
+        None => short_id.to_owned(),
+    };
+    match entry.commit.scope {
+        Some(ref scope) => format!("* **{}:** {} ({})", scope, entry.commit.description, short_id),
+        None => format!("* {} ({})", entry.commit.description, short_id),
+    }
+}
+
+fn format_entries(entries: &[Entry], commit_type: CommitType, repository_url: Option<&str>) -> String {
+    entries.iter()
+        .filter(|entry| entry.commit.commit_type == commit_type)
+        .map(|entry| format_entry(entry, repository_url))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Renders a changelog template. Available placeholders:
+///
+/// * `{tag}`, `{version}` and `{date}` (`YYYY-MM-DD`)
+/// * `{breaking}`, `{features}` and `{fixes}`: the commits resulting in a major,
+///   minor or patch bump, one list item each
+///
+/// Commit ids are linked if the repository URL is known.
+pub fn render(template: &str, tag: &str, version: &str, date: &str, entries: &[Entry], repository_url: Option<&str>) -> String {
+    let breaking = format_entries(entries, CommitType::Major, repository_url);
+    let features = format_entries(entries, CommitType::Minor, repository_url);
+    let fixes = format_entries(entries, CommitType::Patch, repository_url);
+
+    render_template(template, &[
+        ("tag", tag),
+        ("version", version),
+        ("date", date),
+        ("breaking", &breaking),
+        ("features", &features),
+        ("fixes", &fixes),
+    ])
+}
+
+/// Renders the changelog in the layout clog used, with the given sections.
+/// Breaking changes are listed first, and again in the section of their type.
+/// Sections without commits are left out.
+pub fn render_sections(tag: &str, date: &str, entries: &[Entry], sections: &[(String, String)],
+                       show_other: bool, repository_url: Option<&str>) -> String {
+    let mut changelog = format!("<a name=\"{}\"></a>\n## {} ({})\n", tag, tag, date);
+
+    let breaking = entries.iter()
+        .filter(|entry| entry.commit.breaking)
+        .map(|entry| format_entry(entry, repository_url))
+        .collect::<Vec<_>>();
+    push_section(&mut changelog, "Breaking Changes", &breaking);
+
+    for &(ref kind, ref title) in sections {
+        let items = entries.iter()
+            .filter(|entry| entry.commit.kind.as_ref() == Some(kind))
+            .map(|entry| format_entry(entry, repository_url))
+            .collect::<Vec<_>>();
+        push_section(&mut changelog, title, &items);
+    }
+
+    if show_other {
+        let items = entries.iter()
+            .filter(|entry| match entry.commit.kind {
+                Some(ref kind) => !sections.iter().any(|&(ref section, _)| section == kind),
+                None => false,
+            })
+            .map(|entry| format_entry(entry, repository_url))
+            .collect::<Vec<_>>();
+        push_section(&mut changelog, "Other", &items);
+    }
+
+    changelog
+}
+
+fn push_section(changelog: &mut String, title: &str, items: &[String]) {
+    if !items.is_empty() {
+        changelog.push_str(&format!("\n### {}\n\n{}\n", title, items.join("\n")));
+    }
+}
+
+/// Turns plain `#123` references into links to the issue.
+/// References that are already linked are left alone.
+pub fn link_issues(changelog: &str, repository_url: &str) -> String {
+    let re = Regex::new(r"(?P<before>^|[^\[\w/&])#(?P<issue>\d+)").unwrap();
+    let link = format!("$before[#$issue]({}/issues/$issue)", repository_url);
+    let mut linked = changelog.lines()
+        .map(|line| re.replace_all(line, &link[..]))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if changelog.ends_with('\n') {
+        linked.push('\n');
+    }
+    linked
+}
+
+/// Turns ticket references matching `pattern`, like `PROJ-123`, into links to `url_template`,
+/// where `{id}` is replaced with the reference.
+/// References that are part of a word, a URL or already linked are left alone.
+pub fn link_tickets(changelog: &str, pattern: &Regex, url_template: &str) -> String {
+    let is_word = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+
+    let mut linked = String::new();
+    let mut last = 0;
+    for (start, end) in pattern.find_iter(changelog) {
+        let before = changelog[..start].chars().next_back();
+        let after = changelog[end..].chars().next();
+        let standalone = before.map(|c| !is_word(c) && c != '[' && c != '/').unwrap_or(true) &&
+            after.map(|c| !is_word(c) && c != ']').unwrap_or(true);
+        if start == end || !standalone {
+            continue;
+        }
+
+        let id = &changelog[start..end];
+        linked.push_str(&changelog[last..start]);
+        linked.push_str(&format!("[{}]({})", id, render_template(url_template, &[("id", id)])));
+        last = end;
+    }
+    linked.push_str(&changelog[last..]);
+    linked
+}
+
+/// Appends the author to every line mentioning a commit
+/// and adds a footer listing each contributor once.
+pub fn with_authors(changelog: &str, entries: &[Entry]) -> String {
+    let mut lines = changelog.lines().map(|line| {
+        let entry = entries.iter()
+            .find(|entry| entry.id.len() >= 7 && line.contains(&entry.id[..7]));
+        match entry {
+            Some(entry) => format!("{} by {}", line, entry.credit()),
+            None => line.to_owned(),
+        }
+    }).collect::<Vec<_>>();
+
+    let mut contributors: Vec<String> = vec![];
+    for entry in entries {
+        let credit = entry.credit();
+        if !entry.author.is_empty() && !contributors.contains(&credit) {
+            contributors.push(credit);
+        }
+
+        for co_author in entry.commit.footer_values("Co-authored-by") {
+            // `Name <email>`, the address isn't shown
+            let name = co_author.split('<').next().unwrap_or("").trim().to_owned();
+            if !name.is_empty() && !contributors.contains(&name) {
+                contributors.push(name);
+            }
+        }
+    }
+
+    if !contributors.is_empty() {
+        lines.push("".into());
+        lines.push("### Contributors".into());
+        lines.push("".into());
+        lines.extend(contributors.iter().map(|name| format!("* {}", name)));
+    }
+
+    let mut changelog = lines.join("\n");
+    changelog.push('\n');
+    changelog
+}
+
+fn update_file(path: &PathBuf, section: &str, version: &str, date: &str) -> ::std::io::Result<()> {
+    let mut existing = String::new();
+    if path.exists() {
+        let mut file = try!(File::open(path));
+        try!(file.read_to_string(&mut existing));
+    }
+
+    let headers = Headers::new();
+    let updated = match promote_unreleased_with(&existing, section, version, date, &headers) {
+        Some(updated) => updated,
+        None => prepend(&existing, section, &headers),
+    };
+
+    let mut file = try!(OpenOptions::new().write(true).create(true).truncate(true).open(path));
+    file.write_all(updated.as_bytes())
+}
+
+/// Inserts `section` above the newest release, below the title and any other preamble.
+/// An empty changelog gets a title first.
+fn prepend(existing: &str, section: &str, headers: &Headers) -> String {
+    let mut section = section.trim_right().to_owned();
+    section.push('\n');
+
+    if existing.trim().is_empty() {
+        return format!("{}\n\n{}", CHANGELOG_TITLE, section);
+    }
+
+    let offset = existing.split('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len() + 1;
+            Some((start, line))
+        })
+        .find(|&(_, line)| headers.is_section_start(line))
+        .map(|(start, _)| start);
+
+    match offset {
+        // The title and preamble stay on top, however many blank lines follow them
+        Some(offset) if existing[..offset].trim().is_empty() => format!("{}\n{}", section, &existing[offset..]),
+        Some(offset) => format!("{}\n\n{}\n{}", existing[..offset].trim_right(), section, &existing[offset..]),
+        None => {
+            let mut updated = existing.trim_right().to_owned();
+            updated.push_str("\n\n");
+            updated.push_str(&section);
+            updated
+        }
+    }
+}
+
+const CHANGELOG_TITLE: &'static str = "# Changelog";
+
+/// The headers a changelog is split into sections by, compiled once per changelog.
+struct Headers {
+    unreleased: Regex,
+    version: Regex,
+}
+
+impl Headers {
+    fn new() -> Headers {
+        Headers {
+            unreleased: Regex::new(r"(?i)^##\s*\[?unreleased\]?\s*$").unwrap(),
+            version: Regex::new(r"^#{2,3}\s+\[?[^\s\]]*?\d+\.\d+\.\d+").unwrap(),
+        }
+    }
+
+    fn is_unreleased(&self, line: &str) -> bool {
+        self.unreleased.is_match(line.trim())
+    }
+
+    /// Where a release section starts: clog's anchor, the Unreleased header or a header
+    /// naming a version, like `## v1.0.0 (2017-06-20)` or `### [1.0.1] - 2017-06-21`.
+    /// Other headers, e.g. in the preamble below the title, don't count.
+    fn is_section_start(&self, line: &str) -> bool {
+        line.starts_with("<a name=") || self.is_unreleased(line) || self.version.is_match(line)
+    }
+}
+
+/// The notes of the release section whose header names `version`, without the header.
+pub fn section_for(existing: &str, version: &str) -> Option<String> {
+    let headers = Headers::new();
+    let lines = existing.lines().collect::<Vec<_>>();
+    let names_version = |line: &str| {
+        line.starts_with('#') && headers.is_section_start(line) &&
+            line.split(|c: char| c.is_whitespace() || c == '[' || c == ']')
+                .any(|word| word == version || word.ends_with(&format!("v{}", version)))
+    };
+
+    let header = match lines.iter().position(|line| names_version(line)) {
+        Some(header) => header,
+        None => return None,
+    };
+    let end = lines[header + 1..].iter()
+        .position(|line| headers.is_section_start(line))
+        .map(|offset| header + 1 + offset)
+        .unwrap_or(lines.len());
+
+    let notes = lines[header + 1..end].join("\n");
+    if notes.trim().is_empty() { None } else { Some(notes.trim().to_owned()) }
+}
+
+/// Turns an existing `## [Unreleased]` section into the section for `version`
+/// and starts a new, empty one above it.
+/// If the Unreleased section has no notes, the generated `section` is used instead.
+///
+/// Returns `None` if there is no Unreleased section.
+pub fn promote_unreleased(existing: &str, section: &str, version: &str, date: &str) -> Option<String> {
+    promote_unreleased_with(existing, section, version, date, &Headers::new())
+}
+
+fn promote_unreleased_with(existing: &str, section: &str, version: &str, date: &str,
+                           headers: &Headers) -> Option<String> {
+    let lines = existing.lines().collect::<Vec<_>>();
+    let start = match lines.iter().position(|line| headers.is_unreleased(line)) {
+        Some(start) => start,
+        None => return None,
+    };
+    let end = lines[start + 1..].iter()
+        .position(|line| headers.is_section_start(line))
+        .map(|offset| start + 1 + offset)
+        .unwrap_or(lines.len());
+
+    let mut updated = lines[..start].iter().map(|line| format!("{}\n", line)).collect::<String>();
+    updated.push_str("## [Unreleased]\n\n");
+
+    let notes = lines[start + 1..end].join("\n");
+    if notes.trim().is_empty() {
+        updated.push_str(section.trim_right());
+    } else {
+        updated.push_str(&format!("## [{}] - {}\n\n{}", version, date, notes.trim()));
+    }
+    updated.push_str("\n\n");
+
+    for line in &lines[end..] {
+        updated.push_str(line);
+        updated.push('\n');
+    }
+
+    Some(updated)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use commit_analyzer::{AnalyzedCommit, CommitType};
+    use config::DEFAULT_ISSUE_PATTERN;
+
+    fn entry(id: &str, commit_type: CommitType, scope: Option<&str>, description: &str) -> Entry {
+        Entry {
+            id: id.into(),
+            author: "Jane Doe".into(),
+            email: "jane@example.com".into(),
+            commit: AnalyzedCommit {
+                commit_type: commit_type,
+                kind: Some(match commit_type {
+                    CommitType::Minor => "feat",
+                    CommitType::Patch => "fix",
+                    _ => "chore",
+                }.into()),
+                scope: scope.map(|s| s.to_owned()),
+                description: description.into(),
+                breaking: commit_type == CommitType::Major,
+                footers: vec![],
+            },
+        }
+    }
+
+    #[test]
+    fn custom_template_changes_output() {
+        let entries = [
+            entry("1234567890", CommitType::Minor, Some("cli"), "Add a flag"),
+            entry("abcdefabcd", CommitType::Patch, None, "Fix a crash"),
+            entry("0000000000", CommitType::Unknown, None, "Update docs"),
+        ];
+        let template = "# Release {version} ({date})\n\n## New\n{features}\n\n## Fixed\n{fixes}\n";
+
+        let rendered = render(template, "v1.2.0", "1.2.0", "2016-12-24", &entries, None);
+
+        assert_eq!("# Release 1.2.0 (2016-12-24)\n\n## New\n* **cli:** Add a flag (1234567)\n\n## Fixed\n* Fix a crash (abcdefa)\n",
+                   rendered);
+    }
+
+    #[test]
+    fn empty_sections_render_empty() {
+        let rendered = render("{tag}: {breaking}", "v1.0.0", "1.0.0", "2016-12-24", &[], None);
+        assert_eq!("v1.0.0: ", rendered);
+    }
+
+    #[test]
+    fn credits_authors_once() {
+        let mut other = entry("abcdefabcd", CommitType::Patch, None, "Fix a crash");
+        other.author = "John Roe".into();
+        other.email = "1234+jroe@users.noreply.github.com".into();
+        let entries = [
+            entry("1234567890", CommitType::Minor, None, "Add a flag"),
+            other,
+            entry("1111111111", CommitType::Patch, None, "Fix another crash"),
+        ];
+        let changelog = "## Features\n\n* Add a flag (1234567)\n\n## Fixes\n\n* Fix a crash (abcdefab)\n* Fix another crash (1111111)\n";
+
+        assert_eq!("## Features\n\n* Add a flag (1234567) by Jane Doe\n\n## Fixes\n\n* Fix a crash (abcdefab) by John Roe (@jroe)\n* Fix another crash (1111111) by Jane Doe\n\n### Contributors\n\n* Jane Doe\n* John Roe (@jroe)\n",
+                   with_authors(changelog, &entries));
+    }
+
+    #[test]
+    fn credits_co_authors() {
+        let mut paired = entry("1234567890", CommitType::Minor, None, "Add a flag");
+        paired.commit.footers = vec![("Co-authored-by".into(), "John Roe <john@example.com>".into()),
+                                     ("Refs".into(), "#12".into())];
+        let changelog = "## Features\n\n* Add a flag (1234567)\n";
+
+        assert_eq!("## Features\n\n* Add a flag (1234567) by Jane Doe\n\n### Contributors\n\n* Jane Doe\n* John Roe\n",
+                   with_authors(changelog, &[paired]));
+    }
+
+    #[test]
+    fn github_handle_from_noreply_address() {
+        let mut commit = entry("1234567890", CommitType::Patch, None, "Fix");
+        assert_eq!(None, commit.github_handle());
+
+        commit.email = "jdoe@users.noreply.github.com".into();
+        assert_eq!(Some("jdoe"), commit.github_handle());
+    }
+
+    #[test]
+    fn links_commits_in_templates() {
+        let entries = [entry("1234567890", CommitType::Patch, None, "Fix a crash")];
+        let rendered = render("{fixes}", "v1.0.1", "1.0.1", "2016-12-24", &entries, Some("https://github.com/user/repo"));
+        assert_eq!("* Fix a crash ([1234567](https://github.com/user/repo/commit/1234567890))", rendered);
+    }
+
+    #[test]
+    fn links_issue_references() {
+        let url = "https://github.com/user/repo";
+        assert_eq!("* Fix a crash, closes [#12](https://github.com/user/repo/issues/12)\n",
+                   link_issues("* Fix a crash, closes #12\n", url));
+        assert_eq!("[#3](https://github.com/user/repo/issues/3) and [#4](https://github.com/user/repo/issues/4)",
+                   link_issues("#3 and #4", url));
+        assert_eq!("already [#5](https://github.com/user/repo/issues/5), color &#35;",
+                   link_issues("already [#5](https://github.com/user/repo/issues/5), color &#35;", url));
+    }
+
+    #[test]
+    fn links_ticket_references() {
+        let pattern = Regex::new(DEFAULT_ISSUE_PATTERN).unwrap();
+        let url = "https://jira.example.com/browse/{id}";
+        assert_eq!("* Fix a crash, [PROJ-12](https://jira.example.com/browse/PROJ-12) (abc1234)\n",
+                   link_tickets("* Fix a crash, PROJ-12 (abc1234)\n", &pattern, url));
+        assert_eq!("[PROJ-3](https://jira.example.com/browse/PROJ-3) and closes #4",
+                   link_tickets("PROJ-3 and closes #4", &pattern, url));
+        assert_eq!("already [PROJ-5](https://jira.example.com/browse/PROJ-5)",
+                   link_tickets("already [PROJ-5](https://jira.example.com/browse/PROJ-5)", &pattern, url));
+        assert_eq!("XPROJ-5x stays", link_tickets("XPROJ-5x stays", &Regex::new("PROJ-[0-9]+").unwrap(), url));
+    }
+
+    #[test]
+    fn promotes_unreleased_notes() {
+        let existing = "# Changelog\n\n## [Unreleased]\n\n* Added a thing\n\n## [1.0.0] - 2016-01-01\n\n* Initial\n";
+        let updated = promote_unreleased(existing, "## v1.1.0\n\n* generated\n", "1.1.0", "2016-12-24").unwrap();
+
+        assert_eq!("# Changelog\n\n## [Unreleased]\n\n## [1.1.0] - 2016-12-24\n\n* Added a thing\n\n## [1.0.0] - 2016-01-01\n\n* Initial\n",
+                   updated);
+    }
+
+    #[test]
+    fn empty_unreleased_section_uses_generated_notes() {
+        let existing = "## Unreleased\n\n<a name=\"v1.0.0\"></a>\n## v1.0.0 (2016-01-01)\n";
+        let updated = promote_unreleased(existing, "<a name=\"v1.1.0\"></a>\n## v1.1.0 (2016-12-24)\n\n* generated\n", "1.1.0", "2016-12-24").unwrap();
+
+        assert_eq!("## [Unreleased]\n\n<a name=\"v1.1.0\"></a>\n## v1.1.0 (2016-12-24)\n\n* generated\n\n<a name=\"v1.0.0\"></a>\n## v1.0.0 (2016-01-01)\n",
+                   updated);
+    }
+
+    #[test]
+    fn without_unreleased_section_nothing_is_promoted() {
+        assert_eq!(None, promote_unreleased("## v1.0.0\n", "## v1.1.0\n", "1.1.0", "2016-12-24"));
+        assert_eq!("## v1.1.0\n\n## v1.0.0\n", prepend("## v1.0.0\n", "## v1.1.0", &Headers::new()));
+    }
+
+    #[test]
+    fn consecutive_releases_keep_all_sections() {
+        let first = prepend("", "<a name=\"v1.0.0\"></a>\n## v1.0.0\n\n* First\n", &Headers::new());
+        let second = prepend(&first, "<a name=\"v1.1.0\"></a>\n## v1.1.0\n\n* Second\n", &Headers::new());
+
+        assert_eq!("# Changelog\n\n<a name=\"v1.1.0\"></a>\n## v1.1.0\n\n* Second\n\n<a name=\"v1.0.0\"></a>\n## v1.0.0\n\n* First\n",
+                   second);
+    }
+
+    #[test]
+    fn new_sections_go_below_the_preamble() {
+        let existing = "# Changelog\n\nAll notable changes are listed here.\n\n## v1.0.0\n\n* First\n";
+        assert_eq!("# Changelog\n\nAll notable changes are listed here.\n\n## v1.1.0\n\n## v1.0.0\n\n* First\n",
+                   prepend(existing, "## v1.1.0\n", &Headers::new()));
+        assert_eq!("# Changelog\n\n## v1.0.0\n", prepend("# Changelog\n", "## v1.0.0", &Headers::new()));
+    }
+
+    #[test]
+    fn finds_the_section_of_a_version() {
+        let existing = "# Changelog\n\n<a name=\"v1.1.0\"></a>\n## v1.1.0 (2017-07-01)\n\n* Second\n\n\
+                        <a name=\"v1.0.0\"></a>\n## [1.0.0] - 2017-06-20\n\n* First\n";
+
+        assert_eq!(Some("* Second".to_owned()), section_for(existing, "1.1.0"));
+        assert_eq!(Some("* First".to_owned()), section_for(existing, "1.0.0"));
+        assert_eq!(None, section_for(existing, "1.0"));
+        assert_eq!(None, section_for(existing, "2.0.0"));
+    }
+
+    #[test]
+    fn keeps_a_keep_a_changelog_preamble() {
+        let existing = "# Changelog\n\n\
+                        All notable changes to this project will be documented in this file.\n\n\
+                        ## Format\n\n\
+                        The format is based on [Keep a Changelog](https://keepachangelog.com/en/1.0.0/),\n\
+                        and this project adheres to [Semantic Versioning](https://semver.org/spec/v2.0.0.html).\n\n\n\n\
+                        ## [1.0.0] - 2017-06-20\n\n\
+                        ### Added\n\
+                        - First\n";
+        let expected = "# Changelog\n\n\
+                        All notable changes to this project will be documented in this file.\n\n\
+                        ## Format\n\n\
+                        The format is based on [Keep a Changelog](https://keepachangelog.com/en/1.0.0/),\n\
+                        and this project adheres to [Semantic Versioning](https://semver.org/spec/v2.0.0.html).\n\n\
+                        <a name=\"v1.1.0\"></a>\n\
+                        ## v1.1.0 (2017-07-01)\n\n\
+                        * Second\n\n\
+                        ## [1.0.0] - 2017-06-20\n\n\
+                        ### Added\n\
+                        - First\n";
+
+        assert_eq!(expected, prepend(existing, "<a name=\"v1.1.0\"></a>\n## v1.1.0 (2017-07-01)\n\n* Second\n", &Headers::new()));
+    }
+
+    #[test]
+    fn preamble_without_releases_keeps_new_section_below() {
+        let existing = "# Changelog\n\n## Format\n\nOne section per release.\n\n\n";
+        assert_eq!("# Changelog\n\n## Format\n\nOne section per release.\n\n### v0.1.1\n",
+                   prepend(existing, "### v0.1.1", &Headers::new()));
+    }
+
+    #[test]
+    fn regenerating_keeps_the_preamble() {
+        let existing = "# Changelog\n\nAll notable changes.\n\n<a name=\"v1.0.0\"></a>\n## v1.0.0 (2017-06-20)\n";
+        assert_eq!("# Changelog\n\nAll notable changes.", preamble(existing));
+        assert_eq!("# Changelog", preamble(""));
+        assert_eq!("# Changelog", preamble("## v1.0.0 (2017-06-20)\n"));
+    }
+
+    #[test]
+    fn renders_configured_sections() {
+        let mut perf = entry("2222222222", CommitType::Patch, None, "Faster parsing");
+        perf.commit.kind = Some("perf".into());
+        let entries = [
+            entry("1111111111", CommitType::Patch, None, "Fix a crash"),
+            perf,
+            entry("3333333333", CommitType::Unknown, None, "Update docs"),
+        ];
+        let sections = vec![
+            ("feat".to_owned(), "Features".to_owned()),
+            ("fix".to_owned(), "Bug Fixes".to_owned()),
+        ];
+
+        assert_eq!("<a name=\"v1.0.1\"></a>\n## v1.0.1 (2016-12-24)\n\n### Bug Fixes\n\n* Fix a crash (1111111)\n",
+                   render_sections("v1.0.1", "2016-12-24", &entries, &sections, false, None));
+
+        assert_eq!("<a name=\"v1.0.1\"></a>\n## v1.0.1 (2016-12-24)\n\n### Bug Fixes\n\n* Fix a crash (1111111)\n\n### Other\n\n* Faster parsing (2222222)\n* Update docs (3333333)\n",
+                   render_sections("v1.0.1", "2016-12-24", &entries, &sections, true, None));
+    }
+
+    #[test]
+    fn default_layout_is_rendered_from_the_entries() {
+        let mut breaking = entry("fedcbafedc", CommitType::Major, None, "Drop the config file");
+        breaking.commit.kind = Some("feat".into());
+        let entries = [
+            entry("1234567890", CommitType::Minor, Some("cli"), "Add a flag"),
+            entry("abcdefabcd", CommitType::Patch, None, "Fix a crash"),
+            entry("0000000000", CommitType::None, None, "Update dependencies"),
+            breaking,
+        ];
+
+        // Nothing is read from the repository
+        let changelog = generate("/nonexistent", "v2.0.0", &entries, &ChangelogOptions::default()).unwrap();
+        assert!(changelog.starts_with("## v2.0.0 ("), "{}", changelog);
+        assert!(changelog.ends_with("\n\n### Breaking Changes\n\n* Drop the config file (fedcbaf)\n\n\
+                                     ### Features\n\n* **cli:** Add a flag (1234567)\n* Drop the config file (fedcbaf)\n\n\
+                                     ### Bug Fixes\n\n* Fix a crash (abcdefa)\n"),
+                "{}", changelog);
+    }
+}
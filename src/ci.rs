@@ -0,0 +1,171 @@
+//! What the CI service tells us about the build: the branch being built and whether it's a pull or merge request.
+
+use std::env;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Service {
+    Travis,
+    GitHubActions,
+    GitLab,
+    /// Another CI service or none at all, git is asked instead.
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Environment {
+    pub service: Service,
+    /// The branch being built, if the service says so. Checkouts are often on a detached HEAD.
+    pub branch: Option<String>,
+    /// Pull request builds never release.
+    pub pull_request: bool,
+}
+
+impl Environment {
+    /// Only Travis runs several jobs per build, where one job waits for the others before releasing.
+    /// Everywhere else the job releases right away.
+    pub fn waits_for_other_jobs(&self) -> bool {
+        self.service == Service::Travis
+    }
+}
+
+/// Reads the environment of the current process.
+pub fn environment() -> Environment {
+    from_vars(|name| env::var(name).ok())
+}
+
+/// Detects the service from the variables `var` looks up.
+pub fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> Environment {
+    if var("GITHUB_ACTIONS").map(|value| value == "true").unwrap_or(false) {
+        github_actions(&var)
+    } else if var("GITLAB_CI").is_some() {
+        gitlab(&var)
+    } else {
+        travis(&var)
+    }
+}
+
+/// Variables are read even without `TRAVIS` being set, as semantic-rs always did.
+fn travis<F: Fn(&str) -> Option<String>>(var: &F) -> Environment {
+    let service = if var("TRAVIS").is_some() || var("TRAVIS_BRANCH").is_some() {
+        Service::Travis
+    } else {
+        Service::Unknown
+    };
+
+    Environment {
+        service: service,
+        branch: var("TRAVIS_BRANCH"),
+        pull_request: var("TRAVIS_PULL_REQUEST").map(|pr| pr != "false").unwrap_or(false),
+    }
+}
+
+/// Pull requests build a merge commit, `GITHUB_HEAD_REF` names the branch it comes from.
+/// Other builds name the branch in `GITHUB_REF`, unless a tag is built.
+fn github_actions<F: Fn(&str) -> Option<String>>(var: &F) -> Environment {
+    let event = var("GITHUB_EVENT_NAME").unwrap_or(String::new());
+    let head_ref = var("GITHUB_HEAD_REF").and_then(|head| if head.is_empty() { None } else { Some(head) });
+    let branch = head_ref.or_else(|| {
+        var("GITHUB_REF").and_then(|git_ref| {
+            if git_ref.starts_with("refs/heads/") {
+                Some(git_ref["refs/heads/".len()..].to_owned())
+            } else {
+                None
+            }
+        })
+    });
+
+    Environment {
+        service: Service::GitHubActions,
+        branch: branch,
+        pull_request: event == "pull_request" || event == "pull_request_target",
+    }
+}
+
+/// Merge request pipelines build the source branch, `CI_COMMIT_REF_NAME` names it in all other pipelines.
+/// Tag pipelines have no branch.
+fn gitlab<F: Fn(&str) -> Option<String>>(var: &F) -> Environment {
+    let merge_request = var("CI_PIPELINE_SOURCE").map(|source| source == "merge_request_event").unwrap_or(false) ||
+        var("CI_MERGE_REQUEST_IID").is_some();
+    let branch = if merge_request {
+        var("CI_MERGE_REQUEST_SOURCE_BRANCH_NAME")
+    } else if var("CI_COMMIT_TAG").is_some() {
+        None
+    } else {
+        var("CI_COMMIT_REF_NAME")
+    };
+
+    Environment {
+        service: Service::GitLab,
+        branch: branch,
+        pull_request: merge_request,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Environment {
+        from_vars(|name| vars.iter().find(|&&(key, _)| key == name).map(|&(_, value)| value.to_owned()))
+    }
+
+    #[test]
+    fn travis_builds() {
+        let push = env(&[("CI", "true"), ("TRAVIS", "true"), ("TRAVIS_BRANCH", "master"), ("TRAVIS_PULL_REQUEST", "false")]);
+        assert_eq!(Service::Travis, push.service);
+        assert_eq!(Some("master".to_owned()), push.branch);
+        assert!(!push.pull_request);
+        assert!(push.waits_for_other_jobs());
+
+        let pull_request = env(&[("TRAVIS", "true"), ("TRAVIS_BRANCH", "master"), ("TRAVIS_PULL_REQUEST", "42")]);
+        assert!(pull_request.pull_request);
+    }
+
+    #[test]
+    fn github_actions_builds() {
+        let push = env(&[("CI", "true"), ("GITHUB_ACTIONS", "true"), ("GITHUB_EVENT_NAME", "push"),
+                         ("GITHUB_REF", "refs/heads/main"), ("GITHUB_HEAD_REF", "")]);
+        assert_eq!(Service::GitHubActions, push.service);
+        assert_eq!(Some("main".to_owned()), push.branch);
+        assert!(!push.pull_request);
+        assert!(!push.waits_for_other_jobs());
+
+        let pull_request = env(&[("GITHUB_ACTIONS", "true"), ("GITHUB_EVENT_NAME", "pull_request"),
+                                 ("GITHUB_REF", "refs/pull/42/merge"), ("GITHUB_HEAD_REF", "feature/quiet")]);
+        assert_eq!(Some("feature/quiet".to_owned()), pull_request.branch);
+        assert!(pull_request.pull_request);
+
+        let tag = env(&[("GITHUB_ACTIONS", "true"), ("GITHUB_EVENT_NAME", "push"), ("GITHUB_REF", "refs/tags/v1.0.0")]);
+        assert_eq!(None, tag.branch);
+    }
+
+    #[test]
+    fn gitlab_builds() {
+        let push = env(&[("CI", "true"), ("GITLAB_CI", "true"), ("CI_PIPELINE_SOURCE", "push"),
+                         ("CI_COMMIT_REF_NAME", "main"), ("CI_COMMIT_BRANCH", "main")]);
+        assert_eq!(Service::GitLab, push.service);
+        assert_eq!(Some("main".to_owned()), push.branch);
+        assert!(!push.pull_request);
+        assert!(!push.waits_for_other_jobs());
+
+        let merge_request = env(&[("GITLAB_CI", "true"), ("CI_PIPELINE_SOURCE", "merge_request_event"),
+                                  ("CI_COMMIT_REF_NAME", "feature/quiet"), ("CI_MERGE_REQUEST_IID", "42"),
+                                  ("CI_MERGE_REQUEST_SOURCE_BRANCH_NAME", "feature/quiet")]);
+        assert_eq!(Some("feature/quiet".to_owned()), merge_request.branch);
+        assert!(merge_request.pull_request);
+
+        let tag = env(&[("GITLAB_CI", "true"), ("CI_PIPELINE_SOURCE", "push"),
+                        ("CI_COMMIT_REF_NAME", "v1.0.0"), ("CI_COMMIT_TAG", "v1.0.0")]);
+        assert_eq!(None, tag.branch);
+        assert!(!tag.pull_request);
+    }
+
+    #[test]
+    fn unknown_service() {
+        let local = env(&[]);
+        assert_eq!(Service::Unknown, local.service);
+        assert_eq!(None, local.branch);
+        assert!(!local.pull_request);
+        assert!(!local.waits_for_other_jobs());
+    }
+}
@@ -1,53 +1,631 @@
-use clog::Clog;
-use clog::error::Error;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use regex::Regex;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
 
-#[derive(PartialEq,Eq,Debug,PartialOrd,Ord)]
+/// Ordered by the size of the bump, so the highest one wins.
+#[derive(PartialEq,Eq,Debug,PartialOrd,Ord,Clone,Copy)]
 pub enum CommitType {
+    /// Not a conventional commit
     Unknown,
+    /// A conventional commit that doesn't warrant a release, like `chore:` or `docs:`
+    None,
     Patch,
     Minor,
     Major,
 }
 
-use self::CommitType::*;
+// `None` stays qualified, so it doesn't shadow `Option::None`
+use self::CommitType::{Unknown, Patch, Minor, Major};
 
-pub fn analyze_single(commit: &str) -> Result<CommitType, Error> {
-    let clog = Clog::new().expect("Clog initialization failed");
-    let commit = clog.parse_raw_commit(commit);
+impl CommitType {
+    /// The bigger of both bumps.
+    pub fn max(self, other: CommitType) -> CommitType {
+        cmp::max(self, other)
+    }
+
+    /// The biggest bump among `types`, `Unknown` if there are none.
+    pub fn max_of<I: IntoIterator<Item=CommitType>>(types: I) -> CommitType {
+        types.into_iter().fold(Unknown, CommitType::max)
+    }
+}
+
+impl FromStr for CommitType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<CommitType, String> {
+        match &s.to_lowercase()[..] {
+            "major" => Ok(Major),
+            "minor" => Ok(Minor),
+            "patch" => Ok(Patch),
+            "none" => Ok(CommitType::None),
+            "unknown" => Ok(Unknown),
+            _ => Err(format!("Unknown bump level '{}', expected one of major, minor, patch or none", s)),
+        }
+    }
+}
+
+pub type BumpRules = HashMap<String, CommitType>;
+
+/// The commit types semantic-rs understands out of the box.
+pub fn default_bump_rules() -> BumpRules {
+    let mut rules = HashMap::new();
+    rules.insert("feat".into(), Minor);
+    rules.insert("ft".into(), Minor);
+    rules.insert("fix".into(), Patch);
+    rules.insert("fx".into(), Patch);
+    rules
+}
+
+#[derive(Debug,Clone)]
+pub struct AnalyzerOptions {
+    pub bump_rules: BumpRules,
+    /// Also look at conventional-commit lines in the body, as produced by squash merges.
+    pub scan_squashed_bodies: bool,
+}
+
+impl Default for AnalyzerOptions {
+    fn default() -> AnalyzerOptions {
+        AnalyzerOptions {
+            bump_rules: default_bump_rules(),
+            scan_squashed_bodies: true,
+        }
+    }
+}
+
+#[derive(PartialEq,Eq,Debug,Clone)]
+pub struct AnalyzedCommit {
+    pub commit_type: CommitType,
+    /// The conventional commit type, like `feat` or `fix`
+    pub kind: Option<String>,
+    pub scope: Option<String>,
+    pub description: String,
+    pub breaking: bool,
+    /// Trailers like `Refs: #12` or `Co-authored-by: ...`, in order of appearance.
+    pub footers: Vec<(String, String)>,
+}
+
+impl AnalyzedCommit {
+    /// The values of all footers called `key`, which is compared case-insensitively.
+    pub fn footer_values(&self, key: &str) -> Vec<&str> {
+        self.footers.iter()
+            .filter(|&&(ref name, _)| name.to_lowercase() == key.to_lowercase())
+            .map(|&(_, ref value)| &value[..])
+            .collect()
+    }
+}
+
+struct Subject {
+    kind: String,
+    scope: Option<String>,
+    description: String,
+    breaking: bool,
+}
+
+/// The regexes of the analysis, compiled once for a whole range instead of for every line.
+struct Patterns {
+    /// Spaces around the colon are tolerated, as in `fix:typo` or `fix : typo`.
+    subject: Regex,
+    /// The bullet in front of commits listed in a squash merge's body.
+    bullet: Regex,
+    /// A footer line like `Reviewed-by: Jane` or `Refs #12`.
+    footer: Regex,
+}
+
+impl Patterns {
+    fn new() -> Patterns {
+        Patterns {
+            subject: Regex::new(r"^(?P<kind>\w+)(?:\((?P<scope>[^\)]*)\))?(?P<bang>!)?[ \t]*:(?P<description>.*)$").unwrap(),
+            bullet: Regex::new(r"^\s*[-*]?\s*").unwrap(),
+            footer: Regex::new(r"^(?P<key>[A-Za-z0-9-]+|BREAKING CHANGE)(?::[ \t]*(?P<value>.*)| (?P<issue>#.*))$").unwrap(),
+        }
+    }
+}
+
+fn parse_subject(subject: &str, patterns: &Patterns) -> Option<Subject> {
+    let captures = match patterns.subject.captures(subject.trim()) {
+        Some(captures) => captures,
+        None => return None,
+    };
+
+    let scope = captures.name("scope")
+        .map(|scope| scope.trim())
+        .and_then(|scope| if scope.is_empty() { None } else { Some(scope.to_owned()) });
+
+    Some(Subject {
+        kind: captures.name("kind").unwrap_or("").to_owned(),
+        scope: scope,
+        description: captures.name("description").unwrap_or("").trim().to_owned(),
+        breaking: captures.name("bang").is_some(),
+    })
+}
+
+fn type_to_bump(kind: &str, rules: &BumpRules) -> CommitType {
+    if kind == "revert" {
+        return CommitType::None;
+    }
+    rules.get(kind).cloned().unwrap_or(CommitType::None)
+}
+
+/// A `BREAKING CHANGE:` token anywhere in the body or footer marks a breaking change.
+/// The token is case-sensitive, `BREAKING-CHANGE:` is accepted as an alias.
+fn has_breaking_footer<'a, I: Iterator<Item=&'a str>>(lines: I) -> bool {
+    lines.map(|line| line.trim_left())
+        .any(|line| line.starts_with("BREAKING CHANGE:") || line.starts_with("BREAKING-CHANGE:"))
+}
+
+/// The key and value of a footer line, the `#` of `Refs #12` is kept in the value.
+fn parse_footer(line: &str, patterns: &Patterns) -> Option<(String, String)> {
+    patterns.footer.captures(line.trim_right()).map(|caps| {
+        let value = caps.name("value").or(caps.name("issue")).unwrap_or("");
+        (caps.name("key").unwrap_or("").to_owned(), value.trim().to_owned())
+    })
+}
+
+/// Reads the last paragraph of the body as footers, following the git trailer format.
+/// Indented lines continue the previous value. If any line of the paragraph
+/// is no footer, it's regular text and there are no footers.
+pub fn parse_footers(body: &[&str]) -> Vec<(String, String)> {
+    read_footers(body, &Patterns::new())
+}
+
+fn read_footers(body: &[&str], patterns: &Patterns) -> Vec<(String, String)> {
+    let mut paragraph = body.iter()
+        .rev()
+        .skip_while(|line| line.trim().is_empty())
+        .take_while(|line| !line.trim().is_empty())
+        .cloned()
+        .collect::<Vec<_>>();
+    paragraph.reverse();
+
+    let mut footers: Vec<(String, String)> = vec![];
+    for line in paragraph {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            match footers.last_mut() {
+                Some(footer) => {
+                    footer.1.push(' ');
+                    footer.1.push_str(line.trim());
+                },
+                None => return vec![],
+            }
+        } else {
+            match parse_footer(line, patterns) {
+                Some(footer) => footers.push(footer),
+                None => return vec![],
+            }
+        }
+    }
+    footers
+}
+
+/// Squash merges list the original commits as (possibly bulleted) body lines,
+/// e.g. `* feat: add thing`. Returns the highest bump found among them.
+fn squashed_body_bump<'a, I: Iterator<Item=&'a str>>(lines: I, rules: &BumpRules, patterns: &Patterns) -> CommitType {
+    let bumps = lines.filter_map(|line| parse_subject(&patterns.bullet.replace(line, ""), patterns))
+        .map(|subject| if subject.breaking { Major } else { type_to_bump(&subject.kind, rules) });
+    CommitType::max_of(bumps)
+}
+
+/// Merges and reverts only shuffle around changes that were already analyzed,
+/// so they never contribute to a bump on their own.
+fn is_merge_or_revert(subject: &str) -> bool {
+    subject.starts_with("Merge ") || subject.starts_with("Revert ")
+}
+
+/// Analyzes a single commit, formatted as its id followed by the full message
+/// on the next line.
+pub fn analyze_single(commit: &str, options: &AnalyzerOptions) -> AnalyzedCommit {
+    analyze_with(commit, options, &Patterns::new())
+}
+
+fn analyze_with(commit: &str, options: &AnalyzerOptions, patterns: &Patterns) -> AnalyzedCommit {
+    let rules = &options.bump_rules;
+    let mut lines = commit.lines().skip(1);
 
-    if !commit.breaks.is_empty() {
-        return Ok(Major);
+    let subject_line = lines.next().unwrap_or("");
+    if is_merge_or_revert(subject_line) {
+        return AnalyzedCommit {
+            commit_type: Unknown,
+            kind: None,
+            scope: None,
+            description: subject_line.trim().to_owned(),
+            breaking: false,
+            footers: vec![],
+        };
     }
 
-    let commit_type = match &commit.commit_type[..] {
-        "Features" => Minor,
-        "Bug Fixes" => Patch,
-        _ => Unknown,
+    let (commit_type, kind, scope, description, breaking_subject) = match parse_subject(subject_line, patterns) {
+        Some(subject) => (type_to_bump(&subject.kind, rules), Some(subject.kind), subject.scope, subject.description, subject.breaking),
+        None => (Unknown, None, None, subject_line.trim().to_owned(), false),
+    };
+
+    let body = lines.collect::<Vec<_>>();
+    let breaking = breaking_subject || has_breaking_footer(body.iter().cloned());
+    let commit_type = if breaking { Major } else { commit_type };
+
+    let commit_type = if options.scan_squashed_bodies {
+        commit_type.max(squashed_body_bump(body.iter().cloned(), rules, patterns))
+    } else {
+        commit_type
     };
 
-    Ok(commit_type)
+    AnalyzedCommit {
+        commit_type: commit_type,
+        kind: kind,
+        scope: scope,
+        description: description,
+        breaking: breaking,
+        footers: read_footers(&body, patterns),
+    }
+}
+
+/// Below this many commits, spreading the analysis over threads costs more than it saves.
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 1000;
+
+/// Analyzes each commit, in order, on the current thread.
+pub fn analyze_each<S: AsRef<str>>(commits: &[S], options: &AnalyzerOptions) -> Vec<AnalyzedCommit> {
+    let patterns = Patterns::new();
+    commits.iter().map(|commit| analyze_with(commit.as_ref(), options, &patterns)).collect()
+}
+
+/// Like `analyze_each`, but spread over rayon's thread pool. The order is kept.
+#[cfg(feature = "parallel")]
+pub fn analyze_each_parallel<S: AsRef<str> + Sync>(commits: &[S], options: &AnalyzerOptions) -> Vec<AnalyzedCommit> {
+    let patterns = Patterns::new();
+    commits.par_iter().map(|commit| analyze_with(commit.as_ref(), options, &patterns)).collect()
+}
+
+/// Analyzes each commit, in parallel for large ranges if built with the `parallel` feature.
+#[cfg(feature = "parallel")]
+pub fn analyze_all<S: AsRef<str> + Sync>(commits: &[S], options: &AnalyzerOptions) -> Vec<AnalyzedCommit> {
+    if commits.len() < PARALLEL_THRESHOLD {
+        analyze_each(commits, options)
+    } else {
+        analyze_each_parallel(commits, options)
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn analyze_all<S: AsRef<str> + Sync>(commits: &[S], options: &AnalyzerOptions) -> Vec<AnalyzedCommit> {
+    analyze_each(commits, options)
+}
+
+/// How many commits fall into each `CommitType`.
+pub fn count_types<I>(types: I) -> BTreeMap<CommitType, usize>
+    where I: IntoIterator<Item=CommitType>
+{
+    let mut counts = BTreeMap::new();
+    for commit_type in types {
+        *counts.entry(commit_type).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Whether a commit message asks not to release, e.g. with `[skip release]`.
+/// An empty token never matches.
+pub fn has_skip_marker(message: &str, token: &str) -> bool {
+    !token.is_empty() && message.to_lowercase().contains(&token.to_lowercase())
+}
+
+/// Describes counts from `count_types`,
+/// e.g. "1 breaking change, 3 features, 12 fixes, 8 ignored".
+pub fn describe_counts(counts: &BTreeMap<CommitType, usize>) -> String {
+    let count = |commit_type| counts.get(&commit_type).cloned().unwrap_or(0);
+    let plural = |n: usize, one: &str, many: &str| {
+        format!("{} {}", n, if n == 1 { one } else { many })
+    };
+
+    let mut parts = vec![];
+    if count(Major) > 0 {
+        parts.push(plural(count(Major), "breaking change", "breaking changes"));
+    }
+    if count(Minor) > 0 {
+        parts.push(plural(count(Minor), "feature", "features"));
+    }
+    if count(Patch) > 0 {
+        parts.push(plural(count(Patch), "fix", "fixes"));
+    }
+    if count(CommitType::None) > 0 {
+        parts.push(format!("{} ignored", count(CommitType::None)));
+    }
+    if count(Unknown) > 0 {
+        parts.push(format!("{} not conventional", count(Unknown)));
+    }
+
+    if parts.is_empty() {
+        "no commits".into()
+    } else {
+        parts.join(", ")
+    }
+}
+
+#[test]
+fn commit_types_are_ordered_by_bump() {
+    assert!(Major > Minor);
+    assert!(Minor > Patch);
+    assert!(Patch > CommitType::None);
+    assert!(CommitType::None > Unknown);
+
+    assert_eq!(Minor, Patch.max(Minor));
+    assert_eq!(Minor, Minor.max(CommitType::None));
+}
+
+#[test]
+fn aggregates_to_the_biggest_bump() {
+    assert_eq!(Minor, CommitType::max_of(vec![Patch, CommitType::None, Minor, Unknown, Patch]));
+    assert_eq!(Major, CommitType::max_of(vec![Major, Minor]));
+    assert_eq!(CommitType::None, CommitType::max_of(vec![Unknown, CommitType::None]));
+    assert_eq!(Unknown, CommitType::max_of(vec![]));
 }
 
 #[test]
 fn unknown_type() {
     let commit = "0\nThis commit message has no type";
-    assert_eq!(Unknown, analyze_single(commit).unwrap());
+    assert_eq!(Unknown, analyze_single(commit, &AnalyzerOptions::default()).commit_type);
 }
 
 #[test]
 fn patch_commit() {
     let commit = "0\nfix: This commit fixes a bug";
-    assert_eq!(Patch, analyze_single(commit).unwrap());
+    assert_eq!(Patch, analyze_single(commit, &AnalyzerOptions::default()).commit_type);
+}
+
+#[test]
+fn patch_commit_without_space_after_colon() {
+    let commit = analyze_single("0\nfix:handle empty input", &AnalyzerOptions::default());
+    assert_eq!(Patch, commit.commit_type);
+    assert_eq!("handle empty input", commit.description);
+}
+
+#[test]
+fn patch_commit_with_space_before_colon() {
+    let commit = analyze_single("0\nfix : handle empty input", &AnalyzerOptions::default());
+    assert_eq!(Patch, commit.commit_type);
+    assert_eq!(Some("fix".to_owned()), commit.kind);
+    assert_eq!("handle empty input", commit.description);
+
+    let scoped = analyze_single("0\nfix(parser) :handle empty input", &AnalyzerOptions::default());
+    assert_eq!(Patch, scoped.commit_type);
+    assert_eq!(Some("parser".to_owned()), scoped.scope);
+}
+
+#[test]
+fn prose_is_not_a_type() {
+    let commit = "0\nfixing the thing";
+    assert_eq!(Unknown, analyze_single(commit, &AnalyzerOptions::default()).commit_type);
+    let commit = "0\nfix the thing: it was broken";
+    assert_eq!(Unknown, analyze_single(commit, &AnalyzerOptions::default()).commit_type);
 }
 
 #[test]
 fn minor_commit() {
     let commit = "0\nfeat: This commit introduces a new feature";
-    assert_eq!(Minor, analyze_single(commit).unwrap());
+    assert_eq!(Minor, analyze_single(commit, &AnalyzerOptions::default()).commit_type);
 }
 
 #[test]
 fn major_commit() {
     let commit = "0\nfeat: This commits breaks something\nBREAKING CHANGE: breaks things";
-    assert_eq!(Major, analyze_single(commit).unwrap());
+    assert_eq!(Major, analyze_single(commit, &AnalyzerOptions::default()).commit_type);
+}
+
+#[test]
+fn single_footer() {
+    let commit = analyze_single("0\nfix: Handle empty input\n\nRefs: #12", &AnalyzerOptions::default());
+    assert_eq!(Patch, commit.commit_type);
+    assert_eq!(vec![("Refs".to_owned(), "#12".to_owned())], commit.footers);
+}
+
+#[test]
+fn multiple_footers() {
+    let commit = analyze_single("0\ndocs: Explain footers\n\nSome more context.\n\n\
+                                 Reviewed-by: Jane Doe\nRefs #12\nCo-authored-by: John Roe <john@example.com>\n\
+                                 Co-authored-by: Max Mustermann <max@example.com>\n",
+                                &AnalyzerOptions::default());
+
+    assert_eq!(CommitType::None, commit.commit_type);
+    assert_eq!(4, commit.footers.len());
+    assert_eq!(vec!["#12"], commit.footer_values("refs"));
+    assert_eq!(vec!["John Roe <john@example.com>", "Max Mustermann <max@example.com>"],
+               commit.footer_values("Co-authored-by"));
+}
+
+#[test]
+fn multi_line_footer_values() {
+    let commit = analyze_single("0\nfeat: Require a subcommand\n\n\
+                                 BREAKING CHANGE: running without a subcommand\n  is an error now\nRefs: #12",
+                                &AnalyzerOptions::default());
+
+    assert_eq!(Major, commit.commit_type);
+    assert_eq!(vec!["running without a subcommand is an error now"], commit.footer_values("BREAKING CHANGE"));
+    assert_eq!(vec!["#12"], commit.footer_values("Refs"));
+}
+
+#[test]
+fn body_text_is_no_footer() {
+    let commit = analyze_single("0\nfix: Handle empty input\n\nThis was reported in #12: it crashed.\nNote: it's fixed now",
+                                &AnalyzerOptions::default());
+    assert!(commit.footers.is_empty());
+}
+
+#[test]
+fn scoped_commit() {
+    let commit = analyze_single("0\nfeat(parser): Parse scopes", &AnalyzerOptions::default());
+    assert_eq!(Minor, commit.commit_type);
+    assert_eq!(Some("feat".to_owned()), commit.kind);
+    assert_eq!(Some("parser".to_owned()), commit.scope);
+    assert_eq!("Parse scopes", commit.description);
+}
+
+#[test]
+fn empty_scope_is_none() {
+    let commit = analyze_single("0\nfix(): Nothing in parens", &AnalyzerOptions::default());
+    assert_eq!(Patch, commit.commit_type);
+    assert_eq!(None, commit.scope);
+}
+
+#[test]
+fn scope_is_trimmed() {
+    let commit = analyze_single("0\nfix( cli ): Trim the scope", &AnalyzerOptions::default());
+    assert_eq!(Some("cli".to_owned()), commit.scope);
+}
+
+#[test]
+fn breaking_footer_in_body() {
+    let commit = "0\nfeat: add thing\n\nSome more details.\n\nBREAKING CHANGE: the old thing is gone";
+    let commit = analyze_single(commit, &AnalyzerOptions::default());
+    assert_eq!(Major, commit.commit_type);
+    assert!(commit.breaking);
+}
+
+#[test]
+fn breaking_footer_alias() {
+    let commit = "0\nfix: small thing\n\nBREAKING-CHANGE: not so small";
+    assert_eq!(Major, analyze_single(commit, &AnalyzerOptions::default()).commit_type);
+}
+
+#[test]
+fn breaking_footer_is_case_sensitive() {
+    let commit = "0\nfix: small thing\n\nbreaking change: just a remark";
+    assert_eq!(Patch, analyze_single(commit, &AnalyzerOptions::default()).commit_type);
+}
+
+#[test]
+fn bang_marks_breaking_fix() {
+    let commit = analyze_single("0\nfix!: drop the old behaviour", &AnalyzerOptions::default());
+    assert_eq!(Major, commit.commit_type);
+    assert!(commit.breaking);
+}
+
+#[test]
+fn bang_marks_breaking_scoped_feature() {
+    let commit = analyze_single("0\nfeat(x)!: drop old API", &AnalyzerOptions::default());
+    assert_eq!(Major, commit.commit_type);
+    assert_eq!(Some("x".to_owned()), commit.scope);
+    assert_eq!("drop old API", commit.description);
+}
+
+#[test]
+fn no_bang_is_not_breaking() {
+    let commit = analyze_single("0\nfeat: add new API", &AnalyzerOptions::default());
+    assert_eq!(Minor, commit.commit_type);
+    assert!(!commit.breaking);
+}
+
+#[test]
+fn custom_bump_rules() {
+    let mut options = AnalyzerOptions::default();
+    options.bump_rules.insert("perf".into(), Patch);
+
+    assert_eq!(Patch, analyze_single("0\nperf: Faster parsing", &options).commit_type);
+    assert_eq!(CommitType::None, analyze_single("0\nrefactor: Move things", &options).commit_type);
+    assert_eq!(Minor, analyze_single("0\nfeat: Still a feature", &options).commit_type);
+}
+
+#[test]
+fn parse_bump_level() {
+    assert_eq!(Ok(Major), "major".parse::<CommitType>());
+    assert_eq!(Ok(Patch), "Patch".parse::<CommitType>());
+    assert_eq!(Ok(CommitType::None), "none".parse::<CommitType>());
+    assert!("huge".parse::<CommitType>().is_err());
+}
+
+#[test]
+fn no_release_types_differ_from_unknown() {
+    let options = AnalyzerOptions::default();
+    assert_eq!(CommitType::None, analyze_single("0\nchore: Update dependencies", &options).commit_type);
+    assert_eq!(CommitType::None, analyze_single("0\ndocs(readme): Fix typo", &options).commit_type);
+    assert_eq!(Unknown, analyze_single("0\nupdate stuff", &options).commit_type);
+    assert!(Unknown < CommitType::None && CommitType::None < Patch);
+}
+
+#[test]
+fn merge_and_revert_commits_are_ignored() {
+    let options = AnalyzerOptions::default();
+    assert_eq!(Unknown, analyze_single("0\nMerge pull request #42 from user/feat-branch", &options).commit_type);
+    assert_eq!(Unknown, analyze_single("0\nRevert \"feat: add thing\"", &options).commit_type);
+    assert_eq!(CommitType::None, analyze_single("0\nrevert: feat: add thing", &options).commit_type);
+}
+
+#[test]
+fn merge_and_revert_do_not_inflate_range() {
+    let commits = [
+        "0\nfix: a small fix",
+        "1\nMerge branch 'feature' into master\n\nBREAKING CHANGE: from the merged branch",
+        "2\nRevert \"feat: new feature\"",
+        "3\nrevert: feat!: drop old API",
+    ];
+    let analyzed = analyze_each(&commits, &AnalyzerOptions::default());
+    assert_eq!(Patch, CommitType::max_of(analyzed.iter().map(|commit| commit.commit_type)));
+}
+
+#[test]
+fn squashed_commit_uses_highest_body_bump() {
+    let commit = "0\nfix: Squashed pull request (#12)\n\n* fix: first fix\n* feat(api): new endpoint\n- feat!: removed old endpoint\n  fix: indented fix";
+    assert_eq!(Major, analyze_single(commit, &AnalyzerOptions::default()).commit_type);
+}
+
+#[test]
+fn squashed_bodies_can_be_ignored() {
+    let mut options = AnalyzerOptions::default();
+    options.scan_squashed_bodies = false;
+
+    let commit = "0\nfix: Squashed pull request (#12)\n\n* feat: new endpoint";
+    assert_eq!(Patch, analyze_single(commit, &options).commit_type);
+}
+
+#[test]
+fn counts_commits_by_type() {
+    let commits = [
+        "0\nfix: one",
+        "1\nfix: two",
+        "2\nfeat!: breaking",
+        "3\nchore: nothing",
+        "4\nwhatever",
+    ];
+    let analyzed = analyze_each(&commits, &AnalyzerOptions::default());
+    let counts = count_types(analyzed.iter().map(|commit| commit.commit_type));
+
+    assert_eq!(Some(&2), counts.get(&Patch));
+    assert_eq!(None, counts.get(&Minor));
+    assert_eq!("1 breaking change, 2 fixes, 1 ignored, 1 not conventional", describe_counts(&counts));
+}
+
+#[test]
+fn describes_empty_range() {
+    assert_eq!("no commits", describe_counts(&BTreeMap::new()));
+}
+
+#[test]
+fn skip_marker() {
+    assert!(has_skip_marker("docs: Fix typo [skip release]", "[skip release]"));
+    assert!(has_skip_marker("chore(release): Skip\n\nNot yet", "chore(release): skip"));
+    assert!(!has_skip_marker("fix: Crash on start", "[skip release]"));
+    assert!(!has_skip_marker("fix: Crash on start", ""));
+}
+
+#[test]
+#[cfg(feature = "parallel")]
+fn parallel_analysis_keeps_the_order() {
+    let commits = (0..2500)
+        .map(|i| match i % 3 {
+            0 => format!("{}\nfix: Crash number {}", i, i),
+            1 => format!("{}\nfeat: Feature number {}", i, i),
+            _ => format!("{}\nUpdate readme", i),
+        })
+        .collect::<Vec<_>>();
+    let options = AnalyzerOptions::default();
+
+    let parallel = analyze_all(&commits, &options);
+    let sequential = analyze_each(&commits, &options);
+
+    assert_eq!(sequential.len(), parallel.len());
+    for (a, b) in sequential.iter().zip(&parallel) {
+        assert_eq!(a.commit_type, b.commit_type);
+        assert_eq!(a.description, b.description);
+    }
+    assert_eq!(Minor, parallel[1].commit_type);
 }
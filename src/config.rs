@@ -1,10 +1,73 @@
 use git2::{Repository, Signature};
+use regex::Regex;
+use semver::Version;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml::{Parser, Table, Value};
+use cargo::CargoOptions;
+use changelog::{ChangelogOptions, WalkOptions};
+use commit_analyzer::{self, AnalyzerOptions, BumpRules, CommitType};
+use logger::REDACTED;
+use manifest::VersionSource;
+use report::bump_name;
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+use utils::render_template;
+
+pub const DEFAULT_COMMIT_MESSAGE: &'static str = "Bump version to {version}";
+pub const DEFAULT_TAG_MESSAGE: &'static str = "{changelog}";
+/// Jira style ticket references, like `PROJ-123`.
+pub const DEFAULT_ISSUE_PATTERN: &'static str = r"[A-Z][A-Z0-9]+-[0-9]+";
+pub const DEFAULT_CHANGELOG_PATH: &'static str = "Changelog.md";
+pub const DEFAULT_SKIP_RELEASE_TOKEN: &'static str = "[skip release]";
+/// crates.io rejects crates bigger than 10 MiB.
+pub const DEFAULT_MAX_PACKAGE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Where release objects are created after the tag was pushed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    GitHub,
+    GitLab,
+    Bitbucket,
+    /// Any other host, e.g. Azure DevOps: release commit and tag are only pushed.
+    None,
+}
+
+impl Provider {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Provider::GitHub => "github",
+            Provider::GitLab => "gitlab",
+            Provider::Bitbucket => "bitbucket",
+            Provider::None => "none",
+        }
+    }
+}
+
+impl FromStr for Provider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Provider, String> {
+        match &s.to_lowercase()[..] {
+            "github" => Ok(Provider::GitHub),
+            "gitlab" => Ok(Provider::GitLab),
+            "bitbucket" => Ok(Provider::Bitbucket),
+            "none" => Ok(Provider::None),
+            _ => Err(format!("Unknown provider '{}', expected github, gitlab, bitbucket or none", s)),
+        }
+    }
+}
 
 pub struct Config {
     pub user: Option<String>,
     pub repository_name: Option<String>,
 
+    /// The branch currently checked out, which is the one being released.
     pub branch: String,
+    /// Names or glob patterns (`release/*`) of branches releases are done from.
+    pub release_branches: Vec<String>,
 
     pub remote: Result<String, String>,
 
@@ -17,20 +80,775 @@ pub struct Config {
     pub signature: Signature<'static>,
 
     pub gh_token: Option<String>,
+    pub gitlab_token: Option<String>,
+    pub bitbucket_token: Option<String>,
     pub cargo_token: Option<String>,
+
+    pub bump_rules: BumpRules,
+    pub scan_squashed_bodies: bool,
+
+    pub tag_prefix: String,
+
+    pub sign_tags: bool,
+    pub sign_commits: bool,
+
+    pub commit_message_template: String,
+
+    pub assets: Vec<String>,
+
+    pub github_draft: bool,
+    pub github_prerelease: Option<bool>,
+
+    pub provider: Provider,
+
+    pub registry: Option<String>,
+
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+    pub no_verify: bool,
+
+    pub pre_release_hooks: Vec<String>,
+    pub post_release_hooks: Vec<String>,
+
+    /// Release exactly this version instead of deriving it from the commits.
+    pub version_override: Option<Version>,
+
+    /// Bump at least this much, regardless of what the commits indicate.
+    pub forced_bump: Option<CommitType>,
+
+    /// Where the changelog is written to, relative to the repository.
+    pub changelog_path: String,
+
+    /// Whether the changelog file is written and used as the tag message.
+    pub generate_changelog: bool,
+
+    /// Template file the changelog is rendered with, relative to the repository.
+    pub changelog_template: Option<String>,
+
+    /// Credit commit authors in the changelog.
+    pub changelog_show_authors: bool,
+
+    /// Also consider the latest release tag when determining the current version.
+    pub version_from_tags: bool,
+
+    /// How often creating the GitHub release and publishing the crate is tried.
+    pub max_attempts: u32,
+
+    /// Changelog sections as pairs of commit type and title, in the order they are listed.
+    pub changelog_sections: Vec<(String, String)>,
+
+    /// List commit types without a section under "Other" instead of hiding them.
+    pub changelog_show_other: bool,
+
+    /// Release even though the working tree has uncommitted changes.
+    pub allow_dirty: bool,
+
+    /// Name of the crate being released, if it has one.
+    pub package_name: Option<String>,
+    /// Directory of the workspace member selected with `--package`, relative to the repository.
+    pub package_dir: Option<PathBuf>,
+
+    /// Format of release tags, see `tag_prefix_from_format`.
+    pub tag_format: Option<String>,
+
+    /// Seconds to wait for a published crate to show up on crates.io before publishing its dependents.
+    pub publish_timeout: u64,
+
+    /// Package the crate and publish it to the registry. Off for crates that are only released on GitHub.
+    pub publish: bool,
+
+    /// Create a GitHub release for the new tag.
+    pub github_release: bool,
+
+    /// The crate only has binary targets, so it isn't published unless forced.
+    pub binary_only: bool,
+    /// Publish binary-only crates, too.
+    pub force_publish: bool,
+
+    /// Marker in the message of the latest commit that prevents a release. Empty to disable.
+    pub skip_release_token: String,
+
+    /// URL that is sent a JSON payload after a successful release, see `notify::webhook_payload`.
+    pub notify_webhook: Option<String>,
+    /// Slack incoming webhook that announces the release.
+    pub slack_webhook: Option<String>,
+
+    /// Label of the pre-release channel, e.g. `rc` releases `1.2.0-rc.1`, then `1.2.0-rc.2`.
+    pub prerelease: Option<String>,
+    /// Promote a pre-release version to the final release, e.g. `1.2.0-rc.2` to `1.2.0`.
+    pub release_final: bool,
+
+    /// Build metadata appended to the new version, `{sha}` is the short id of the released commit.
+    pub build_metadata: Option<String>,
+
+    /// Release the version in Cargo.toml as is, with a changelog of the whole history.
+    pub first_release: bool,
+
+    /// Abort instead of warning when HEAD doesn't descend from the latest release tag.
+    pub strict_history: bool,
+
+    /// Only create the GitHub release for this existing tag, e.g. when another step pushed it.
+    pub release_only: Option<String>,
+
+    /// Packaged crates bigger than this many bytes (uncompressed) are warned about.
+    pub max_package_size: u64,
+
+    /// Abort when the packaged crate looks suspicious, instead of only warning.
+    pub strict: bool,
+
+    /// Seconds to wait for a pushed tag to show up on GitHub before creating the release.
+    pub tag_wait_timeout: u64,
+
+    /// File holding the canonical version, relative to the repository.
+    /// `Cargo.toml` of the released crate if unset, see `manifest::VersionSource`.
+    pub version_file: Option<String>,
+
+    /// Further files containing the version, as pairs of file and a regex capturing the version.
+    pub version_replacements: Vec<(String, String)>,
+
+    /// Let GitHub append its notes generated from merged pull requests to the release body.
+    pub github_generate_notes: bool,
+
+    /// Only build the `.crate` of the current state, without bumping, committing or publishing.
+    pub package_only: bool,
+
+    /// Path patterns, commits only touching matching files are left out. Empty keeps all commits.
+    pub exclude_paths: Vec<String>,
+
+    /// Path patterns, if set only commits touching a matching file are analyzed.
+    pub include_paths: Vec<String>,
+
+    /// Below 1.0.0, breaking changes bump the minor and features the patch version, as cargo treats 0.x.
+    pub zero_ver_semantics: bool,
+
+    /// A tag or commit to start the bump and changelog from, instead of the latest release tag.
+    pub since: Option<String>,
+
+    /// Only rebuild the whole changelog from the release tags, without bumping or committing.
+    pub regenerate_changelog: bool,
+
+    /// On Travis, wait for the other jobs of the build and only release from the leader.
+    pub ci_wait: bool,
+
+    /// The CI service builds a pull request, which never releases.
+    pub pull_request: bool,
+
+    /// Create annotated release tags carrying the changelog. Lightweight tags only point at the commit.
+    pub tag_annotated: bool,
+
+    /// Message of annotated release tags, see `git::generate_tag_message`.
+    pub tag_message_template: String,
+
+    /// Push the release and publish it. Without, the release commit and tag are only created locally.
+    pub push: bool,
+
+    /// Link to tickets of an issue tracker like Jira, e.g. `https://jira.example.com/browse/{id}`.
+    pub issue_url_template: Option<String>,
+    /// Regex matching the ticket references that are linked with `issue_url_template`.
+    pub issue_pattern: String,
+
+    /// File the notes of the new release alone are written to, even in dry-run mode.
+    pub changelog_output: Option<String>,
 }
 
 impl Config {
+    /// Without a provider the remote URL doesn't need to name a user and repository.
     pub fn can_push(&self) -> bool {
-        self.user.is_some() && self.repository_name.is_some()
+        match self.provider {
+            Provider::None => self.remote.is_ok(),
+            _ => self.user.is_some() && self.repository_name.is_some(),
+        }
+    }
+
+    /// Whether the release leaves this machine: pushing, creating releases and publishing.
+    pub fn releases_remotely(&self) -> bool {
+        self.release_mode && self.push
     }
 
     pub fn can_release_to_github(&self) -> bool {
-        self.can_push() && self.gh_token.is_some()
+        self.github_release && self.provider == Provider::GitHub && self.can_push() && self.gh_token.is_some()
+    }
+
+    pub fn can_release_to_gitlab(&self) -> bool {
+        self.provider == Provider::GitLab && self.can_push() && self.gitlab_token.is_some()
+    }
+
+    pub fn can_release_to_bitbucket(&self) -> bool {
+        self.provider == Provider::Bitbucket && self.can_push() && self.bitbucket_token.is_some()
+    }
+
+    pub fn registry_name(&self) -> &str {
+        self.registry.as_ref().map(|r| &r[..]).unwrap_or("crates.io")
+    }
+
+    /// Binary-only crates are rarely meant to be used as a dependency,
+    /// so they are only published when explicitly asked to.
+    pub fn should_publish(&self) -> bool {
+        self.publish && (self.force_publish || !self.binary_only)
     }
 
     pub fn can_release_to_cratesio(&self) -> bool {
-        self.cargo_token.is_some()
+        self.should_publish() && self.cargo_token.is_some()
+    }
+
+    /// The directory holding the `Cargo.toml` of the crate being released.
+    pub fn manifest_dir(&self) -> String {
+        match self.package_dir {
+            Some(ref dir) => Path::new(&self.repository_path).join(dir).to_string_lossy().into_owned(),
+            None => self.repository_path.clone(),
+        }
+    }
+
+    pub fn version_source(&self) -> VersionSource {
+        match self.version_file {
+            Some(ref file) => VersionSource::from_path(&Path::new(&self.repository_path).join(file)),
+            None => VersionSource::Cargo(PathBuf::from(self.manifest_dir())),
+        }
+    }
+
+    pub fn tag_name(&self, version: &str) -> String {
+        format!("{}{}", self.tag_prefix, version)
+    }
+
+    /// How release tags are described in messages.
+    pub fn tag_kind(&self) -> &'static str {
+        if self.tag_annotated { "annotated" } else { "lightweight" }
+    }
+
+    /// Releases of pre-release versions (e.g. `1.2.0-rc.1`) are marked as such on GitHub,
+    /// unless explicitly configured otherwise.
+    pub fn is_github_prerelease(&self, version: &Version) -> bool {
+        self.github_prerelease.unwrap_or(!version.pre.is_empty())
+    }
+
+    pub fn cargo_options(&self) -> CargoOptions {
+        CargoOptions {
+            features: self.features.clone(),
+            all_features: self.all_features,
+            no_default_features: self.no_default_features,
+            no_verify: self.no_verify,
+        }
+    }
+
+    pub fn changelog_options(&self) -> ChangelogOptions {
+        ChangelogOptions {
+            template: self.changelog_template.clone(),
+            tag_prefix: self.tag_prefix.clone(),
+            analyzer: self.analyzer_options(),
+            walk: self.walk_options(),
+            show_authors: self.changelog_show_authors,
+            repository_url: self.repository_url(),
+            sections: self.changelog_sections.clone(),
+            show_other: self.changelog_show_other,
+            issue_url_template: self.issue_url_template.clone(),
+            issue_pattern: self.issue_pattern.clone(),
+        }
+    }
+
+    /// Where the release of `tag_name` can be seen, if the provider has releases.
+    pub fn release_url(&self, tag_name: &str) -> Option<String> {
+        if !self.can_release_to_github() {
+            return None;
+        }
+        self.repository_url().map(|url| format!("{}/releases/tag/{}", url, tag_name))
+    }
+
+    /// The GitHub web URL of the repository, if it is known.
+    pub fn repository_url(&self) -> Option<String> {
+        if self.provider != Provider::GitHub {
+            return None;
+        }
+
+        match (self.user.as_ref(), self.repository_name.as_ref()) {
+            (Some(user), Some(repo)) => Some(format!("https://github.com/{}/{}", user, repo)),
+            _ => None,
+        }
+    }
+
+    pub fn analyzer_options(&self) -> AnalyzerOptions {
+        AnalyzerOptions {
+            bump_rules: self.bump_rules.clone(),
+            scan_squashed_bodies: self.scan_squashed_bodies,
+        }
+    }
+
+    pub fn walk_options(&self) -> WalkOptions {
+        WalkOptions {
+            exclude_paths: self.exclude_paths.clone(),
+            include_paths: self.include_paths.clone(),
+        }
+    }
+
+    /// Tokens and webhook URLs, which must never show up in the output.
+    pub fn secrets(&self) -> Vec<String> {
+        let mut secrets = vec![];
+        for secret in &[&self.gh_token, &self.gitlab_token, &self.bitbucket_token, &self.cargo_token,
+                        &self.notify_webhook, &self.slack_webhook] {
+            if let Some(ref secret) = **secret {
+                secrets.push(secret.clone());
+            }
+        }
+        // `username:app_password`, the password is used on its own to push
+        if let Some(password) = self.bitbucket_token.as_ref().and_then(|token| token.splitn(2, ':').nth(1)) {
+            secrets.push(password.to_owned());
+        }
+        secrets
+    }
+
+    /// The effective configuration, as printed by `--config-print`.
+    /// Tokens and webhook URLs are only shown as set or not.
+    pub fn to_json(&self) -> Json {
+        let mut bump_rules = BTreeMap::new();
+        for (kind, bump) in &self.bump_rules {
+            bump_rules.insert(kind.clone(), Json::String(bump_name(*bump).to_owned()));
+        }
+        let sections = self.changelog_sections.iter()
+            .map(|&(ref kind, ref title)| Json::Array(vec![string(kind), string(title)]))
+            .collect();
+        let version_replacements = self.version_replacements.iter()
+            .map(|&(ref file, ref pattern)| Json::Array(vec![string(file), string(pattern)]))
+            .collect();
+
+        let mut object = BTreeMap::new();
+        object.insert("user".to_owned(), optional(&self.user));
+        object.insert("repository_name".to_owned(), optional(&self.repository_name));
+        object.insert("branch".to_owned(), string(&self.branch));
+        object.insert("release_branches".to_owned(), strings(&self.release_branches));
+        object.insert("remote".to_owned(), match self.remote {
+            Ok(ref remote) => string(remote),
+            Err(_) => Json::Null,
+        });
+        object.insert("repository_path".to_owned(), string(&self.repository_path));
+        object.insert("write_mode".to_owned(), Json::Boolean(self.write_mode));
+        object.insert("release_mode".to_owned(), Json::Boolean(self.release_mode));
+        object.insert("committer".to_owned(), Json::String(format!("{} <{}>",
+            self.signature.name().unwrap_or(""), self.signature.email().unwrap_or(""))));
+        object.insert("gh_token".to_owned(), secret(&self.gh_token));
+        object.insert("gitlab_token".to_owned(), secret(&self.gitlab_token));
+        object.insert("bitbucket_token".to_owned(), secret(&self.bitbucket_token));
+        object.insert("cargo_token".to_owned(), secret(&self.cargo_token));
+        object.insert("bump_rules".to_owned(), Json::Object(bump_rules));
+        object.insert("scan_squashed_bodies".to_owned(), Json::Boolean(self.scan_squashed_bodies));
+        object.insert("tag_prefix".to_owned(), string(&self.tag_prefix));
+        object.insert("tag_format".to_owned(), optional(&self.tag_format));
+        object.insert("sign_tags".to_owned(), Json::Boolean(self.sign_tags));
+        object.insert("sign_commits".to_owned(), Json::Boolean(self.sign_commits));
+        object.insert("commit_message_template".to_owned(), string(&self.commit_message_template));
+        object.insert("tag_message_template".to_owned(), string(&self.tag_message_template));
+        object.insert("assets".to_owned(), strings(&self.assets));
+        object.insert("github_draft".to_owned(), Json::Boolean(self.github_draft));
+        object.insert("github_prerelease".to_owned(), match self.github_prerelease {
+            Some(prerelease) => Json::Boolean(prerelease),
+            None => Json::Null,
+        });
+        object.insert("github_release".to_owned(), Json::Boolean(self.github_release));
+        object.insert("github_generate_notes".to_owned(), Json::Boolean(self.github_generate_notes));
+        object.insert("provider".to_owned(), string(self.provider.name()));
+        object.insert("registry".to_owned(), optional(&self.registry));
+        object.insert("features".to_owned(), strings(&self.features));
+        object.insert("all_features".to_owned(), Json::Boolean(self.all_features));
+        object.insert("no_default_features".to_owned(), Json::Boolean(self.no_default_features));
+        object.insert("no_verify".to_owned(), Json::Boolean(self.no_verify));
+        object.insert("pre_release_hooks".to_owned(), strings(&self.pre_release_hooks));
+        object.insert("post_release_hooks".to_owned(), strings(&self.post_release_hooks));
+        object.insert("version_override".to_owned(), optional(&self.version_override.as_ref().map(|v| v.to_string())));
+        object.insert("forced_bump".to_owned(), match self.forced_bump {
+            Some(bump) => string(bump_name(bump)),
+            None => Json::Null,
+        });
+        object.insert("changelog_path".to_owned(), string(&self.changelog_path));
+        object.insert("changelog_output".to_owned(), optional(&self.changelog_output));
+        object.insert("generate_changelog".to_owned(), Json::Boolean(self.generate_changelog));
+        object.insert("changelog_template".to_owned(), optional(&self.changelog_template));
+        object.insert("issue_url_template".to_owned(), optional(&self.issue_url_template));
+        object.insert("issue_pattern".to_owned(), string(&self.issue_pattern));
+        object.insert("changelog_show_authors".to_owned(), Json::Boolean(self.changelog_show_authors));
+        object.insert("changelog_sections".to_owned(), Json::Array(sections));
+        object.insert("changelog_show_other".to_owned(), Json::Boolean(self.changelog_show_other));
+        object.insert("version_from_tags".to_owned(), Json::Boolean(self.version_from_tags));
+        object.insert("max_attempts".to_owned(), Json::U64(self.max_attempts as u64));
+        object.insert("allow_dirty".to_owned(), Json::Boolean(self.allow_dirty));
+        object.insert("package_name".to_owned(), optional(&self.package_name));
+        object.insert("package_dir".to_owned(), optional(&self.package_dir.as_ref().map(|dir| dir.to_string_lossy().into_owned())));
+        object.insert("publish_timeout".to_owned(), Json::U64(self.publish_timeout));
+        object.insert("publish".to_owned(), Json::Boolean(self.publish));
+        object.insert("binary_only".to_owned(), Json::Boolean(self.binary_only));
+        object.insert("force_publish".to_owned(), Json::Boolean(self.force_publish));
+        object.insert("skip_release_token".to_owned(), string(&self.skip_release_token));
+        object.insert("notify_webhook".to_owned(), secret(&self.notify_webhook));
+        object.insert("slack_webhook".to_owned(), secret(&self.slack_webhook));
+        object.insert("prerelease".to_owned(), optional(&self.prerelease));
+        object.insert("release_final".to_owned(), Json::Boolean(self.release_final));
+        object.insert("build_metadata".to_owned(), optional(&self.build_metadata));
+        object.insert("first_release".to_owned(), Json::Boolean(self.first_release));
+        object.insert("strict_history".to_owned(), Json::Boolean(self.strict_history));
+        object.insert("release_only".to_owned(), optional(&self.release_only));
+        object.insert("max_package_size".to_owned(), Json::U64(self.max_package_size));
+        object.insert("strict".to_owned(), Json::Boolean(self.strict));
+        object.insert("tag_wait_timeout".to_owned(), Json::U64(self.tag_wait_timeout));
+        object.insert("version_file".to_owned(), optional(&self.version_file));
+        object.insert("version_replacements".to_owned(), Json::Array(version_replacements));
+        object.insert("package_only".to_owned(), Json::Boolean(self.package_only));
+        object.insert("exclude_paths".to_owned(), strings(&self.exclude_paths));
+        object.insert("include_paths".to_owned(), strings(&self.include_paths));
+        object.insert("zero_ver_semantics".to_owned(), Json::Boolean(self.zero_ver_semantics));
+        object.insert("since".to_owned(), optional(&self.since));
+        object.insert("regenerate_changelog".to_owned(), Json::Boolean(self.regenerate_changelog));
+        object.insert("ci_wait".to_owned(), Json::Boolean(self.ci_wait));
+        object.insert("pull_request".to_owned(), Json::Boolean(self.pull_request));
+        object.insert("tag_annotated".to_owned(), Json::Boolean(self.tag_annotated));
+        object.insert("push".to_owned(), Json::Boolean(self.push));
+        Json::Object(object)
+    }
+}
+
+fn string(value: &str) -> Json {
+    Json::String(value.to_owned())
+}
+
+fn strings(values: &[String]) -> Json {
+    Json::Array(values.iter().map(|value| string(value)).collect())
+}
+
+fn optional(value: &Option<String>) -> Json {
+    match *value {
+        Some(ref value) => string(value),
+        None => Json::Null,
+    }
+}
+
+/// Only whether a secret is set, never its value.
+fn secret(value: &Option<String>) -> Json {
+    match *value {
+        Some(_) => string(REDACTED),
+        None => Json::Null,
+    }
+}
+
+/// Settings read from a `.semantic-rs.toml` file.
+///
+/// ```toml
+/// branches = ["master", "release/*"]
+/// tag_prefix = "v"
+/// sign_tags = true
+///
+/// [bump_rules]
+/// perf = "patch"
+///
+/// [hooks]
+/// pre_release = ["cargo doc"]
+/// ```
+#[derive(Debug, Default)]
+pub struct FileConfig {
+    pub branches: Vec<String>,
+    pub remote: Option<String>,
+    pub provider: Option<Provider>,
+    pub registry: Option<String>,
+    pub tag_prefix: Option<String>,
+    pub tag_format: Option<String>,
+    pub commit_message: Option<String>,
+    pub tag_message: Option<String>,
+    pub issue_url_template: Option<String>,
+    pub issue_pattern: Option<String>,
+    pub changelog: Option<String>,
+    pub generate_changelog: Option<bool>,
+    pub changelog_template: Option<String>,
+    pub changelog_authors: Option<bool>,
+    pub changelog_sections: Vec<(String, String)>,
+    pub version_replacements: Vec<(String, String)>,
+    pub changelog_other_section: Option<bool>,
+    pub version_from_tags: Option<bool>,
+    pub zero_ver_semantics: Option<bool>,
+    pub ci_wait: Option<bool>,
+    pub tag_annotated: Option<bool>,
+    pub push: Option<bool>,
+    pub max_attempts: Option<u32>,
+    pub publish_timeout: Option<u64>,
+    pub tag_wait_timeout: Option<u64>,
+    pub version_file: Option<String>,
+    /// In KiB.
+    pub max_package_size: Option<u64>,
+    pub publish: Option<bool>,
+    pub github_release: Option<bool>,
+    pub skip_release_token: Option<String>,
+    pub notify_webhook: Option<String>,
+    pub sign_tags: Option<bool>,
+    pub sign_commits: Option<bool>,
+    pub scan_squashed_bodies: Option<bool>,
+    pub github_draft: Option<bool>,
+    pub github_prerelease: Option<bool>,
+    pub github_generate_notes: Option<bool>,
+    pub features: Vec<String>,
+    pub all_features: Option<bool>,
+    pub no_default_features: Option<bool>,
+    pub no_verify: Option<bool>,
+    pub assets: Vec<String>,
+    pub exclude_paths: Vec<String>,
+    pub include_paths: Vec<String>,
+    pub bump_rules: Vec<(String, CommitType)>,
+    pub pre_release_hooks: Vec<String>,
+    pub post_release_hooks: Vec<String>,
+}
+
+fn get_string(table: &Table, key: &str) -> Result<Option<String>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(&Value::String(ref value)) => Ok(Some(value.clone())),
+        Some(_) => Err(format!("`{}` should be a string", key)),
+    }
+}
+
+fn get_bool(table: &Table, key: &str) -> Result<Option<bool>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(&Value::Boolean(value)) => Ok(Some(value)),
+        Some(_) => Err(format!("`{}` should be true or false", key)),
+    }
+}
+
+fn get_integer(table: &Table, key: &str) -> Result<Option<i64>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(&Value::Integer(value)) => Ok(Some(value)),
+        Some(_) => Err(format!("`{}` should be a number", key)),
+    }
+}
+
+fn get_strings(table: &Table, key: &str) -> Result<Vec<String>, String> {
+    match table.get(key) {
+        None => Ok(vec![]),
+        Some(&Value::Array(ref values)) => {
+            values.iter()
+                .map(|value| value.as_str()
+                     .map(|s| s.to_owned())
+                     .ok_or(format!("`{}` should be a list of strings", key)))
+                .collect()
+        }
+        Some(_) => Err(format!("`{}` should be a list of strings", key)),
+    }
+}
+
+fn get_table<'a>(table: &'a Table, key: &str) -> Result<Option<&'a Table>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(&Value::Table(ref value)) => Ok(Some(value)),
+        Some(_) => Err(format!("`[{}]` should be a table", key)),
+    }
+}
+
+/// Reads an array of `{ type = "...", title = "..." }` tables, keeping their order.
+fn get_sections(table: &Table, key: &str) -> Result<Vec<(String, String)>, String> {
+    get_pairs(table, key, "type", "title")
+}
+
+/// A list of tables like `[[key]]`, each holding the strings `first` and `second`.
+fn get_pairs(table: &Table, key: &str, first: &str, second: &str) -> Result<Vec<(String, String)>, String> {
+    let values = match table.get(key) {
+        None => return Ok(vec![]),
+        Some(&Value::Array(ref values)) => values,
+        Some(_) => return Err(format!("`[[{}]]` should be a list of tables", key)),
+    };
+
+    values.iter()
+        .map(|value| match *value {
+            Value::Table(ref section) => {
+                match (try!(get_string(section, first)), try!(get_string(section, second))) {
+                    (Some(first), Some(second)) => Ok((first, second)),
+                    _ => Err(format!("Every entry of `[[{}]]` needs a `{}` and a `{}`", key, first, second)),
+                }
+            }
+            _ => Err(format!("`[[{}]]` should be a list of tables", key)),
+        })
+        .collect()
+}
+
+impl FileConfig {
+    pub fn parse(content: &str) -> Result<FileConfig, String> {
+        let mut parser = Parser::new(content);
+        let table = match parser.parse() {
+            Some(table) => table,
+            None => {
+                let errors = parser.errors.iter()
+                    .map(|e| e.desc.clone())
+                    .collect::<Vec<_>>();
+                return Err(format!("Invalid TOML: {}", errors.join(", ")));
+            }
+        };
+
+        let provider = match try!(get_string(&table, "provider")) {
+            Some(provider) => Some(try!(provider.parse())),
+            None => None,
+        };
+
+        let mut bump_rules = vec![];
+        if let Some(rules) = try!(get_table(&table, "bump_rules")) {
+            for (commit_type, bump) in rules {
+                let bump = match bump.as_str() {
+                    Some(bump) => try!(bump.parse()),
+                    None => return Err(format!("The bump rule for `{}` should be a string", commit_type)),
+                };
+                bump_rules.push((commit_type.clone(), bump));
+            }
+        }
+
+        let (pre_release_hooks, post_release_hooks) = match try!(get_table(&table, "hooks")) {
+            Some(hooks) => (try!(get_strings(hooks, "pre_release")), try!(get_strings(hooks, "post_release"))),
+            None => (vec![], vec![]),
+        };
+
+        // `branch` is accepted as a shorthand for a single release branch.
+        let mut branches = try!(get_strings(&table, "branches"));
+        if let Some(branch) = try!(get_string(&table, "branch")) {
+            branches.insert(0, branch);
+        }
+
+        let changelog_sections = try!(get_sections(&table, "changelog_sections"));
+
+        let version_replacements = try!(get_pairs(&table, "version_replacements", "file", "pattern"));
+        for &(_, ref pattern) in &version_replacements {
+            try!(validate_version_pattern(pattern));
+        }
+
+        let tag_format = try!(get_string(&table, "tag_format"));
+        if let Some(ref format) = tag_format {
+            try!(validate_tag_format(format));
+        }
+
+        let issue_pattern = try!(get_string(&table, "issue_pattern"));
+        if let Some(ref pattern) = issue_pattern {
+            try!(validate_issue_pattern(pattern));
+        }
+
+        let max_attempts = match try!(get_integer(&table, "max_attempts")) {
+            Some(attempts) if attempts < 1 => return Err("`max_attempts` should be at least 1".into()),
+            Some(attempts) => Some(attempts as u32),
+            None => None,
+        };
+
+        let publish_timeout = match try!(get_integer(&table, "publish_timeout")) {
+            Some(seconds) if seconds < 0 => return Err("`publish_timeout` can't be negative".into()),
+            Some(seconds) => Some(seconds as u64),
+            None => None,
+        };
+
+        let tag_wait_timeout = match try!(get_integer(&table, "tag_wait_timeout")) {
+            Some(seconds) if seconds < 0 => return Err("`tag_wait_timeout` can't be negative".into()),
+            Some(seconds) => Some(seconds as u64),
+            None => None,
+        };
+
+        let max_package_size = match try!(get_integer(&table, "max_package_size")) {
+            Some(size) if size < 1 => return Err("`max_package_size` should be at least 1".into()),
+            Some(size) => Some(size as u64),
+            None => None,
+        };
+
+        Ok(FileConfig {
+            branches: branches,
+            remote: try!(get_string(&table, "remote")),
+            provider: provider,
+            registry: try!(get_string(&table, "registry")),
+            tag_prefix: try!(get_string(&table, "tag_prefix")),
+            tag_format: tag_format,
+            commit_message: try!(get_string(&table, "commit_message")),
+            tag_message: try!(get_string(&table, "tag_message")),
+            issue_url_template: try!(get_string(&table, "issue_url_template")),
+            issue_pattern: issue_pattern,
+            changelog: try!(get_string(&table, "changelog")),
+            generate_changelog: try!(get_bool(&table, "generate_changelog")),
+            changelog_template: try!(get_string(&table, "changelog_template")),
+            changelog_authors: try!(get_bool(&table, "changelog_authors")),
+            changelog_sections: changelog_sections,
+            version_replacements: version_replacements,
+            changelog_other_section: try!(get_bool(&table, "changelog_other_section")),
+            version_from_tags: try!(get_bool(&table, "version_from_tags")),
+            zero_ver_semantics: try!(get_bool(&table, "zero_ver_semantics")),
+            ci_wait: try!(get_bool(&table, "ci_wait")),
+            tag_annotated: try!(get_bool(&table, "tag_annotated")),
+            push: try!(get_bool(&table, "push")),
+            max_attempts: max_attempts,
+            publish_timeout: publish_timeout,
+            tag_wait_timeout: tag_wait_timeout,
+            version_file: try!(get_string(&table, "version_file")),
+            max_package_size: max_package_size,
+            publish: try!(get_bool(&table, "publish")),
+            github_release: try!(get_bool(&table, "github_release")),
+            skip_release_token: try!(get_string(&table, "skip_release_token")),
+            notify_webhook: try!(get_string(&table, "notify_webhook")),
+            sign_tags: try!(get_bool(&table, "sign_tags")),
+            sign_commits: try!(get_bool(&table, "sign_commits")),
+            scan_squashed_bodies: try!(get_bool(&table, "scan_squashed_bodies")),
+            github_draft: try!(get_bool(&table, "github_draft")),
+            github_prerelease: try!(get_bool(&table, "github_prerelease")),
+            github_generate_notes: try!(get_bool(&table, "github_generate_notes")),
+            features: try!(get_strings(&table, "features")),
+            all_features: try!(get_bool(&table, "all_features")),
+            no_default_features: try!(get_bool(&table, "no_default_features")),
+            no_verify: try!(get_bool(&table, "no_verify")),
+            assets: try!(get_strings(&table, "assets")),
+            exclude_paths: try!(get_strings(&table, "exclude_paths")),
+            include_paths: try!(get_strings(&table, "include_paths")),
+            bump_rules: bump_rules,
+            pre_release_hooks: pre_release_hooks,
+            post_release_hooks: post_release_hooks,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<FileConfig, String> {
+        let mut content = String::new();
+        try!(File::open(path)
+             .and_then(|mut file| file.read_to_string(&mut content))
+             .map_err(|e| format!("Can't read {}: {}", path.display(), e)));
+
+        FileConfig::parse(&content)
+            .map_err(|e| format!("Can't parse {}: {}", path.display(), e))
+    }
+}
+
+/// A tag format has to end in `{version}`, so tags can be matched by their prefix.
+pub fn validate_tag_format(format: &str) -> Result<(), String> {
+    if format.ends_with("{version}") && format.matches("{version}").count() == 1 {
+        Ok(())
+    } else {
+        Err(format!("The tag format `{}` has to end with {{version}}", format))
+    }
+}
+
+/// A pre-release label becomes part of the version, so it may only contain
+/// ASCII letters, digits and hyphens, and can't be a number.
+pub fn validate_prerelease_label(label: &str) -> Result<(), String> {
+    let valid_chars = label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if label.is_empty() || !valid_chars || label.chars().all(|c| c.is_ascii_digit()) {
+        Err(format!("`{}` is not a valid pre-release label, use letters, digits and hyphens like `rc` or `beta`", label))
+    } else {
+        Ok(())
+    }
+}
+
+/// Patterns of `version_replacements` have to be valid and capture the version.
+pub fn validate_version_pattern(pattern: &str) -> Result<(), String> {
+    match Regex::new(pattern) {
+        Ok(ref re) if re.captures_len() > 1 => Ok(()),
+        Ok(_) => Err(format!("The version pattern `{}` needs a capture group around the version", pattern)),
+        Err(err) => Err(format!("The version pattern `{}` is invalid: {}", pattern, err)),
+    }
+}
+
+/// The issue pattern has to be a valid regex, unlike version patterns it needs no capture group.
+pub fn validate_issue_pattern(pattern: &str) -> Result<(), String> {
+    Regex::new(pattern)
+        .map(|_| ())
+        .map_err(|err| format!("The issue pattern `{}` is invalid: {}", pattern, err))
+}
+
+/// The tag prefix described by a format like `{name}-v{version}`.
+/// Without a package name `{name}` is left as is.
+pub fn tag_prefix_from_format(format: &str, package_name: Option<&str>) -> String {
+    let prefix = format.trim_right_matches("{version}");
+    match package_name {
+        Some(name) => render_template(prefix, &[("name", name)]),
+        None => prefix.to_owned(),
     }
 }
 
@@ -39,6 +857,7 @@ pub struct ConfigBuilder {
     repository_name: Option<String>,
 
     branch: Option<String>,
+    release_branches: Option<Vec<String>>,
 
     repository_path: Option<String>,
 
@@ -51,7 +870,127 @@ pub struct ConfigBuilder {
     signature: Option<Signature<'static>>,
 
     gh_token: Option<String>,
+    gitlab_token: Option<String>,
+    bitbucket_token: Option<String>,
     cargo_token: Option<String>,
+
+    bump_rules: BumpRules,
+    scan_squashed_bodies: bool,
+
+    tag_prefix: Option<String>,
+
+    sign_tags: bool,
+    sign_commits: bool,
+
+    commit_message_template: Option<String>,
+
+    assets: Vec<String>,
+
+    github_draft: bool,
+    github_prerelease: Option<bool>,
+
+    provider: Provider,
+
+    registry: Option<String>,
+
+    features: Vec<String>,
+    all_features: bool,
+    no_default_features: bool,
+    no_verify: bool,
+
+    pre_release_hooks: Vec<String>,
+    post_release_hooks: Vec<String>,
+
+    version_override: Option<Version>,
+
+    forced_bump: Option<CommitType>,
+
+    changelog_path: Option<String>,
+
+    generate_changelog: bool,
+
+    changelog_template: Option<String>,
+
+    changelog_show_authors: bool,
+
+    version_from_tags: bool,
+
+    max_attempts: u32,
+
+    changelog_sections: Vec<(String, String)>,
+
+    changelog_show_other: bool,
+
+    allow_dirty: bool,
+
+    package_name: Option<String>,
+    package_dir: Option<PathBuf>,
+
+    tag_format: Option<String>,
+
+    publish_timeout: u64,
+
+    publish: bool,
+
+    github_release: bool,
+
+    binary_only: bool,
+    force_publish: bool,
+
+    skip_release_token: Option<String>,
+
+    notify_webhook: Option<String>,
+    slack_webhook: Option<String>,
+
+    prerelease: Option<String>,
+    release_final: bool,
+
+    build_metadata: Option<String>,
+
+    first_release: bool,
+
+    strict_history: bool,
+
+    release_only: Option<String>,
+
+    max_package_size: u64,
+
+    strict: bool,
+
+    tag_wait_timeout: u64,
+
+    version_file: Option<String>,
+
+    version_replacements: Vec<(String, String)>,
+
+    github_generate_notes: bool,
+
+    package_only: bool,
+
+    exclude_paths: Vec<String>,
+
+    include_paths: Vec<String>,
+
+    zero_ver_semantics: bool,
+
+    since: Option<String>,
+
+    regenerate_changelog: bool,
+
+    ci_wait: bool,
+
+    pull_request: bool,
+
+    tag_annotated: bool,
+
+    tag_message_template: Option<String>,
+
+    push: bool,
+
+    issue_url_template: Option<String>,
+    issue_pattern: Option<String>,
+
+    changelog_output: Option<String>,
 }
 
 impl ConfigBuilder {
@@ -60,14 +999,82 @@ impl ConfigBuilder {
             user: None,
             repository_name: None,
             branch: None,
+            release_branches: None,
             repository_path: None,
             write_mode: false,
             release_mode: false,
             repository: None,
             signature: None,
             gh_token: None,
+            gitlab_token: None,
+            bitbucket_token: None,
             cargo_token: None,
-            remote: None
+            remote: None,
+            bump_rules: commit_analyzer::default_bump_rules(),
+            scan_squashed_bodies: true,
+            tag_prefix: None,
+            sign_tags: false,
+            sign_commits: false,
+            commit_message_template: None,
+            assets: vec![],
+            github_draft: false,
+            github_prerelease: None,
+            provider: Provider::GitHub,
+            registry: None,
+            features: vec![],
+            all_features: false,
+            no_default_features: false,
+            no_verify: false,
+            pre_release_hooks: vec![],
+            post_release_hooks: vec![],
+            version_override: None,
+            forced_bump: None,
+            changelog_path: None,
+            generate_changelog: true,
+            changelog_template: None,
+            changelog_show_authors: false,
+            version_from_tags: false,
+            max_attempts: 3,
+            changelog_sections: vec![],
+            changelog_show_other: true,
+            allow_dirty: false,
+            package_name: None,
+            package_dir: None,
+            tag_format: None,
+            publish_timeout: 300,
+            publish: true,
+            github_release: true,
+            binary_only: false,
+            force_publish: false,
+            skip_release_token: None,
+            notify_webhook: None,
+            slack_webhook: None,
+            prerelease: None,
+            release_final: false,
+            build_metadata: None,
+            first_release: false,
+            strict_history: false,
+            release_only: None,
+            max_package_size: DEFAULT_MAX_PACKAGE_SIZE,
+            strict: false,
+            tag_wait_timeout: 30,
+            version_file: None,
+            version_replacements: vec![],
+            github_generate_notes: false,
+            package_only: false,
+            exclude_paths: vec![],
+            include_paths: vec![],
+            zero_ver_semantics: false,
+            since: None,
+            regenerate_changelog: false,
+            ci_wait: true,
+            pull_request: false,
+            tag_annotated: true,
+            tag_message_template: None,
+            push: true,
+            issue_url_template: None,
+            issue_pattern: None,
+            changelog_output: None,
         }
     }
 
@@ -86,6 +1093,11 @@ impl ConfigBuilder {
         self
     }
 
+    pub fn release_branches(&mut self, branches: Vec<String>) -> &mut Self {
+        self.release_branches = Some(branches);
+        self
+    }
+
     pub fn repository_path(&mut self, path: String) -> &mut Self {
         self.repository_path = Some(path);
         self
@@ -116,6 +1128,16 @@ impl ConfigBuilder {
         self
     }
 
+    pub fn gitlab_token(&mut self, token: String) -> &mut Self {
+        self.gitlab_token = Some(token);
+        self
+    }
+
+    pub fn bitbucket_token(&mut self, token: String) -> &mut Self {
+        self.bitbucket_token = Some(token);
+        self
+    }
+
     pub fn cargo_token(&mut self, token: String) -> &mut Self {
         self.cargo_token = Some(token);
         self
@@ -126,19 +1148,577 @@ impl ConfigBuilder {
         self
     }
 
-    pub fn build(self) -> Config {
-        Config {
-            user: self.user,
-            repository_name: self.repository_name,
-            branch: self.branch.unwrap_or("master".into()),
-            repository_path: self.repository_path.unwrap(),
-            write_mode: self.write_mode,
+    /// Maps a commit type (e.g. `perf`) to the bump it triggers,
+    /// overriding the default for that type.
+    pub fn bump_rule(&mut self, commit_type: String, bump: CommitType) -> &mut Self {
+        self.bump_rules.insert(commit_type, bump);
+        self
+    }
+
+    pub fn scan_squashed_bodies(&mut self, scan: bool) -> &mut Self {
+        self.scan_squashed_bodies = scan;
+        self
+    }
+
+    /// Replaces a tag format set before, e.g. from the config file.
+    pub fn tag_prefix(&mut self, prefix: String) -> &mut Self {
+        self.tag_prefix = Some(prefix);
+        self.tag_format = None;
+        self
+    }
+
+    pub fn sign_tags(&mut self, sign: bool) -> &mut Self {
+        self.sign_tags = sign;
+        self
+    }
+
+    pub fn sign_commits(&mut self, sign: bool) -> &mut Self {
+        self.sign_commits = sign;
+        self
+    }
+
+    /// `{version}` and `{tag}` are replaced with the new version and tag name.
+    pub fn commit_message_template(&mut self, template: String) -> &mut Self {
+        self.commit_message_template = Some(template);
+        self
+    }
+
+    pub fn asset(&mut self, path: String) -> &mut Self {
+        self.assets.push(path);
+        self
+    }
+
+    pub fn github_draft(&mut self, draft: bool) -> &mut Self {
+        self.github_draft = draft;
+        self
+    }
+
+    pub fn github_prerelease(&mut self, prerelease: bool) -> &mut Self {
+        self.github_prerelease = Some(prerelease);
+        self
+    }
+
+    pub fn provider(&mut self, provider: Provider) -> &mut Self {
+        self.provider = provider;
+        self
+    }
+
+    pub fn registry(&mut self, registry: String) -> &mut Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    pub fn feature(&mut self, feature: String) -> &mut Self {
+        self.features.push(feature);
+        self
+    }
+
+    pub fn all_features(&mut self, all: bool) -> &mut Self {
+        self.all_features = all;
+        self
+    }
+
+    pub fn no_default_features(&mut self, no_default: bool) -> &mut Self {
+        self.no_default_features = no_default;
+        self
+    }
+
+    pub fn no_verify(&mut self, no_verify: bool) -> &mut Self {
+        self.no_verify = no_verify;
+        self
+    }
+
+    /// Shell command run after the version and changelog are written, before committing.
+    pub fn pre_release_hook(&mut self, hook: String) -> &mut Self {
+        self.pre_release_hooks.push(hook);
+        self
+    }
+
+    /// Shell command run after the release is published.
+    pub fn post_release_hook(&mut self, hook: String) -> &mut Self {
+        self.post_release_hooks.push(hook);
+        self
+    }
+
+    /// Takes over all settings present in the file.
+    /// Apply it before any command line flags, so those take precedence.
+    pub fn file_config(&mut self, file: &FileConfig) -> &mut Self {
+        if !file.branches.is_empty() {
+            self.release_branches(file.branches.clone());
+        }
+        if let Some(provider) = file.provider {
+            self.provider(provider);
+        }
+        if let Some(ref registry) = file.registry {
+            self.registry(registry.clone());
+        }
+        if let Some(ref prefix) = file.tag_prefix {
+            self.tag_prefix(prefix.clone());
+        }
+        if let Some(ref format) = file.tag_format {
+            self.tag_format(format.clone());
+        }
+        if let Some(ref template) = file.commit_message {
+            self.commit_message_template(template.clone());
+        }
+        if let Some(ref template) = file.tag_message {
+            self.tag_message_template(template.clone());
+        }
+        if let Some(ref template) = file.issue_url_template {
+            self.issue_url_template(template.clone());
+        }
+        if let Some(ref pattern) = file.issue_pattern {
+            self.issue_pattern(pattern.clone());
+        }
+        if let Some(ref path) = file.changelog {
+            self.changelog_path(path.clone());
+        }
+        if let Some(ref template) = file.changelog_template {
+            self.changelog_template(template.clone());
+        }
+        if let Some(attempts) = file.max_attempts {
+            self.max_attempts(attempts);
+        }
+        if let Some(seconds) = file.publish_timeout {
+            self.publish_timeout(seconds);
+        }
+        if let Some(ref path) = file.version_file {
+            self.version_file(path.clone());
+        }
+        if let Some(seconds) = file.tag_wait_timeout {
+            self.tag_wait_timeout(seconds);
+        }
+        if let Some(kilobytes) = file.max_package_size {
+            self.max_package_size(kilobytes * 1024);
+        }
+        if let Some(publish) = file.publish {
+            self.publish(publish);
+        }
+        if let Some(release) = file.github_release {
+            self.github_release(release);
+        }
+        if let Some(ref token) = file.skip_release_token {
+            self.skip_release_token(token.clone());
+        }
+        if let Some(ref url) = file.notify_webhook {
+            self.notify_webhook(url.clone());
+        }
+        if let Some(enabled) = file.version_from_tags {
+            self.version_from_tags(enabled);
+        }
+        if let Some(enabled) = file.zero_ver_semantics {
+            self.zero_ver_semantics(enabled);
+        }
+        if let Some(wait) = file.ci_wait {
+            self.ci_wait(wait);
+        }
+        if let Some(annotated) = file.tag_annotated {
+            self.tag_annotated(annotated);
+        }
+        if let Some(push) = file.push {
+            self.push(push);
+        }
+        if let Some(show) = file.changelog_authors {
+            self.changelog_show_authors(show);
+        }
+        for &(ref commit_type, ref title) in &file.changelog_sections {
+            self.changelog_section(commit_type.clone(), title.clone());
+        }
+        for &(ref path, ref pattern) in &file.version_replacements {
+            self.version_replacement(path.clone(), pattern.clone());
+        }
+        if let Some(show) = file.changelog_other_section {
+            self.changelog_show_other(show);
+        }
+        if let Some(generate) = file.generate_changelog {
+            self.generate_changelog(generate);
+        }
+        if let Some(sign) = file.sign_tags {
+            self.sign_tags(sign);
+        }
+        if let Some(sign) = file.sign_commits {
+            self.sign_commits(sign);
+        }
+        if let Some(scan) = file.scan_squashed_bodies {
+            self.scan_squashed_bodies(scan);
+        }
+        if let Some(draft) = file.github_draft {
+            self.github_draft(draft);
+        }
+        if let Some(prerelease) = file.github_prerelease {
+            self.github_prerelease(prerelease);
+        }
+        if let Some(generate) = file.github_generate_notes {
+            self.github_generate_notes(generate);
+        }
+        if let Some(all) = file.all_features {
+            self.all_features(all);
+        }
+        if let Some(no_default) = file.no_default_features {
+            self.no_default_features(no_default);
+        }
+        if let Some(no_verify) = file.no_verify {
+            self.no_verify(no_verify);
+        }
+        for feature in &file.features {
+            self.feature(feature.clone());
+        }
+        for asset in &file.assets {
+            self.asset(asset.clone());
+        }
+        for pattern in &file.exclude_paths {
+            self.exclude_path(pattern.clone());
+        }
+        for pattern in &file.include_paths {
+            self.include_path(pattern.clone());
+        }
+        for &(ref commit_type, bump) in &file.bump_rules {
+            self.bump_rule(commit_type.clone(), bump);
+        }
+        for hook in &file.pre_release_hooks {
+            self.pre_release_hook(hook.clone());
+        }
+        for hook in &file.post_release_hooks {
+            self.post_release_hook(hook.clone());
+        }
+        self
+    }
+
+    pub fn version_override(&mut self, version: Version) -> &mut Self {
+        self.version_override = Some(version);
+        self
+    }
+
+    pub fn forced_bump(&mut self, bump: CommitType) -> &mut Self {
+        self.forced_bump = Some(bump);
+        self
+    }
+
+    pub fn changelog_path(&mut self, path: String) -> &mut Self {
+        self.changelog_path = Some(path);
+        self
+    }
+
+    pub fn generate_changelog(&mut self, generate: bool) -> &mut Self {
+        self.generate_changelog = generate;
+        self
+    }
+
+    pub fn changelog_template(&mut self, path: String) -> &mut Self {
+        self.changelog_template = Some(path);
+        self
+    }
+
+    pub fn changelog_show_authors(&mut self, show: bool) -> &mut Self {
+        self.changelog_show_authors = show;
+        self
+    }
+
+    pub fn version_from_tags(&mut self, enabled: bool) -> &mut Self {
+        self.version_from_tags = enabled;
+        self
+    }
+
+    pub fn max_attempts(&mut self, attempts: u32) -> &mut Self {
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// Lists commits of `commit_type` under their own section called `title`.
+    pub fn changelog_section(&mut self, commit_type: String, title: String) -> &mut Self {
+        self.changelog_sections.push((commit_type, title));
+        self
+    }
+
+    pub fn changelog_show_other(&mut self, show: bool) -> &mut Self {
+        self.changelog_show_other = show;
+        self
+    }
+
+    pub fn allow_dirty(&mut self, allow: bool) -> &mut Self {
+        self.allow_dirty = allow;
+        self
+    }
+
+    /// Releases a single member of a workspace, found in `dir` relative to the repository.
+    pub fn package(&mut self, name: String, dir: PathBuf) -> &mut Self {
+        self.package_name = Some(name);
+        self.package_dir = Some(dir);
+        self
+    }
+
+    pub fn package_name(&mut self, name: String) -> &mut Self {
+        self.package_name = Some(name);
+        self
+    }
+
+    /// Tag names like `{name}-v{version}`, instead of a plain prefix.
+    pub fn tag_format(&mut self, format: String) -> &mut Self {
+        self.tag_format = Some(format);
+        self
+    }
+
+    pub fn publish_timeout(&mut self, seconds: u64) -> &mut Self {
+        self.publish_timeout = seconds;
+        self
+    }
+
+    pub fn publish(&mut self, publish: bool) -> &mut Self {
+        self.publish = publish;
+        self
+    }
+
+    pub fn github_release(&mut self, release: bool) -> &mut Self {
+        self.github_release = release;
+        self
+    }
+
+    pub fn binary_only(&mut self, binary_only: bool) -> &mut Self {
+        self.binary_only = binary_only;
+        self
+    }
+
+    pub fn force_publish(&mut self, force: bool) -> &mut Self {
+        self.force_publish = force;
+        self
+    }
+
+    pub fn skip_release_token(&mut self, token: String) -> &mut Self {
+        self.skip_release_token = Some(token);
+        self
+    }
+
+    pub fn notify_webhook(&mut self, url: String) -> &mut Self {
+        self.notify_webhook = Some(url);
+        self
+    }
+
+    pub fn slack_webhook(&mut self, url: String) -> &mut Self {
+        self.slack_webhook = Some(url);
+        self
+    }
+
+    pub fn prerelease(&mut self, label: String) -> &mut Self {
+        self.prerelease = Some(label);
+        self
+    }
+
+    pub fn release_final(&mut self, release_final: bool) -> &mut Self {
+        self.release_final = release_final;
+        self
+    }
+
+    pub fn build_metadata(&mut self, template: String) -> &mut Self {
+        self.build_metadata = Some(template);
+        self
+    }
+
+    pub fn first_release(&mut self, first_release: bool) -> &mut Self {
+        self.first_release = first_release;
+        self
+    }
+
+    pub fn strict_history(&mut self, strict: bool) -> &mut Self {
+        self.strict_history = strict;
+        self
+    }
+
+    pub fn release_only(&mut self, tag_name: String) -> &mut Self {
+        self.release_only = Some(tag_name);
+        self
+    }
+
+    pub fn max_package_size(&mut self, bytes: u64) -> &mut Self {
+        self.max_package_size = bytes;
+        self
+    }
+
+    pub fn strict(&mut self, strict: bool) -> &mut Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn tag_wait_timeout(&mut self, seconds: u64) -> &mut Self {
+        self.tag_wait_timeout = seconds;
+        self
+    }
+
+    pub fn version_file(&mut self, path: String) -> &mut Self {
+        self.version_file = Some(path);
+        self
+    }
+
+    /// Also rewrites the version in `file`, where the first capture group of `pattern` matches it.
+    pub fn version_replacement(&mut self, file: String, pattern: String) -> &mut Self {
+        self.version_replacements.push((file, pattern));
+        self
+    }
+
+    pub fn github_generate_notes(&mut self, generate: bool) -> &mut Self {
+        self.github_generate_notes = generate;
+        self
+    }
+
+    pub fn package_only(&mut self, package_only: bool) -> &mut Self {
+        self.package_only = package_only;
+        self
+    }
+
+    /// Commits only changing files matching `pattern` don't count towards the bump.
+    pub fn exclude_path(&mut self, pattern: String) -> &mut Self {
+        self.exclude_paths.push(pattern);
+        self
+    }
+
+    /// Only commits changing files matching `pattern` count towards the bump.
+    pub fn include_path(&mut self, pattern: String) -> &mut Self {
+        self.include_paths.push(pattern);
+        self
+    }
+
+    pub fn zero_ver_semantics(&mut self, enabled: bool) -> &mut Self {
+        self.zero_ver_semantics = enabled;
+        self
+    }
+
+    /// Analyze commits since `revision` instead of since the latest release tag.
+    pub fn since(&mut self, revision: String) -> &mut Self {
+        self.since = Some(revision);
+        self
+    }
+
+    pub fn regenerate_changelog(&mut self, regenerate: bool) -> &mut Self {
+        self.regenerate_changelog = regenerate;
+        self
+    }
+
+    pub fn ci_wait(&mut self, wait: bool) -> &mut Self {
+        self.ci_wait = wait;
+        self
+    }
+
+    pub fn pull_request(&mut self, pull_request: bool) -> &mut Self {
+        self.pull_request = pull_request;
+        self
+    }
+
+    pub fn tag_annotated(&mut self, annotated: bool) -> &mut Self {
+        self.tag_annotated = annotated;
+        self
+    }
+
+    /// `{version}`, `{tag}`, `{date}` and `{changelog}` are replaced.
+    pub fn tag_message_template(&mut self, template: String) -> &mut Self {
+        self.tag_message_template = Some(template);
+        self
+    }
+
+    pub fn push(&mut self, push: bool) -> &mut Self {
+        self.push = push;
+        self
+    }
+
+    /// Link ticket references in the changelog, `{id}` is replaced with the reference.
+    pub fn issue_url_template(&mut self, template: String) -> &mut Self {
+        self.issue_url_template = Some(template);
+        self
+    }
+
+    pub fn issue_pattern(&mut self, pattern: String) -> &mut Self {
+        self.issue_pattern = Some(pattern);
+        self
+    }
+
+    /// Also write the notes of the new release alone to `path`.
+    pub fn changelog_output(&mut self, path: String) -> &mut Self {
+        self.changelog_output = Some(path);
+        self
+    }
+
+    pub fn build(self) -> Config {
+        Config {
+            user: self.user,
+            repository_name: self.repository_name,
+            branch: self.branch.unwrap_or("master".into()),
+            release_branches: self.release_branches.unwrap_or(vec!["master".into()]),
+            repository_path: self.repository_path.unwrap(),
+            write_mode: self.write_mode,
             release_mode: self.release_mode,
             repository: self.repository.unwrap(),
             signature: self.signature.unwrap(),
             gh_token: self.gh_token,
+            gitlab_token: self.gitlab_token,
+            bitbucket_token: self.bitbucket_token,
             cargo_token: self.cargo_token,
-            remote: self.remote.unwrap_or(Err("No remote found".into()))
+            remote: self.remote.unwrap_or(Err("No remote found".into())),
+            bump_rules: self.bump_rules,
+            scan_squashed_bodies: self.scan_squashed_bodies,
+            tag_prefix: match self.tag_format {
+                Some(ref format) => tag_prefix_from_format(format, self.package_name.as_ref().map(|n| &n[..])),
+                None => self.tag_prefix.unwrap_or("v".into()),
+            },
+            sign_tags: self.sign_tags,
+            sign_commits: self.sign_commits,
+            commit_message_template: self.commit_message_template.unwrap_or(DEFAULT_COMMIT_MESSAGE.into()),
+            assets: self.assets,
+            github_draft: self.github_draft,
+            github_prerelease: self.github_prerelease,
+            provider: self.provider,
+            registry: self.registry,
+            features: self.features,
+            all_features: self.all_features,
+            no_default_features: self.no_default_features,
+            no_verify: self.no_verify,
+            pre_release_hooks: self.pre_release_hooks,
+            post_release_hooks: self.post_release_hooks,
+            version_override: self.version_override,
+            forced_bump: self.forced_bump,
+            changelog_path: self.changelog_path.unwrap_or(DEFAULT_CHANGELOG_PATH.into()),
+            generate_changelog: self.generate_changelog,
+            changelog_template: self.changelog_template,
+            changelog_show_authors: self.changelog_show_authors,
+            version_from_tags: self.version_from_tags,
+            max_attempts: self.max_attempts,
+            changelog_sections: self.changelog_sections,
+            changelog_show_other: self.changelog_show_other,
+            allow_dirty: self.allow_dirty,
+            package_name: self.package_name,
+            package_dir: self.package_dir,
+            tag_format: self.tag_format,
+            publish_timeout: self.publish_timeout,
+            publish: self.publish,
+            github_release: self.github_release,
+            binary_only: self.binary_only,
+            force_publish: self.force_publish,
+            skip_release_token: self.skip_release_token.unwrap_or(DEFAULT_SKIP_RELEASE_TOKEN.into()),
+            notify_webhook: self.notify_webhook,
+            slack_webhook: self.slack_webhook,
+            prerelease: self.prerelease,
+            release_final: self.release_final,
+            build_metadata: self.build_metadata,
+            first_release: self.first_release,
+            strict_history: self.strict_history,
+            release_only: self.release_only,
+            max_package_size: self.max_package_size,
+            strict: self.strict,
+            tag_wait_timeout: self.tag_wait_timeout,
+            version_file: self.version_file,
+            version_replacements: self.version_replacements,
+            github_generate_notes: self.github_generate_notes,
+            package_only: self.package_only,
+            exclude_paths: self.exclude_paths,
+            include_paths: self.include_paths,
+            zero_ver_semantics: self.zero_ver_semantics,
+            since: self.since,
+            regenerate_changelog: self.regenerate_changelog,
+            ci_wait: self.ci_wait,
+            pull_request: self.pull_request,
+            tag_annotated: self.tag_annotated,
+            tag_message_template: self.tag_message_template.unwrap_or(DEFAULT_TAG_MESSAGE.into()),
+            push: self.push,
+            issue_url_template: self.issue_url_template,
+            issue_pattern: self.issue_pattern.unwrap_or(DEFAULT_ISSUE_PATTERN.into()),
+            changelog_output: self.changelog_output,
         }
     }
 }
@@ -148,3 +1728,100 @@ impl Default for ConfigBuilder {
         ConfigBuilder::new()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use commit_analyzer::CommitType;
+
+    #[test]
+    fn parses_config_file() {
+        let file = FileConfig::parse(r#"
+branches = ["main", "release/*"]
+tag_prefix = ""
+provider = "gitlab"
+sign_tags = true
+features = ["cli"]
+
+[bump_rules]
+perf = "patch"
+
+[hooks]
+pre_release = ["cargo doc", "make docs"]
+
+[[changelog_sections]]
+type = "feat"
+title = "Features"
+
+[[changelog_sections]]
+type = "perf"
+title = "Performance"
+
+[[version_replacements]]
+file = "README.md"
+pattern = 'semantic-rs = "([0-9.]+)"'
+"#).unwrap();
+
+        assert_eq!(vec!["main".to_owned(), "release/*".to_owned()], file.branches);
+        assert_eq!(Some("".to_owned()), file.tag_prefix);
+        assert_eq!(Some(Provider::GitLab), file.provider);
+        assert_eq!(Some(true), file.sign_tags);
+        assert_eq!(None, file.sign_commits);
+        assert_eq!(vec!["cli".to_owned()], file.features);
+        assert_eq!(vec![("perf".to_owned(), CommitType::Patch)], file.bump_rules);
+        assert_eq!(vec!["cargo doc".to_owned(), "make docs".to_owned()], file.pre_release_hooks);
+        assert!(file.post_release_hooks.is_empty());
+        assert_eq!(vec![("feat".to_owned(), "Features".to_owned()),
+                        ("perf".to_owned(), "Performance".to_owned())],
+                   file.changelog_sections);
+        assert_eq!(vec![("README.md".to_owned(), r#"semantic-rs = "([0-9.]+)""#.to_owned())],
+                   file.version_replacements);
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert!(FileConfig::parse("sign_tags = \"yes\"").is_err());
+        assert!(FileConfig::parse("[bump_rules]\nperf = \"huge\"").is_err());
+        assert!(FileConfig::parse("branch = ").is_err());
+        assert!(FileConfig::parse("branches = \"main\"").is_err());
+        assert!(FileConfig::parse("tag_format = \"v{version}-{name}\"").is_err());
+        assert!(FileConfig::parse("[[changelog_sections]]\ntype = \"feat\"").is_err());
+        assert!(FileConfig::parse("max_package_size = 0").is_err());
+        assert!(FileConfig::parse("issue_pattern = \"PROJ-[0-9+\"").is_err());
+        assert!(FileConfig::parse("[[version_replacements]]\nfile = \"README.md\"\npattern = \"semantic-rs = \\\"[0-9.]+\\\"\"").is_err());
+    }
+
+    #[test]
+    fn tag_prefix_from_tag_format() {
+        assert_eq!("crate-a-v", tag_prefix_from_format("{name}-v{version}", Some("crate-a")));
+        assert_eq!("release-", tag_prefix_from_format("release-{version}", None));
+        assert_eq!("{name}@", tag_prefix_from_format("{name}@{version}", None));
+
+        assert!(validate_tag_format("{name}-v{version}").is_ok());
+        assert!(validate_tag_format("{version}-{name}").is_err());
+        assert!(validate_tag_format("{version}{version}").is_err());
+    }
+
+    #[test]
+    fn tag_prefix_overrides_earlier_tag_format() {
+        let file = FileConfig::parse("tag_prefix = \"v\"\ntag_format = \"{name}-v{version}\"").unwrap();
+        let mut builder = ConfigBuilder::new();
+        builder.file_config(&file);
+        assert_eq!(Some("{name}-v{version}".to_owned()), builder.tag_format);
+
+        builder.tag_prefix("release-".into());
+        assert_eq!(None, builder.tag_format);
+        assert_eq!(Some("release-".to_owned()), builder.tag_prefix);
+    }
+
+    #[test]
+    fn prerelease_labels() {
+        assert!(validate_prerelease_label("rc").is_ok());
+        assert!(validate_prerelease_label("beta-2").is_ok());
+
+        assert!(validate_prerelease_label("").is_err());
+        assert!(validate_prerelease_label("42").is_err());
+        assert!(validate_prerelease_label("rc.1").is_err());
+        assert!(validate_prerelease_label("nightly build").is_err());
+    }
+}
@@ -3,6 +3,9 @@ use std::env::VarError;
 use std::error::Error as StdError;
 use std::io::Error as IoError;
 use hubcaps::Error as HubcapsError;
+use hyper::Error as HyperError;
+use hyper::status::StatusCode;
+use cargo;
 
 use std::fmt;
 use std::convert::From;
@@ -15,6 +18,17 @@ pub enum Error {
     Var(VarError),
     Io(IoError),
     GitHub(HubcapsError),
+    Signing(String),
+    Http(HyperError),
+    Api(String),
+    Hook(String),
+    Publish(String),
+    Remote(String),
+    Repository(String),
+    Manifest(String),
+    Changelog(String),
+    Cargo(String),
+    Release(String),
 }
 
 impl From<GitError> for Error {
@@ -41,6 +55,12 @@ impl From<HubcapsError> for Error {
     }
 }
 
+impl From<HyperError> for Error {
+    fn from(err: HyperError) -> Error {
+        Error::Http(err)
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -48,6 +68,17 @@ impl fmt::Display for Error {
             Var(ref e) => e.fmt(f),
             Io(ref e) => e.fmt(f),
             GitHub(ref e) => e.fmt(f),
+            Signing(ref e) => write!(f, "{}", e),
+            Http(ref e) => e.fmt(f),
+            Api(ref e) => write!(f, "{}", e),
+            Hook(ref e) => write!(f, "{}", e),
+            Publish(ref e) => write!(f, "{}", e),
+            Remote(ref e) => write!(f, "{}", e),
+            Repository(ref e) => write!(f, "{}", e),
+            Manifest(ref e) => write!(f, "{}", e),
+            Changelog(ref e) => write!(f, "{}", e),
+            Cargo(ref e) => write!(f, "{}", e),
+            Release(ref e) => write!(f, "{}", e),
         }
 
     }
@@ -60,6 +91,33 @@ impl StdError for Error {
             Var(ref e) => e.description(),
             Io(ref e) => e.description(),
             GitHub(ref e) => e.description(),
+            Signing(ref e) => e,
+            Http(ref e) => e.description(),
+            Api(ref e) => e,
+            Hook(ref e) => e,
+            Publish(ref e) => e,
+            Remote(ref e) => e,
+            Repository(ref e) => e,
+            Manifest(ref e) => e,
+            Changelog(ref e) => e,
+            Cargo(ref e) => e,
+            Release(ref e) => e,
+        }
+    }
+}
+
+impl Error {
+    /// Whether trying again later might succeed:
+    /// network failures, rate limits and server errors, but not auth or validation errors.
+    pub fn is_transient(&self) -> bool {
+        match *self {
+            Http(_) => true,
+            GitHub(HubcapsError::Http(_)) => true,
+            GitHub(HubcapsError::Fault { code, .. }) => {
+                code == StatusCode::TooManyRequests || code.is_server_error()
+            },
+            Publish(ref output) => cargo::is_transient_failure(output),
+            _ => false,
         }
     }
 }
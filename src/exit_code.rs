@@ -0,0 +1,63 @@
+//! Exit codes of the `semantic-rs` binary, so CI scripts can tell
+//! "nothing to release" apart from a failure, and failures apart from each other.
+
+use error::Error;
+use report::Outcome;
+
+/// A release was done, or would have been done in a dry run.
+pub const SUCCESS: i32 = 0;
+/// Any failure not covered by a more specific code, like a failing hook.
+pub const FAILURE: i32 = 1;
+/// Invalid arguments, configuration or manifest.
+pub const CONFIG: i32 = 2;
+/// Reading the repository, committing, tagging or pushing failed.
+pub const GIT: i32 = 3;
+/// Publishing the crate or creating the release on the git host failed.
+pub const PUBLISH: i32 = 4;
+/// There was nothing to release. Only used with `--no-release-exit-code`, `SUCCESS` otherwise.
+pub const NO_RELEASE: i32 = 10;
+
+pub fn for_error(err: &Error) -> i32 {
+    match *err {
+        Error::Var(_) | Error::Signing(_) | Error::Manifest(_) => CONFIG,
+        Error::Git(_) | Error::Remote(_) | Error::Repository(_) => GIT,
+        Error::GitHub(_) | Error::Http(_) | Error::Api(_) | Error::Publish(_) | Error::Cargo(_) => PUBLISH,
+        Error::Io(_) | Error::Hook(_) | Error::Changelog(_) | Error::Release(_) => FAILURE,
+    }
+}
+
+/// A run without a new version or a package had nothing to do,
+/// e.g. only chores were committed or it ran on another branch.
+pub fn for_outcome(outcome: &Outcome, distinguish_no_release: bool) -> i32 {
+    let nothing_done = outcome.new_version.is_none() && outcome.package.is_none();
+    if nothing_done && distinguish_no_release {
+        NO_RELEASE
+    } else {
+        SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use error::Error;
+    use report::Outcome;
+
+    #[test]
+    fn no_release_is_only_distinct_on_request() {
+        let nothing = Outcome::default();
+        assert_eq!(SUCCESS, for_outcome(&nothing, false));
+        assert_eq!(NO_RELEASE, for_outcome(&nothing, true));
+
+        let released = Outcome { new_version: Some("1.1.0".into()), released: true, ..Outcome::default() };
+        assert_eq!(SUCCESS, for_outcome(&released, true));
+    }
+
+    #[test]
+    fn errors_are_grouped() {
+        assert_eq!(CONFIG, for_error(&Error::Manifest("Reading `Cargo.toml` failed".into())));
+        assert_eq!(GIT, for_error(&Error::Repository("Failed to push git".into())));
+        assert_eq!(PUBLISH, for_error(&Error::Publish("crate already uploaded".into())));
+        assert_eq!(FAILURE, for_error(&Error::Hook("Pre-release hook failed".into())));
+    }
+}
@@ -1,55 +1,68 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use semver::Version;
 use std::env;
-use git2::{self, Repository, Commit, Signature, PushOptions, RemoteCallbacks, Cred};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use git2::{self, Repository, Commit, Signature, PushOptions, RemoteCallbacks, Cred, StatusOptions, ErrorCode};
 
-use commit_analyzer::{self, CommitType};
+use changelog::{self, Entry};
+use commit_analyzer::CommitType;
 use error::Error;
-use config::Config;
-
-pub fn get_signature(repo: &Repository) -> Result<Signature, Error> {
-    let author = {
-        let mut author = env::var("GIT_COMMITTER_NAME").map_err(Error::from);
-
-        if author.is_err() {
-            let config = try!(repo.config());
-            author = config.get_string("user.name").map_err(Error::from);
+use config::{Config, Provider};
+use bitbucket;
+use logger::{self, Verbosity};
+use toml_file;
+use std::time::SystemTime;
+use utils::{iso_date, path_matches, render_template, user_repo_from_url};
+
+/// Explicitly passed committer information takes precedence over the environment,
+/// which in turn takes precedence over the git config.
+pub fn get_signature(repo: &Repository, name: Option<&str>, email: Option<&str>) -> Result<Signature<'static>, Error> {
+    let author = match name {
+        Some(name) => name.to_owned(),
+        None => {
+            let mut author = env::var("GIT_COMMITTER_NAME").map_err(Error::from);
+
+            if author.is_err() {
+                let config = try!(repo.config());
+                author = config.get_string("user.name").map_err(Error::from);
+            }
+            try!(author)
         }
-        try!(author)
     };
 
-    let email = {
-        let mut email = env::var("GIT_COMMITTER_EMAIL").map_err(Error::from);
+    let email = match email {
+        Some(email) => email.to_owned(),
+        None => {
+            let mut email = env::var("GIT_COMMITTER_EMAIL").map_err(Error::from);
 
-        if email.is_err() {
-            let config = try!(repo.config());
-            email = config.get_string("user.email").map_err(Error::from);
+            if email.is_err() {
+                let config = try!(repo.config());
+                email = config.get_string("user.email").map_err(Error::from);
+            }
+            try!(email)
         }
-        try!(email)
     };
 
     Signature::now(&author, &email).map_err(From::from)
 }
 
-fn range_to_head(commit: &str) -> String {
-    format!("{}..HEAD", commit)
-}
-
-fn format_commit(commit: Commit) -> String {
-    format!("{}\n{}", commit.id(), commit.message().unwrap_or(""))
-}
-
-fn add<P: AsRef<Path>>(repo: &Repository, files: &[P]) -> Result<(), git2::Error> {
+/// Stages exactly `files` on top of HEAD and returns the resulting tree.
+/// Anything else that was staged before is unstaged again, but stays in the working tree.
+fn stage_only<P: AsRef<Path>>(repo: &Repository, files: &[P]) -> Result<git2::Oid, git2::Error> {
+    let head = try!(repo.head().and_then(|head| head.peel_to_tree()));
     let mut index = try!(repo.index());
+    try!(index.read_tree(&head));
 
     for path in files {
         try!(index.add_path(path.as_ref()));
     }
 
-    index.write()
+    try!(index.write());
+    index.write_tree()
 }
 
-fn commit(config: &Config, message: &str) -> Result<(), git2::Error> {
+fn commit(config: &Config, files: &[PathBuf], message: &str) -> Result<(), Error> {
     let update_ref = format!("refs/heads/{}", config.branch);
     let repo = &config.repository;
 
@@ -57,13 +70,75 @@ fn commit(config: &Config, message: &str) -> Result<(), git2::Error> {
     let parent_commit = try!(repo.find_commit(oid));
     let parents = vec![&parent_commit];
 
-    let mut index = try!(repo.index());
-    let tree_oid = try!(index.write_tree());
+    let tree_oid = try!(stage_only(repo, files));
     let tree = try!(repo.find_tree(tree_oid));
 
-    repo
-        .commit(Some(&update_ref), &config.signature, &config.signature, message, &tree, &parents)
-        .map(|_| ())
+    if !config.sign_commits {
+        return repo
+            .commit(Some(&update_ref), &config.signature, &config.signature, message, &tree, &parents)
+            .map(|_| ())
+            .map_err(Error::from);
+    }
+
+    let key = match signing_key(repo) {
+        Some(key) => key,
+        None => return Err(Error::Signing("No signing key configured (git config user.signingkey)".into())),
+    };
+
+    let content = commit_content(&config.signature, message, &tree, &parents);
+    let signature = try!(gpg_sign(&key, &content));
+
+    let oid = try!(repo.commit_signed(&content, &signature, Some("gpgsig")));
+    try!(repo.reference(&update_ref, oid, true, message));
+    Ok(())
+}
+
+/// The raw commit object git would write, which is what gets signed.
+/// git2 0.7 can't create it itself.
+fn commit_content(signature: &git2::Signature, message: &str, tree: &git2::Tree, parents: &[&Commit]) -> String {
+    let mut content = format!("tree {}\n", tree.id());
+    for parent in parents {
+        content.push_str(&format!("parent {}\n", parent.id()));
+    }
+
+    let when = signature.when();
+    let offset = when.offset_minutes();
+    let sign = if offset < 0 { '-' } else { '+' };
+    let person = format!("{} <{}> {} {}{:02}{:02}",
+                         signature.name().unwrap_or(""), signature.email().unwrap_or(""),
+                         when.seconds(), sign, offset.abs() / 60, offset.abs() % 60);
+    content.push_str(&format!("author {}\ncommitter {}\n\n{}", person, person, message));
+    content
+}
+
+/// Creates an ASCII-armored detached signature of `content` using gpg.
+fn gpg_sign(key: &str, content: &str) -> Result<String, Error> {
+    let child = Command::new("gpg")
+        .arg("--local-user").arg(key)
+        .arg("--detach-sign")
+        .arg("--armor")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) => return Err(Error::Signing(format!("Could not run gpg, is it installed? {}", err))),
+    };
+
+    if let Some(stdin) = child.stdin.as_mut() {
+        try!(stdin.write_all(content.as_bytes()));
+    }
+
+    let output = try!(child.wait_with_output());
+    if !output.status.success() {
+        return Err(Error::Signing(format!("gpg failed to sign the release commit: {}",
+                                          String::from_utf8_lossy(&output.stderr).trim())));
+    }
+
+    String::from_utf8(output.stdout)
+        .map_err(|_| Error::Signing("gpg produced an invalid signature".into()))
 }
 
 fn create_tag(config: &Config, tag_name: &str, message: &str) -> Result<(), git2::Error> {
@@ -76,6 +151,155 @@ fn create_tag(config: &Config, tag_name: &str, message: &str) -> Result<(), git2
         .map(|_| ())
 }
 
+fn create_lightweight_tag(config: &Config, tag_name: &str) -> Result<(), git2::Error> {
+    let repo = &config.repository;
+
+    let rev = format!("refs/heads/{}", config.branch);
+    let obj = try!(repo.revparse_single(&rev));
+
+    repo.tag_lightweight(tag_name, &obj, false)
+        .map(|_| ())
+}
+
+/// The key git would use to sign tags and commits, if one is configured.
+pub fn signing_key(repo: &Repository) -> Option<String> {
+    repo.config()
+        .and_then(|config| config.get_string("user.signingkey"))
+        .ok()
+}
+
+/// git2 can't sign tags itself, so we let git and gpg do the work.
+fn create_signed_tag(config: &Config, tag_name: &str, message: &str) -> Result<(), Error> {
+    let key = match signing_key(&config.repository) {
+        Some(key) => key,
+        None => return Err(Error::Signing("No signing key configured (git config user.signingkey)".into())),
+    };
+
+    let rev = format!("refs/heads/{}", config.branch);
+    let status = Command::new("git")
+        .current_dir(&config.repository_path)
+        .env("GIT_COMMITTER_NAME", config.signature.name().unwrap_or(""))
+        .env("GIT_COMMITTER_EMAIL", config.signature.email().unwrap_or(""))
+        .arg("tag")
+        .arg("--local-user").arg(&key)
+        .arg("--message").arg(message)
+        .arg(tag_name)
+        .arg(&rev)
+        .status();
+
+    match status {
+        Ok(ref status) if status.success() => Ok(()),
+        Ok(status) => Err(Error::Signing(format!("`git tag --local-user {}` failed with {}", key, status))),
+        Err(err) => Err(Error::Signing(format!("Could not run git to sign the tag: {}", err))),
+    }
+}
+
+/// Tracked files with staged or unstaged changes.
+/// Untracked and ignored files don't end up in the release commit, so they are not reported.
+pub fn dirty_files(repo: &Repository) -> Result<Vec<String>, Error> {
+    let mut options = StatusOptions::new();
+    options.include_untracked(false)
+        .include_ignored(false)
+        .exclude_submodules(true);
+
+    let statuses = try!(repo.statuses(Some(&mut options)));
+    Ok(statuses.iter()
+       .filter_map(|entry| entry.path().map(|path| path.to_owned()))
+       .collect())
+}
+
+/// The full message of the commit HEAD points to.
+pub fn head_message(repo: &Repository) -> Result<String, Error> {
+    let head = try!(repo.head().and_then(|head| head.peel_to_commit()));
+    Ok(head.message().unwrap_or("").to_owned())
+}
+
+/// How the history of HEAD relates to a release tag.
+#[derive(Debug, PartialEq)]
+pub enum History {
+    /// HEAD descends from the tag.
+    Linear,
+    /// The tag is not in HEAD's history, they split off at this commit.
+    Diverged(String),
+    /// HEAD and the tag have no commit in common.
+    Unrelated,
+}
+
+/// Checks whether HEAD descends from `tag_name`, e.g. rewritten history doesn't.
+pub fn history_since(repo: &Repository, tag_name: &str) -> Result<History, Error> {
+    let tag_commit = try!(repo.revparse_single(tag_name).and_then(|tag| tag.peel(git2::ObjectType::Commit))).id();
+    let head = try!(repo.head().and_then(|head| head.peel_to_commit())).id();
+
+    match repo.merge_base(tag_commit, head) {
+        Ok(base) if base == tag_commit => Ok(History::Linear),
+        Ok(base) => Ok(History::Diverged(base.to_string())),
+        Err(ref err) if err.code() == ErrorCode::NotFound => Ok(History::Unrelated),
+        Err(err) => Err(Error::from(err)),
+    }
+}
+
+/// The message of an annotated tag, `None` for a lightweight tag.
+pub fn tag_message(repo: &Repository, tag_name: &str) -> Result<Option<String>, Error> {
+    let oid = try!(repo.refname_to_id(&format!("refs/tags/{}", tag_name)));
+    match repo.find_tag(oid) {
+        Ok(tag) => Ok(tag.message().map(|message| message.to_owned())),
+        Err(_) => Ok(None),
+    }
+}
+
+pub fn tag_exists(repo: &Repository, tag_name: &str) -> bool {
+    repo.refname_to_id(&format!("refs/tags/{}", tag_name)).is_ok()
+}
+
+/// Whether `revision`, a tag name or commit id, resolves to a commit.
+pub fn commit_exists(repo: &Repository, revision: &str) -> bool {
+    repo.revparse_single(revision)
+        .and_then(|object| object.peel(git2::ObjectType::Commit))
+        .is_ok()
+}
+
+/// The abbreviated id of the commit HEAD points to.
+pub fn head_short_id(repo: &Repository) -> Result<String, Error> {
+    let head = try!(repo.head().and_then(|head| head.peel_to_commit()));
+    Ok(head.id().to_string()[..7].to_owned())
+}
+
+/// The branch the remote called `name` points its HEAD to, like `main`.
+/// Only known locally if the repository was cloned or `git remote set-head` was run.
+pub fn default_branch(repo: &Repository, name: &str) -> Option<String> {
+    let remote_head = format!("refs/remotes/{}/HEAD", name);
+    let prefix = format!("refs/remotes/{}/", name);
+
+    let reference = match repo.find_reference(&remote_head) {
+        Ok(reference) => reference,
+        Err(_) => return None,
+    };
+    match reference.symbolic_target() {
+        Some(target) if target.starts_with(&prefix[..]) => Some(target[prefix.len()..].to_owned()),
+        _ => None,
+    }
+}
+
+/// The URL of the remote called `name`.
+pub fn remote_url(repo: &Repository, name: &str) -> Result<String, Error> {
+    let remote = match repo.find_remote(name) {
+        Ok(remote) => remote,
+        Err(_) => return Err(Error::Remote(format!("The {} remote is not set, add it with `git remote add {} <url>`", name, name))),
+    };
+
+    match remote.url() {
+        Some(url) => Ok(url.to_owned()),
+        None => Err(Error::Remote(format!("The URL of the {} remote is not valid UTF-8", name))),
+    }
+}
+
+/// User and repository name, taken from the URL of the remote called `name`.
+pub fn remote_user_and_repo(repo: &Repository, name: &str) -> Result<(String, String), Error> {
+    let url = try!(remote_url(repo, name));
+    user_repo_from_url(&url)
+        .map_err(|e| Error::Remote(format!("Couldn't determine user and repository of the {} remote: {}", name, e)))
+}
+
 fn is_https_remote(maybe_remote: Option<&str>) -> bool {
     if let Some(remote) = maybe_remote {
         remote.starts_with("https://")
@@ -84,58 +308,156 @@ fn is_https_remote(maybe_remote: Option<&str>) -> bool {
     }
 }
 
-pub fn latest_tag(repo: &Repository) -> Option<Version> {
+/// The highest version among all tags named `<tag_prefix><semver>`.
+pub fn latest_version_tag(repo: &Repository, tag_prefix: &str) -> Option<Version> {
+    version_tags(repo, tag_prefix).pop()
+}
+
+/// The versions of all tags named `<tag_prefix><semver>`, lowest first.
+pub fn version_tags(repo: &Repository, tag_prefix: &str) -> Vec<Version> {
     let tags = match repo.tag_names(None) {
         Ok(tags) => tags,
-        Err(_) => return None
+        Err(_) => return vec![]
     };
 
-    tags.iter()
+    let mut versions = tags.iter()
         .map(|tag| tag.unwrap())
-        .filter_map(|tag| Version::parse(&tag[1..]).ok())
-        .max()
+        .filter(|tag| tag.starts_with(tag_prefix))
+        .filter_map(|tag| Version::parse(&tag[tag_prefix.len()..]).ok())
+        .collect::<Vec<_>>();
+    versions.sort();
+    versions
 }
 
-pub fn version_bump_since_latest(repo: &Repository) -> CommitType {
-    match latest_tag(repo) {
-        Some(t) => {
-            let tag = format!("v{}", t.to_string());
-            version_bump_since_tag(repo, &tag)
-        },
-        None => CommitType::Major
+/// The commits since `since_tag`, or the whole history without one.
+/// They are read and analyzed once, so the bump and the changelog don't walk the history again.
+pub fn changelog_entries(config: &Config, since_tag: Option<&str>) -> Result<Vec<Entry>, Error> {
+    match since_tag {
+        Some(tag) => logger::debug(format!("Analyzing commits since tag {}", tag)),
+        None => logger::debug("No release tag found, analyzing all commits"),
     }
+
+    changelog::entries_since(&config.repository_path, since_tag.unwrap_or(""), &config.analyzer_options(), &config.walk_options())
+        .map_err(Error::Repository)
 }
 
-pub fn version_bump_since_tag(repo: &Repository, tag: &str) -> CommitType {
-    let tag = range_to_head(tag);
+/// The files `commit` changed compared to its first parent, or all files of a root commit.
+/// Renames show up with both paths, as a deletion and an addition.
+pub fn changed_paths(repo: &Repository, commit: &Commit) -> Result<Vec<String>, git2::Error> {
+    let tree = try!(commit.tree());
+    let parent_tree = match commit.parents().next() {
+        Some(parent) => Some(try!(parent.tree())),
+        None => None,
+    };
+    let diff = try!(repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None));
+
+    let mut paths: Vec<String> = vec![];
+    for delta in diff.deltas() {
+        for file in &[delta.old_file(), delta.new_file()] {
+            if let Some(path) = file.path().and_then(|path| path.to_str()) {
+                if !paths.iter().any(|p| p == path) {
+                    paths.push(path.to_owned());
+                }
+            }
+        }
+    }
+    Ok(paths)
+}
 
-    let mut walker = repo.revwalk().expect("Creating a revwalk failed");
-    walker.push_range(&tag).expect("Adding a range failed");
+/// Whether all `paths` match one of `patterns`. A commit without changes touches nothing excluded.
+pub fn only_touches(paths: &[String], patterns: &[String]) -> bool {
+    !paths.is_empty() && paths.iter().all(|path| patterns.iter().any(|pattern| path_matches(pattern, path)))
+}
 
-    walker.map(|c| repo.find_commit(c.expect("Not a valid commit")).expect("No commit found"))
-        .map(format_commit)
-        .map(|c| commit_analyzer::analyze_single(&c).expect("Analyzing commit failed"))
-        .max().unwrap_or(CommitType::Unknown)
+/// Whether any of `paths` matches one of `patterns`.
+pub fn touches_any(paths: &[String], patterns: &[String]) -> bool {
+    paths.iter().any(|path| patterns.iter().any(|pattern| path_matches(pattern, path)))
 }
 
-pub fn generate_commit_message(new_version: &str) -> String {
-    format!("Bump version to {}", new_version).into()
+/// Returns the bump the entries ask for. In verbose mode each commit's classification is logged.
+pub fn analyze_entries(entries: &[Entry]) -> CommitType {
+    if logger::verbosity() == Verbosity::Verbose {
+        for entry in entries {
+            logger::debug(format!("{:.7} {:?}: {}", entry.id, entry.commit.commit_type, entry.commit.description));
+        }
+    }
+
+    CommitType::max_of(entries.iter().map(|entry| entry.commit.commit_type))
 }
 
-pub fn commit_files(config: &Config, new_version: &str) -> Result<(), Error> {
+pub fn generate_commit_message(config: &Config, new_version: &str) -> String {
+    let tag_name = config.tag_name(new_version);
+    render_template(&config.commit_message_template,
+                    &[("version", new_version), ("tag", &tag_name)])
+}
+
+/// The message of the annotated tag for `new_version`. With the default template it's just the changelog.
+pub fn generate_tag_message(config: &Config, new_version: &str, changelog: &str) -> String {
+    let tag_name = config.tag_name(new_version);
+    render_template(&config.tag_message_template,
+                    &[("version", new_version), ("tag", &tag_name),
+                      ("date", &iso_date(SystemTime::now())), ("changelog", changelog)])
+}
+
+/// The files a release modifies: the manifests, the lockfile cargo updates when packaging,
+/// and the changelog. Files that don't exist or are ignored are left out.
+pub fn release_files(config: &Config) -> Result<Vec<PathBuf>, Error> {
     let repo = &config.repository;
-    let files = ["Cargo.toml", "Cargo.lock", "Changelog.md"];
-    let files = files.iter().filter(|filename| {
-        let path = Path::new(filename);
-        !repo.status_should_ignore(path).expect("Determining ignore status of file failed")
-    }).collect::<Vec<_>>();
+    let mut files = vec![];
+    match config.package_dir {
+        Some(ref dir) => files.push(dir.join("Cargo.toml")),
+        None => {
+            files.push(PathBuf::from("Cargo.toml"));
+            if let Ok(manifests) = toml_file::workspace_manifests(&config.repository_path) {
+                files.extend(manifests);
+            }
+        }
+    }
+    if let Some(ref version_file) = config.version_file {
+        files.push(PathBuf::from(version_file));
+    }
+    for &(ref file, _) in &config.version_replacements {
+        files.push(PathBuf::from(file));
+    }
+    if config.release_mode {
+        files.push(PathBuf::from("Cargo.lock"));
+    }
+    if config.generate_changelog {
+        files.push(PathBuf::from(&config.changelog_path));
+    }
 
-    try!(add(&config.repository, &files[..]));
+    let mut release_files = vec![];
+    for path in files {
+        if Path::new(&config.repository_path).join(&path).exists() && !try!(repo.status_should_ignore(&path)) {
+            release_files.push(path);
+        }
+    }
+    Ok(release_files)
+}
+
+pub fn commit_files(config: &Config, new_version: &str) -> Result<(), Error> {
+    let files = try!(release_files(config));
+    for file in &files {
+        logger::debug(format!("Committing {}", file.display()));
+    }
 
-    commit(config, &generate_commit_message(new_version)).map_err(Error::from)
+    commit(config, &files, &generate_commit_message(config, new_version))
 }
 
-pub fn tag(config: &Config, tag_name: &str, tag_message: &str) -> Result<(), Error> {
+/// Tags `new_version`. Annotated tags get the message rendered from `Config::tag_message_template`,
+/// lightweight tags have no message.
+pub fn tag(config: &Config, new_version: &str, changelog: &str) -> Result<(), Error> {
+    let tag_name = config.tag_name(new_version);
+    if !config.tag_annotated {
+        return create_lightweight_tag(config, &tag_name)
+            .map_err(Error::from);
+    }
+
+    let tag_message = generate_tag_message(config, new_version, changelog);
+    if config.sign_tags {
+        return create_signed_tag(config, &tag_name, &tag_message);
+    }
+
     create_tag(config, &tag_name, &tag_message)
         .map_err(Error::from)
 }
@@ -144,20 +466,35 @@ pub fn push(config: &Config, tag_name: &str) -> Result<(), Error> {
     let repo      = &config.repository;
 
     let branch    = &config.branch;
-    let token     = config.gh_token.as_ref();
+    let credentials = match config.provider {
+        Provider::Bitbucket => config.bitbucket_token.as_ref().map(|token| bitbucket::push_credentials(token)),
+        // GitLab takes access tokens as the password of the `oauth2` user
+        Provider::GitLab => config.gitlab_token.as_ref().map(|token| ("oauth2".to_owned(), token.clone())),
+        Provider::None => None,
+        _ => config.gh_token.as_ref().map(|token| (token.clone(), String::new())),
+    };
+    let git_config = try!(repo.config());
 
     // We need to push both the branch we just committed as well as the tag we created.
     let branch_ref = format!("refs/heads/{}", branch);
     let tag_ref    = format!("refs/tags/{}", tag_name);
     let refs = [&branch_ref[..], &tag_ref[..]];
 
-    let mut remote = try!(repo.find_remote("origin"));
+    let remote_name = match config.remote {
+        Ok(ref name) => name,
+        Err(ref err) => return Err(Error::from(git2::Error::from_str(err))),
+    };
+    let mut remote = try!(repo.find_remote(remote_name));
     let mut cbs = RemoteCallbacks::new();
     let mut opts = PushOptions::new();
 
     if is_https_remote(remote.url()) {
-        cbs.credentials(|_url, _username, _allowed| {
-            Cred::userpass_plaintext(&token.unwrap(), "")
+        cbs.credentials(|url, username, _allowed| {
+            match credentials {
+                Some((ref user, ref password)) => Cred::userpass_plaintext(user, password),
+                // Without a token, whatever git itself would use
+                None => Cred::credential_helper(&git_config, url, username),
+            }
         });
         opts.remote_callbacks(cbs);
     } else {
@@ -1,15 +1,30 @@
-use hyper::Client;
-use hyper::net::HttpsConnector;
-use hyper_native_tls::NativeTlsClient;
+use hyper::header::{Authorization, ContentType, UserAgent};
+use hyper::mime::Mime;
+use hyper::status::StatusCode;
 use hubcaps::{Github, Credentials};
 use hubcaps::releases::ReleaseOptions;
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
 use error::Error;
 use super::USERAGENT;
 use config::Config;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use url::Url;
+use utils::https_client;
+
+const TAG_POLL_INTERVAL_MS: u64 = 500;
 
 pub fn can_release(config: &Config) -> bool {
     let repo = &config.repository;
-    match repo.find_remote("origin") {
+    let remote_name = match config.remote {
+        Ok(ref name) => name,
+        Err(_) => return false
+    };
+    match repo.find_remote(remote_name) {
         Ok(remote) => {
             let url = match remote.url() {
                 Some(u) => u,
@@ -25,17 +40,19 @@ pub fn is_github_url(url: &str) -> bool {
     url.contains("github.com")
 }
 
-pub fn release(config: &Config, tag_name: &str, tag_message: &str) -> Result<(), Error> {
+/// Creates the release and returns its id.
+pub fn release(config: &Config, tag_name: &str, tag_message: &str, prerelease: bool) -> Result<u64, Error> {
     let user      = &config.user.as_ref().unwrap()[..];
     let repo_name = &config.repository_name.as_ref().unwrap()[..];
     let branch    = &config.branch[..];
     let token     = config.gh_token.as_ref().unwrap();
 
-    let client = Client::with_connector(
-        HttpsConnector::new(
-            NativeTlsClient::new().unwrap()
-        )
-    );
+    if config.github_generate_notes {
+        let payload = release_payload(tag_name, tag_message, branch, config.github_draft, prerelease);
+        return create_release(user, repo_name, token, &payload);
+    }
+
+    let client = https_client();
     let credentials = Credentials::Token(token.to_owned());
     let github = Github::new(USERAGENT, client, credentials);
 
@@ -43,8 +60,8 @@ pub fn release(config: &Config, tag_name: &str, tag_message: &str) -> Result<(),
         .name(tag_name)
         .body(tag_message)
         .commitish(branch)
-        .draft(false)
-        .prerelease(false)
+        .draft(config.github_draft)
+        .prerelease(prerelease)
         .build();
 
     let repo = github.repo(user, repo_name);
@@ -52,6 +69,221 @@ pub fn release(config: &Config, tag_name: &str, tag_message: &str) -> Result<(),
 
     release
         .create(&opts)
-        .map(|_| ())
+        .map(|release| release.id)
         .map_err(Error::from)
 }
+
+/// The request creating a release with generated notes, which `ReleaseOptions` can't express.
+/// GitHub puts `body` above the notes it generates.
+pub fn release_payload(tag_name: &str, body: &str, branch: &str, draft: bool, prerelease: bool) -> Json {
+    let mut payload = BTreeMap::new();
+    payload.insert("tag_name".to_owned(), Json::String(tag_name.to_owned()));
+    payload.insert("name".to_owned(), Json::String(tag_name.to_owned()));
+    payload.insert("body".to_owned(), Json::String(body.to_owned()));
+    payload.insert("target_commitish".to_owned(), Json::String(branch.to_owned()));
+    payload.insert("draft".to_owned(), Json::Boolean(draft));
+    payload.insert("prerelease".to_owned(), Json::Boolean(prerelease));
+    payload.insert("generate_release_notes".to_owned(), Json::Boolean(true));
+    Json::Object(payload)
+}
+
+fn create_release(user: &str, repo_name: &str, token: &str, payload: &Json) -> Result<u64, Error> {
+    let url = format!("https://api.github.com/repos/{}/{}/releases", user, repo_name);
+    let body = payload.to_string();
+
+    let client = https_client();
+    let mut response = try!(client.post(&url)
+        .header(Authorization(format!("token {}", token)))
+        .header(UserAgent(USERAGENT.to_owned()))
+        .header(ContentType::json())
+        .body(&body[..])
+        .send());
+
+    let mut content = String::new();
+    try!(response.read_to_string(&mut content));
+    if !response.status.is_success() {
+        return Err(Error::Api(format!("Creating the release failed with status {}: {}", response.status, content)));
+    }
+
+    Json::from_str(&content).ok()
+        .and_then(|release| release.find("id").and_then(|id| id.as_u64()))
+        .ok_or(Error::Api("GitHub's response doesn't contain the release id".into()))
+}
+
+/// Whether the tag was pushed to the repository on GitHub.
+pub fn tag_exists(config: &Config, tag_name: &str) -> Result<bool, Error> {
+    let user      = &config.user.as_ref().unwrap()[..];
+    let repo_name = &config.repository_name.as_ref().unwrap()[..];
+    let token     = config.gh_token.as_ref().unwrap();
+
+    let url = format!("https://api.github.com/repos/{}/{}/git/ref/tags/{}", user, repo_name, tag_name);
+    let client = https_client();
+    let response = try!(client.get(&url)
+        .header(Authorization(format!("token {}", token)))
+        .header(UserAgent(USERAGENT.to_owned()))
+        .send());
+
+    match response.status {
+        StatusCode::Ok => Ok(true),
+        StatusCode::NotFound => Ok(false),
+        status => Err(Error::Api(format!("Looking up the tag {} failed with status {}", tag_name, status))),
+    }
+}
+
+/// Whether the scopes GitHub lists in `X-OAuth-Scopes` allow creating a release.
+/// `public_repo` is only enough for public repositories.
+pub fn scopes_allow_release(scopes: &str, private: bool) -> bool {
+    scopes.split(',')
+        .map(|scope| scope.trim())
+        .any(|scope| scope == "repo" || (!private && scope == "public_repo"))
+}
+
+/// Makes sure the token can create releases in the repository, before anything is tagged or pushed.
+pub fn check_token(config: &Config) -> Result<(), Error> {
+    let user      = &config.user.as_ref().unwrap()[..];
+    let repo_name = &config.repository_name.as_ref().unwrap()[..];
+    let token     = config.gh_token.as_ref().unwrap();
+
+    let url = format!("https://api.github.com/repos/{}/{}", user, repo_name);
+    let client = https_client();
+    let mut response = try!(client.get(&url)
+        .header(Authorization(format!("token {}", token)))
+        .header(UserAgent(USERAGENT.to_owned()))
+        .send());
+
+    match response.status {
+        StatusCode::Ok => {},
+        StatusCode::Unauthorized => return Err(Error::Api("GitHub rejected the token, check GH_TOKEN".into())),
+        StatusCode::NotFound | StatusCode::Forbidden => {
+            return Err(Error::Api(format!("The token has no access to {}/{}", user, repo_name)));
+        },
+        status => return Err(Error::Api(format!("Verifying the GitHub token failed with status {}", status))),
+    }
+
+    let mut body = String::new();
+    try!(response.read_to_string(&mut body));
+    let repository = try!(Json::from_str(&body)
+        .map_err(|err| Error::Api(format!("GitHub sent an invalid response: {}", err))));
+    let private = repository.find("private").and_then(|private| private.as_boolean()).unwrap_or(true);
+    let can_push = repository.find_path(&["permissions", "push"]).and_then(|push| push.as_boolean()).unwrap_or(false);
+
+    // Fine-grained tokens don't list their scopes
+    let scopes = response.headers.get_raw("X-OAuth-Scopes")
+        .and_then(|values| values.first())
+        .map(|value| String::from_utf8_lossy(value).into_owned());
+    if let Some(scopes) = scopes {
+        if !scopes_allow_release(&scopes, private) {
+            let needed = if private { "repo" } else { "public_repo" };
+            let granted = if scopes.trim().is_empty() { "none" } else { scopes.trim() };
+            return Err(Error::Api(format!("The token is missing the `{}` scope to create releases (granted: {})", needed, granted)));
+        }
+    }
+
+    if !can_push {
+        return Err(Error::Api(format!("The token can't push to {}/{}, which creating a release requires", user, repo_name)));
+    }
+    Ok(())
+}
+
+/// Polls GitHub until a freshly pushed tag is visible, as releases can't be created for unknown tags.
+/// Returns `false` if it didn't show up within `timeout`.
+pub fn wait_for_tag(config: &Config, tag_name: &str, timeout: Duration) -> Result<bool, Error> {
+    let started = Instant::now();
+
+    loop {
+        if try!(tag_exists(config, tag_name)) {
+            return Ok(true);
+        }
+
+        if started.elapsed() >= timeout {
+            return Ok(false);
+        }
+        thread::sleep(Duration::from_millis(TAG_POLL_INTERVAL_MS));
+    }
+}
+
+/// Guesses the content type of a release asset from its file extension.
+pub fn content_type(path: &Path) -> &'static str {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") || name.ends_with(".gz") {
+        "application/gzip"
+    } else if name.ends_with(".zip") {
+        "application/zip"
+    } else if name.ends_with(".tar") {
+        "application/x-tar"
+    } else if name.ends_with(".txt") || name.ends_with(".md") {
+        "text/plain"
+    } else if name.ends_with(".json") {
+        "application/json"
+    } else if name.ends_with(".deb") {
+        "application/vnd.debian.binary-package"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// Attaches the file at `path` to an existing release.
+pub fn upload_asset(config: &Config, release_id: u64, path: &str, content_type: &str) -> Result<(), Error> {
+    let user      = &config.user.as_ref().unwrap()[..];
+    let repo_name = &config.repository_name.as_ref().unwrap()[..];
+    let token     = config.gh_token.as_ref().unwrap();
+
+    let name = match Path::new(path).file_name().and_then(|name| name.to_str()) {
+        Some(name) => name.to_owned(),
+        None => return Err(Error::Api(format!("'{}' is not a valid asset file name", path))),
+    };
+
+    let mut content = vec![];
+    let mut file = try!(File::open(path));
+    try!(file.read_to_end(&mut content));
+
+    let base = format!("https://uploads.github.com/repos/{}/{}/releases/{}/assets", user, repo_name, release_id);
+    let url = try!(Url::parse_with_params(&base, &[("name", &name[..])])
+                   .map_err(|e| Error::Api(format!("Invalid upload URL: {}", e))));
+    let mime = try!(content_type.parse::<Mime>()
+                    .map_err(|_| Error::Api(format!("Invalid content type '{}'", content_type))));
+
+    let client = https_client();
+    let response = try!(client.post(url.as_str())
+        .header(Authorization(format!("token {}", token)))
+        .header(UserAgent(USERAGENT.to_owned()))
+        .header(ContentType(mime))
+        .body(&content[..])
+        .send());
+
+    if response.status.is_success() {
+        Ok(())
+    } else {
+        Err(Error::Api(format!("Uploading {} failed with status {}", name, response.status)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn generated_notes_payload() {
+        let payload = release_payload("v1.2.0", "* Fix a crash", "master", false, true);
+        assert_eq!(r#"{"body":"* Fix a crash","draft":false,"generate_release_notes":true,"name":"v1.2.0","prerelease":true,"tag_name":"v1.2.0","target_commitish":"master"}"#,
+                   payload.to_string());
+    }
+
+    #[test]
+    fn release_scopes() {
+        assert!(scopes_allow_release("gist, repo, workflow", true));
+        assert!(scopes_allow_release("public_repo", false));
+        assert!(!scopes_allow_release("public_repo", true));
+        assert!(!scopes_allow_release("read:org, gist", false));
+        assert!(!scopes_allow_release("", false));
+    }
+
+    #[test]
+    fn guesses_content_types() {
+        assert_eq!("application/gzip", content_type(Path::new("target/semantic-rs-1.0.0.tar.gz")));
+        assert_eq!("application/zip", content_type(Path::new("semantic-rs.zip")));
+        assert_eq!("application/octet-stream", content_type(Path::new("target/release/semantic-rs")));
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use hyper::header::{ContentType, Headers, UserAgent};
+use rustc_serialize::json::Json;
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+use error::Error;
+use super::USERAGENT;
+use config::Config;
+use utils::{https_client, host_and_path_from_url};
+
+pub fn can_release(config: &Config) -> bool {
+    let repo = &config.repository;
+    let remote_name = match config.remote {
+        Ok(ref name) => name,
+        Err(_) => return false
+    };
+    match repo.find_remote(remote_name) {
+        Ok(remote) => {
+            let url = match remote.url() {
+                Some(u) => u,
+                None => return false
+            };
+            host_and_path_from_url(url).is_ok()
+        },
+        Err(_) => false
+    }
+}
+
+/// Matches gitlab.com as well as self-hosted instances on a `gitlab.` host.
+/// Other self-hosted instances need `--provider gitlab`.
+pub fn is_gitlab_url(url: &str) -> bool {
+    url.contains("gitlab.")
+}
+
+pub fn release(config: &Config, tag_name: &str, tag_message: &str) -> Result<(), Error> {
+    let token = config.gitlab_token.as_ref().unwrap();
+
+    let remote = try!(config.repository.find_remote(config.remote.as_ref().unwrap()));
+    let url = match remote.url() {
+        Some(url) => url,
+        None => return Err(Error::Api("Remote URL is not valid UTF-8".into())),
+    };
+    let (host, project) = try!(host_and_path_from_url(url).map_err(Error::Api));
+
+    let api_url = format!("https://{}/api/v4/projects/{}/releases",
+                          host, utf8_percent_encode(&project, PATH_SEGMENT_ENCODE_SET));
+
+    let mut body = BTreeMap::new();
+    body.insert("tag_name".to_owned(), Json::String(tag_name.to_owned()));
+    body.insert("name".to_owned(), Json::String(tag_name.to_owned()));
+    body.insert("description".to_owned(), Json::String(tag_message.to_owned()));
+    let body = Json::Object(body).to_string();
+
+    let mut headers = Headers::new();
+    headers.set(ContentType::json());
+    headers.set(UserAgent(USERAGENT.to_owned()));
+    headers.set_raw("PRIVATE-TOKEN", vec![token.clone().into_bytes()]);
+
+    let client = https_client();
+    let mut response = try!(client.post(&api_url)
+        .headers(headers)
+        .body(&body[..])
+        .send());
+
+    if response.status.is_success() {
+        Ok(())
+    } else {
+        let mut message = String::new();
+        let _ = response.read_to_string(&mut message);
+        Err(Error::Api(format!("GitLab responded with {}: {}", response.status, message)))
+    }
+}
@@ -0,0 +1,34 @@
+use std::process::Command;
+use error::Error;
+
+/// Runs each hook as a shell command inside the repository, in order.
+/// The new version is exposed as `SEMANTIC_RS_VERSION`.
+/// Stops at the first hook that fails.
+pub fn run(hooks: &[String], repository_path: &str, new_version: &str) -> Result<(), Error> {
+    for hook in hooks {
+        let status = try!(shell(hook)
+            .current_dir(repository_path)
+            .env("SEMANTIC_RS_VERSION", new_version)
+            .status());
+
+        if !status.success() {
+            return Err(Error::Hook(format!("`{}` failed with {}", hook, status)));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
@@ -0,0 +1,50 @@
+#![cfg_attr(feature = "dev", allow(unstable_features))]
+#![cfg_attr(feature = "dev", feature(plugin))]
+#![cfg_attr(feature = "dev", plugin(clippy))]
+
+//! semantic-rs releases crates based on their commit history.
+//!
+//! Build a `Config` with a `ConfigBuilder` and pass it to `run`,
+//! which reports what happened as an `Outcome`.
+
+extern crate rustc_serialize;
+extern crate toml;
+extern crate regex;
+extern crate semver;
+extern crate git2;
+extern crate hyper;
+extern crate hubcaps;
+extern crate url;
+extern crate travis_after_all;
+extern crate hyper_native_tls;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+
+pub mod logger;
+pub mod toml_file;
+pub mod git;
+pub mod changelog;
+pub mod commit_analyzer;
+pub mod cargo;
+pub mod error;
+pub mod github;
+pub mod config;
+pub mod utils;
+pub mod gitlab;
+pub mod bitbucket;
+pub mod notify;
+pub mod report;
+pub mod manifest;
+pub mod exit_code;
+pub mod ci;
+mod preflight;
+mod hooks;
+mod retry;
+mod release;
+
+pub use config::{Config, ConfigBuilder};
+pub use error::Error;
+pub use release::run;
+pub use report::Outcome;
+
+pub const USERAGENT: &'static str = concat!("semantic-rs/", env!("CARGO_PKG_VERSION"));
@@ -1,13 +1,106 @@
 use std::convert::AsRef;
 extern crate term;
+#[cfg(unix)]
+extern crate libc;
+use std::io;
 use std::io::prelude::*;
+use std::sync::{Arc, Mutex, Once, ONCE_INIT};
+use std::sync::atomic::{AtomicBool, AtomicUsize, ATOMIC_BOOL_INIT, ATOMIC_USIZE_INIT, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// When set, all messages go to stderr so stdout only carries the final result.
+static STRUCTURED_OUTPUT: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// The current `Verbosity`, the initial 0 means `Normal`.
+static VERBOSITY: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Shown instead of secrets, like tokens.
+pub const REDACTED: &'static str = "<redacted>";
+
+/// Values masked in every message, see `add_secret`. Set up on first use.
+static SECRETS_INIT: Once = ONCE_INIT;
+static mut SECRETS: *const Mutex<Vec<String>> = 0 as *const Mutex<Vec<String>>;
+
+const SPINNER_FRAMES: &'static [char] = &['|', '/', '-', '\\'];
+const SPINNER_INTERVAL_MS: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    /// Only warnings and errors
+    Quiet,
+    /// Progress messages, warnings and errors
+    Normal,
+    /// Everything, including debug details
+    Verbose,
+}
 
 pub enum MessageType {
+    Debug,
     Info,
     Warn,
     Error
 }
 
+pub fn set_structured_output(enabled: bool) {
+    STRUCTURED_OUTPUT.store(enabled, Ordering::SeqCst);
+}
+
+pub fn structured_output() -> bool {
+    STRUCTURED_OUTPUT.load(Ordering::SeqCst)
+}
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    let level = match verbosity {
+        Verbosity::Normal => 0,
+        Verbosity::Quiet => 1,
+        Verbosity::Verbose => 2,
+    };
+    VERBOSITY.store(level, Ordering::SeqCst);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::SeqCst) {
+        0 => Verbosity::Normal,
+        1 => Verbosity::Quiet,
+        _ => Verbosity::Verbose,
+    }
+}
+
+fn secrets() -> &'static Mutex<Vec<String>> {
+    unsafe {
+        SECRETS_INIT.call_once(|| {
+            SECRETS = Box::into_raw(Box::new(Mutex::new(vec![])));
+        });
+        &*SECRETS
+    }
+}
+
+/// Masks `secret` in everything printed from now on, e.g. a token echoed back in an error message.
+pub fn add_secret<S: Into<String>>(secret: S) {
+    let secret = secret.into();
+    if secret.trim().is_empty() {
+        return;
+    }
+
+    let mut secrets = secrets().lock().unwrap();
+    if !secrets.contains(&secret) {
+        secrets.push(secret);
+        // Longer secrets first, in case one contains another
+        secrets.sort_by(|a, b| b.len().cmp(&a.len()));
+    }
+}
+
+/// `message` with all secrets registered with `add_secret` replaced by `REDACTED`.
+pub fn redact(message: &str) -> String {
+    let secrets = secrets().lock().unwrap();
+    secrets.iter().fold(message.to_owned(), |message, secret| message.replace(&secret[..], REDACTED))
+}
+
+pub fn debug<P: AsRef<str>>(message: P) {
+    print_message(message, MessageType::Debug);
+}
+
 pub fn stdout<P: AsRef<str>>(message: P) {
     print_message(message, MessageType::Info);
 }
@@ -20,24 +113,140 @@ pub fn stderr<P: AsRef<str>>(message: P) {
     print_message(message, MessageType::Error);
 }
 
+/// Prints the machine-readable result. This is the only thing written to stdout
+/// in structured output mode.
+pub fn result<P: AsRef<str>>(message: P) {
+    println!("{}", redact(message.as_ref()));
+}
+
 fn print_message<P: AsRef<str>>(message: P, message_type: MessageType) {
+    let shown = match message_type {
+        MessageType::Debug => verbosity() == Verbosity::Verbose,
+        MessageType::Info => verbosity() != Verbosity::Quiet,
+        MessageType::Warn | MessageType::Error => true,
+    };
+    if !shown {
+        return;
+    }
+    let message = redact(message.as_ref());
+
     match message_type {
-        MessageType::Info => {
-            println!("{}", message.as_ref());
+        MessageType::Debug | MessageType::Info => {
+            if structured_output() {
+                writeln!(io::stderr(), "{}", message).unwrap();
+            } else {
+                println!("{}", message);
+            }
         },
         MessageType::Warn => {
-            let mut warn_terminal = term::stdout().unwrap();
-            warn_terminal.fg(term::color::YELLOW).unwrap();
-            writeln!(warn_terminal, "{}", message.as_ref()).unwrap();
-            warn_terminal.reset().unwrap();
-            warn_terminal.flush().unwrap();
+            if structured_output() {
+                let mut warn_terminal = term::stderr().unwrap();
+                warn_terminal.fg(term::color::YELLOW).unwrap();
+                writeln!(warn_terminal, "{}", message).unwrap();
+                warn_terminal.reset().unwrap();
+                warn_terminal.flush().unwrap();
+            } else {
+                let mut warn_terminal = term::stdout().unwrap();
+                warn_terminal.fg(term::color::YELLOW).unwrap();
+                writeln!(warn_terminal, "{}", message).unwrap();
+                warn_terminal.reset().unwrap();
+                warn_terminal.flush().unwrap();
+            }
         },
         MessageType::Error => {
             let mut error_terminal = term::stderr().unwrap();
             error_terminal.fg(term::color::RED).unwrap();
-            writeln!(error_terminal, "{}", message.as_ref()).unwrap();
+            writeln!(error_terminal, "{}", message).unwrap();
             error_terminal.reset().unwrap();
             error_terminal.flush().unwrap();
         }
     }
 }
+
+/// A spinner on stderr while a long operation runs, like pushing or waiting for crates.io.
+/// It is only drawn on a terminal, without `--quiet` or `--verbose` and outside structured output,
+/// otherwise the message is printed once, so CI logs don't fill up with control characters.
+pub struct Progress {
+    running: Arc<AtomicBool>,
+    spinner: Option<JoinHandle<()>>,
+}
+
+pub fn progress<P: AsRef<str>>(message: P) -> Progress {
+    let running = Arc::new(AtomicBool::new(true));
+    if !progress_shown() {
+        stdout(message);
+        return Progress { running: running, spinner: None };
+    }
+
+    let message = redact(message.as_ref());
+    let keep_spinning = running.clone();
+    let spinner = thread::spawn(move || {
+        let mut frames = SPINNER_FRAMES.iter().cycle();
+        while keep_spinning.load(Ordering::SeqCst) {
+            let _ = write!(io::stderr(), "\r{} {}", frames.next().unwrap(), message);
+            let _ = io::stderr().flush();
+            thread::sleep(Duration::from_millis(SPINNER_INTERVAL_MS));
+        }
+        // Clear the line, so whatever comes next starts on an empty one
+        let _ = write!(io::stderr(), "\r\x1b[K");
+        let _ = io::stderr().flush();
+    });
+
+    Progress { running: running, spinner: Some(spinner) }
+}
+
+impl Progress {
+    /// Whether the spinner is drawn, output of child processes would garble it.
+    pub fn is_drawn(&self) -> bool {
+        self.spinner.is_some()
+    }
+
+    /// Removes the spinner and leaves `status` as the final line.
+    pub fn finish<P: AsRef<str>>(mut self, status: P) {
+        self.stop();
+        stdout(status);
+    }
+
+    fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(spinner) = self.spinner.take() {
+            let _ = spinner.join();
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// In verbose mode tools like cargo print their output live, which the spinner would garble.
+fn progress_shown() -> bool {
+    !structured_output() && verbosity() == Verbosity::Normal && stderr_is_tty()
+}
+
+#[cfg(unix)]
+fn stderr_is_tty() -> bool {
+    unsafe { libc::isatty(libc::STDERR_FILENO) != 0 }
+}
+
+#[cfg(not(unix))]
+fn stderr_is_tty() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn redacts_secrets() {
+        add_secret("ghp_logger_secret");
+        add_secret("");
+
+        assert_eq!("GitHub rejected token <redacted> for https://<redacted>@github.com",
+                   redact("GitHub rejected token ghp_logger_secret for https://ghp_logger_secret@github.com"));
+        assert_eq!("Nothing to hide", redact("Nothing to hide"));
+    }
+}
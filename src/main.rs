@@ -2,53 +2,36 @@
 #![cfg_attr(feature = "dev", feature(plugin))]
 #![cfg_attr(feature = "dev", plugin(clippy))]
 
-mod logger;
-mod toml_file;
-mod git;
-mod changelog;
-mod commit_analyzer;
-mod cargo;
-mod error;
-mod github;
-mod config;
-mod utils;
-mod preflight;
-
-extern crate rustc_serialize;
-extern crate toml;
-extern crate regex;
+extern crate semantic_rs;
 extern crate semver;
 extern crate git2;
-extern crate clog;
-extern crate hyper;
-extern crate hubcaps;
-extern crate url;
-extern crate travis_after_all;
 extern crate env_logger;
-extern crate hyper_native_tls;
 extern crate clap;
 
 use clap::{Arg, ArgMatches, App};
-use commit_analyzer::CommitType;
-use config::ConfigBuilder;
+use semantic_rs::{bitbucket, cargo, ci, config, exit_code, git, github, gitlab, logger, toml_file, utils};
+use semantic_rs::config::{ConfigBuilder, FileConfig, Provider};
+use semantic_rs::logger::Verbosity;
+use semantic_rs::report::Outcome;
 use std::process;
 use semver::Version;
 use std::{env,fs};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::error::Error;
-use std::thread;
-use std::time::Duration;
-use travis_after_all::Build;
-use utils::user_repo_from_url;
 
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
-const USERAGENT: &'static str = concat!("semantic-rs/", env!("CARGO_PKG_VERSION"));
+const CONFIG_FILE: &'static str = ".semantic-rs.toml";
 
 const COMMITTER_ERROR_MESSAGE: &'static str = r"
 A release commit needs a committer name and email address.
 We tried fetching it from different locations, but couldn't find one.
 
-Committer information is taken from the following environment variables, if set:
+Committer information can be passed explicitly:
+
+--committer-name
+--committer-email
+
+Otherwise it is taken from the following environment variables, if set:
 
 GIT_COMMITTER_NAME
 GIT_COMMITTER_EMAIL
@@ -59,14 +42,15 @@ Local repository config
 User config
 Global config";
 
+/// Exits on invalid arguments or configuration.
 macro_rules! print_exit {
     ($fmt:expr) => {{
         logger::stderr($fmt);
-        process::exit(1);
+        process::exit(exit_code::CONFIG);
     }};
     ($fmt:expr, $($arg:tt)*) => {{
         logger::stderr(format!($fmt, $($arg)*));
-        process::exit(1);
+        process::exit(exit_code::CONFIG);
     }};
 }
 
@@ -77,24 +61,8 @@ fn string_to_bool(answer: &str) -> bool {
     }
 }
 
-fn version_bump(version: &Version, bump: CommitType) -> Option<Version> {
-    let mut version = version.clone();
-    match bump {
-        CommitType::Unknown => return None,
-        CommitType::Patch => version.increment_patch(),
-        CommitType::Minor => version.increment_minor(),
-        CommitType::Major => version.increment_major(),
-    }
-
-    Some(version)
-}
-
-fn ci_env_set() -> bool {
-    env::var("CI").is_ok()
-}
-
 fn current_branch(repo: &git2::Repository) -> Option<String> {
-    if let Ok(branch) = env::var("TRAVIS_BRANCH") {
+    if let Some(branch) = ci::environment().branch {
         return Some(branch)
     }
 
@@ -108,81 +76,22 @@ fn current_branch(repo: &git2::Repository) -> Option<String> {
     None
 }
 
-fn is_release_branch(current: &str, release: &str) -> bool {
-    if let Ok(pr) = env::var("TRAVIS_PULL_REQUEST") {
-        if pr != "false" {
-            return false;
-        }
-    }
-
-    current == release
-}
-
-fn push_to_github(config: &config::Config, tag_name: &str) {
-    logger::stdout("Pushing new commit and tag");
-    git::push(&config, &tag_name)
-        .unwrap_or_else(|err| print_exit!("Failed to push git: {:?}", err));
-
-    logger::stdout("Waiting a tiny bit, so GitHub can store the git tag");
-    thread::sleep(Duration::from_secs(1));
-}
-
-fn release_on_github(config: &config::Config, tag_message: &str, tag_name: &str) {
-    if github::can_release(&config) {
-        logger::stdout("Creating GitHub release");
-        github::release(&config, &tag_name, &tag_message)
-            .unwrap_or_else(|err| print_exit!("Failed to create GitHub release: {:?}", err));
-    } else {
-        logger::stdout("Project not hosted on GitHub. Skipping release step");
-    }
-}
-
-fn release_on_cratesio(config: &config::Config) {
-    logger::stdout("Publishing crate on crates.io");
-    if !cargo::publish(&config.repository_path, &config.cargo_token.as_ref().unwrap()) {
-        print_exit!("Failed to publish on crates.io");
-    }
-}
-
-fn generate_changelog(repository_path: &str, version: &Version, new_version: &String) -> String {
-    logger::stdout(format!("New version would be: {}", new_version));
-    logger::stdout("Would write the following Changelog:");
-    match changelog::generate(repository_path, &version.to_string(), new_version) {
-        Ok(log) => log,
-        Err(err) => {
-            logger::stderr(format!("Generating Changelog failed: {:?}", err));
-            process::exit(1)
-        }
+fn print_report(outcome: &Outcome) {
+    if logger::structured_output() {
+        logger::result(outcome.to_json().to_string());
     }
 }
 
-fn write_changelog(repository_path: &str, version: &Version, new_version: &str) {
-    logger::stdout("Writing Changelog");
-    changelog::write(repository_path, &version.to_string(), &new_version)
-        .unwrap_or_else(|err| print_exit!("Writing Changelog failed: {:?}", err));
-}
-
-fn print_changelog(changelog: &str) {
-    logger::stdout("====================================");
-    logger::stdout(changelog);
-    logger::stdout("====================================");
-    logger::stdout("Would create annotated git tag");
-}
-
-fn package_crate(config: &config::Config, repository_path: &str, new_version: &str) {
-    if config.release_mode {
-        logger::stdout("Updating lockfile");
-        if !cargo::update_lockfile(repository_path) {
-            print_exit!("`cargo fetch` failed. See above for the cargo error message.");
-        }
-    }
-
-    git::commit_files(&config, &new_version)
-        .unwrap_or_else(|err| print_exit!("Committing files failed: {:?}", err));
-
-    logger::stdout("Package crate");
-    if !cargo::package(repository_path) {
-        print_exit!("`cargo package` failed. See above for the cargo error message.");
+/// Prints the version `--print-version` asks for: the next one, or the current one if nothing would be released.
+/// With `--no-release-exit-code` nothing is printed in that case, the exit code tells.
+fn print_next_version(outcome: &Outcome, distinguish_no_release: bool) {
+    let version = match outcome.new_version {
+        Some(ref version) => Some(version),
+        None if distinguish_no_release => None,
+        None => outcome.current_version.as_ref(),
+    };
+    if let Some(version) = version {
+        logger::result(version);
     }
 }
 
@@ -191,7 +100,7 @@ fn get_repo(repository_path: &str) -> git2::Repository {
         Ok(repo) => repo,
         Err(e) => {
             logger::stderr(format!("Could not open the git repository: {:?}", e));
-            process::exit(1);
+            process::exit(exit_code::GIT);
         }
     }
 }
@@ -205,68 +114,128 @@ fn get_repository_path(matches: &ArgMatches) -> String {
     repo_path.to_string()
 }
 
-fn get_signature<'a>(repository_path: String) -> git2::Signature<'a> {
-    let repo = get_repo(&repository_path);
-    let signature = match git::get_signature(&repo) {
+fn get_signature(repository_path: &str, name: Option<&str>, email: Option<&str>) -> git2::Signature<'static> {
+    let repo = get_repo(repository_path);
+    let signature = match git::get_signature(&repo, name, email) {
         Ok(sig) => sig,
             Err(e) => {
                 logger::stderr(format!("Failed to get the committer's name and email address: {}", e.description()));
                 logger::stderr(COMMITTER_ERROR_MESSAGE);
-                process::exit(1);
+                process::exit(exit_code::CONFIG);
             }
     };
 
     signature.to_owned()
 }
 
-fn get_user_and_repo(repository_path: &str) -> Option<(String, String)> {
+fn get_user_and_repo(repository_path: &str, remote_name: &str, provider: Provider) -> Option<(String, String)> {
     let repo = get_repo(repository_path);
-    let remote_or_none = repo.find_remote("origin");
-    match remote_or_none {
-        Ok(remote) => {
-            let url = remote.url().expect("Remote URL is not valid UTF-8").to_owned();
-            let (user, repo_name) = user_repo_from_url(&url)
-                .unwrap_or_else(|e| print_exit!("Could not extract user and repository name from URL: {:?}", e));
-
-            Some((user, repo_name))
+    match git::remote_url(&repo, remote_name) {
+        // Other hosts have their own URL layout, it's only needed to push
+        Ok(_) if provider == Provider::None => git::remote_user_and_repo(&repo, remote_name).ok(),
+        Ok(_) => {
+            let user_and_repo = git::remote_user_and_repo(&repo, remote_name)
+                .unwrap_or_else(|e| print_exit!("{}", e));
+            Some(user_and_repo)
         },
         Err(err) => {
-            logger::warn(format!("Could not determine the origin remote url: {:?}", err));
+            logger::warn(format!("Could not determine the {} remote url: {}", remote_name, err));
             logger::warn("semantic-rs can't push changes or create a release on GitHub");
             None
         }
     }
 }
 
-fn get_github_token(repository_path: &str) -> Option<String> {
-    let repo = get_repo(repository_path);
-    let remote_or_none = repo.find_remote("origin");
-    match remote_or_none {
-        Ok(remote) => {
-            let url = remote.url().expect("Remote URL is not valid UTF-8").to_owned();
-            if github::is_github_url(&url) {
-                env::var("GH_TOKEN").ok()
-            } else {
-                None
-            }
+/// A token read from the file given with `--<flag>` or `<var>_FILE` wins over the `<var>` environment variable.
+fn get_token(args: &ArgMatches, flag: &str, var: &str) -> Option<String> {
+    let token_file = args.value_of(flag).map(|path| path.to_owned())
+        .or(env::var(format!("{}_FILE", var)).ok());
+
+    match token_file {
+        Some(path) => match utils::read_token_file(Path::new(&path)) {
+            Ok(token) => Some(token),
+            Err(err) => print_exit!("{}", err),
         },
-        Err(_) => None
+        None => env::var(var).ok(),
+    }
+}
+
+fn get_github_token(args: &ArgMatches, repository_path: &str, remote_name: &str) -> Option<String> {
+    match get_remote_url(repository_path, remote_name) {
+        Some(ref url) if github::is_github_url(url) => get_token(args, "gh-token-file", "GH_TOKEN"),
+        _ => None
+    }
+}
+
+fn get_remote_url(repository_path: &str, remote_name: &str) -> Option<String> {
+    let repo = get_repo(repository_path);
+    git::remote_url(&repo, remote_name).ok()
+}
+
+/// An explicitly requested provider wins,
+/// otherwise GitLab or Bitbucket are picked for remotes that look like them.
+fn get_provider(args: &ArgMatches, file_provider: Option<Provider>, repository_path: &str, remote_name: &str) -> Provider {
+    if let Some(provider) = args.value_of("provider") {
+        return provider.parse()
+            .unwrap_or_else(|err| print_exit!("{}", err));
     }
+    if let Some(provider) = file_provider {
+        return provider;
+    }
+
+    match get_remote_url(repository_path, remote_name) {
+        Some(ref url) if gitlab::is_gitlab_url(url) => Provider::GitLab,
+        Some(ref url) if bitbucket::is_bitbucket_url(url) => Provider::Bitbucket,
+        _ => Provider::GitHub,
+    }
+}
+
+fn get_gitlab_token() -> Option<String> {
+    env::var("GITLAB_TOKEN").ok()
+}
+
+fn get_bitbucket_token() -> Option<String> {
+    env::var("BITBUCKET_TOKEN").ok()
 }
 
-fn get_cargo_token() -> Option<String> {
-    env::var("CARGO_TOKEN").ok()
+fn get_slack_webhook() -> Option<String> {
+    env::var("SLACK_WEBHOOK").ok()
+}
+
+fn get_cargo_token(args: &ArgMatches, registry: Option<&str>) -> Option<String> {
+    get_token(args, "cargo-token-file", &cargo::token_env_var(registry))
+}
+
+fn get_file_config(args: &ArgMatches, repository_path: &str) -> FileConfig {
+    let path = match args.value_of("config") {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let path = Path::new(repository_path).join(CONFIG_FILE);
+            if !path.exists() {
+                return FileConfig::default();
+            }
+            path
+        }
+    };
+
+    FileConfig::load(&path)
+        .unwrap_or_else(|err| print_exit!("{}", err))
 }
 
 fn assemble_configuration(args: ArgMatches) -> config::Config {
     let mut config_builder = ConfigBuilder::new();
 
-    // If write mode is requested OR denied,
+    // An explicit dry run always wins.
+    // Otherwise, if write mode is requested OR denied,
     // adhere to the user's wish,
     // otherwise we decide based on whether we are running in CI.
-    let write_mode = match args.value_of("write") {
-        Some(write_mode) => string_to_bool(write_mode),
-        None => ci_env_set()
+    let write_mode = if args.is_present("dry-run") || args.is_present("print-version") {
+        false
+    } else {
+        match args.value_of("write") {
+            Some(write_mode) => string_to_bool(write_mode),
+            None => utils::ci_env_set()
+        }
     };
 
     let release_flag = match args.value_of("release") {
@@ -278,24 +247,301 @@ fn assemble_configuration(args: ArgMatches) -> config::Config {
     let release_mode = write_mode && release_flag;
     let repository_path = get_repository_path(&args);
 
+    // Settings from the config file are applied first,
+    // so that command line flags can override them.
+    let file_config = get_file_config(&args, &repository_path);
+    config_builder.file_config(&file_config);
+
+    let remote_name = args.value_of("remote")
+        .or(file_config.remote.as_ref().map(|r| &r[..]))
+        .unwrap_or("origin");
+    let registry = args.value_of("registry")
+        .or(file_config.registry.as_ref().map(|r| &r[..]));
+
     config_builder.write(write_mode);
     config_builder.release(release_mode);
-    config_builder.branch(args.value_of("branch").unwrap_or("master").to_string());
+    if let Some(branches) = args.values_of("branch") {
+        let branches = branches.flat_map(|b| b.split(','))
+            .map(|b| b.trim())
+            .filter(|b| !b.is_empty())
+            .map(|b| b.to_string())
+            .collect();
+        config_builder.release_branches(branches);
+    }
+    if let Some(prefix) = args.value_of("tag-prefix") {
+        config_builder.tag_prefix(prefix.to_string());
+    }
+    if let Some(format) = args.value_of("tag-format") {
+        if let Err(err) = config::validate_tag_format(format) {
+            print_exit!("{}", err);
+        }
+        config_builder.tag_format(format.to_string());
+    }
+    let mut manifest_dir = PathBuf::from(&repository_path);
+    if let Some(package) = args.value_of("package") {
+        let dir = toml_file::package_dir(&repository_path, package)
+            .unwrap_or_else(|err| print_exit!("Reading the workspace members failed: {:?}", err))
+            .unwrap_or_else(|| print_exit!("The workspace has no member called {}", package));
+        manifest_dir = manifest_dir.join(&dir);
+        config_builder.package(package.to_string(), dir);
+    } else if let Ok(Some(name)) = toml_file::read_package_name(&repository_path) {
+        config_builder.package_name(name);
+    }
+    if let Ok(binary_only) = toml_file::is_binary_only(&manifest_dir.to_string_lossy()) {
+        config_builder.binary_only(binary_only);
+    }
+    if args.is_present("force-publish") {
+        config_builder.force_publish(true);
+    }
+    if let Some(url) = args.value_of("notify-webhook") {
+        config_builder.notify_webhook(url.to_string());
+    }
+    if let Some(url) = args.value_of("slack-webhook").map(|url| url.to_string()).or_else(get_slack_webhook) {
+        config_builder.slack_webhook(url);
+    }
+    if let Some(token) = args.value_of("skip-release-token") {
+        config_builder.skip_release_token(token.to_string());
+    }
+    if args.is_present("sign-tags") {
+        config_builder.sign_tags(true);
+    }
+    if args.is_present("sign-commits") {
+        config_builder.sign_commits(true);
+    }
+    if let Some(version) = args.value_of("set-version") {
+        let version = Version::parse(version)
+            .unwrap_or_else(|err| print_exit!("Invalid version '{}' for --set-version: {:?}", version, err));
+        config_builder.version_override(version);
+    }
+    if args.is_present("version-from-tags") {
+        config_builder.version_from_tags(true);
+    }
+    if args.is_present("zero-ver-semantics") {
+        config_builder.zero_ver_semantics(true);
+    }
+    if let Some(revision) = args.value_of("since") {
+        config_builder.since(revision.to_string());
+    }
+    if args.is_present("regenerate-changelog") {
+        config_builder.regenerate_changelog(true);
+    }
+    if let Some(attempts) = args.value_of("max-attempts") {
+        match attempts.parse() {
+            Ok(attempts) if attempts > 0 => config_builder.max_attempts(attempts),
+            _ => print_exit!("--max-attempts expects a positive number, got '{}'", attempts),
+        };
+    }
+    if let Some(seconds) = args.value_of("publish-timeout") {
+        match seconds.parse() {
+            Ok(seconds) => config_builder.publish_timeout(seconds),
+            Err(_) => print_exit!("--publish-timeout expects a number of seconds, got '{}'", seconds),
+        };
+    }
+    if let Some(seconds) = args.value_of("tag-wait-timeout") {
+        match seconds.parse() {
+            Ok(seconds) => config_builder.tag_wait_timeout(seconds),
+            Err(_) => print_exit!("--tag-wait-timeout expects a number of seconds, got '{}'", seconds),
+        };
+    }
+    if let Some(bump) = args.value_of("force-bump") {
+        let bump = bump.parse()
+            .unwrap_or_else(|err| print_exit!("{}", err));
+        config_builder.forced_bump(bump);
+    }
+    if let Some(label) = args.value_of("prerelease") {
+        if let Err(err) = config::validate_prerelease_label(label) {
+            print_exit!("{}", err);
+        }
+        config_builder.prerelease(label.to_string());
+    }
+    if args.is_present("release-final") {
+        config_builder.release_final(true);
+    }
+    if args.is_present("first-release") {
+        config_builder.first_release(true);
+    }
+    if args.is_present("strict-history") {
+        config_builder.strict_history(true);
+    }
+    if let Some(template) = args.value_of("build-metadata") {
+        config_builder.build_metadata(template.to_string());
+    }
+    if let Some(size) = args.value_of("max-package-size") {
+        match size.parse::<u64>() {
+            Ok(kilobytes) if kilobytes > 0 => config_builder.max_package_size(kilobytes * 1024),
+            _ => print_exit!("--max-package-size expects a positive number of KiB, got '{}'", size),
+        };
+    }
+    if args.is_present("strict") {
+        config_builder.strict(true);
+    }
+    if let Some(path) = args.value_of("version-file") {
+        config_builder.version_file(path.to_string());
+    }
+    if args.is_present("package-only") {
+        config_builder.package_only(true);
+    }
+    if let Some(tag_name) = args.value_of("release-only") {
+        config_builder.release_only(tag_name.to_string());
+    }
+    if let Some(path) = args.value_of("changelog") {
+        config_builder.changelog_path(path.to_string());
+    }
+    if let Some(path) = args.value_of("output-changelog") {
+        config_builder.changelog_output(path.to_string());
+    }
+    if let Some(template) = args.value_of("issue-url") {
+        config_builder.issue_url_template(template.to_string());
+    }
+    if let Some(pattern) = args.value_of("issue-pattern") {
+        if let Err(err) = config::validate_issue_pattern(pattern) {
+            print_exit!("{}", err);
+        }
+        config_builder.issue_pattern(pattern.to_string());
+    }
+    if let Some(template) = args.value_of("changelog-template") {
+        config_builder.changelog_template(template.to_string());
+    }
+    if args.is_present("changelog-authors") {
+        config_builder.changelog_show_authors(true);
+    }
+    if let Some(sections) = args.values_of("changelog-section") {
+        for section in sections {
+            let mut parts = section.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(kind), Some(title)) if !kind.is_empty() && !title.is_empty() => {
+                    config_builder.changelog_section(kind.to_string(), title.to_string());
+                }
+                _ => print_exit!("Invalid changelog section `{}`, expected TYPE=TITLE", section),
+            }
+        }
+    }
+    if args.is_present("changelog-hide-other") {
+        config_builder.changelog_show_other(false);
+    }
+    if args.is_present("no-changelog") || args.is_present("print-version") {
+        config_builder.generate_changelog(false);
+    }
+    if let Some(template) = args.value_of("commit-message") {
+        config_builder.commit_message_template(template.to_string());
+    }
+    if let Some(template) = args.value_of("tag-message") {
+        config_builder.tag_message_template(template.to_string());
+    }
+    if args.is_present("draft") {
+        config_builder.github_draft(true);
+    }
+    if args.is_present("github-generate-notes") {
+        config_builder.github_generate_notes(true);
+    }
+    if let Some(prerelease) = args.value_of("github-prerelease") {
+        config_builder.github_prerelease(string_to_bool(prerelease));
+    }
+    if let Some(hooks) = args.values_of("pre-release-hook") {
+        for hook in hooks {
+            config_builder.pre_release_hook(hook.to_string());
+        }
+    }
+    if let Some(hooks) = args.values_of("post-release-hook") {
+        for hook in hooks {
+            config_builder.post_release_hook(hook.to_string());
+        }
+    }
+    if let Some(patterns) = args.values_of("include-path") {
+        for pattern in patterns {
+            config_builder.include_path(pattern.to_string());
+        }
+    }
+    if let Some(patterns) = args.values_of("exclude-path") {
+        for pattern in patterns {
+            config_builder.exclude_path(pattern.to_string());
+        }
+    }
+    if let Some(assets) = args.values_of("asset") {
+        for asset in assets {
+            config_builder.asset(asset.to_string());
+        }
+    }
     config_builder.repository_path(repository_path.clone());
-    config_builder.signature(get_signature(repository_path.clone()));
-    if let Some((user, repo)) = get_user_and_repo(&repository_path) {
+    config_builder.signature(get_signature(&repository_path,
+                                           args.value_of("committer-name"),
+                                           args.value_of("committer-email")));
+    let provider = get_provider(&args, file_config.provider, &repository_path, remote_name);
+    config_builder.provider(provider);
+    if let Some((user, repo)) = get_user_and_repo(&repository_path, remote_name, provider) {
         config_builder.user(user);
         config_builder.repository_name(repo);
     }
-    if let Some(gh_token)  = get_github_token(&repository_path) {
+    if let Some(gh_token)  = get_github_token(&args, &repository_path, remote_name) {
         config_builder.gh_token(gh_token);
     }
-    if let Some(cargo_token) = get_cargo_token() {
+    if provider == Provider::GitLab {
+        if let Some(gitlab_token) = get_gitlab_token() {
+            config_builder.gitlab_token(gitlab_token);
+        }
+    }
+    if provider == Provider::Bitbucket {
+        if let Some(bitbucket_token) = get_bitbucket_token() {
+            config_builder.bitbucket_token(bitbucket_token);
+        }
+    }
+    if let Some(features) = args.values_of("features") {
+        for feature in features.flat_map(|f| f.split(|c| c == ',' || c == ' ')).filter(|f| !f.is_empty()) {
+            config_builder.feature(feature.to_string());
+        }
+    }
+    if args.is_present("all-features") {
+        config_builder.all_features(true);
+    }
+    if args.is_present("no-default-features") {
+        config_builder.no_default_features(true);
+    }
+    if args.is_present("no-publish") {
+        config_builder.publish(false);
+    }
+    if args.is_present("no-ci-wait") {
+        config_builder.ci_wait(false);
+    }
+    if args.is_present("no-push") {
+        config_builder.push(false);
+    }
+    if args.is_present("lightweight-tags") {
+        config_builder.tag_annotated(false);
+    }
+    if args.is_present("no-github-release") {
+        config_builder.github_release(false);
+    }
+    if args.is_present("allow-dirty") {
+        config_builder.allow_dirty(true);
+    }
+    if args.is_present("no-verify") {
+        config_builder.no_verify(true);
+    }
+    if let Some(registry) = registry {
+        config_builder.registry(registry.to_string());
+    }
+    if let Some(cargo_token) = get_cargo_token(&args, registry) {
         config_builder.cargo_token(cargo_token);
     }
     let repo = get_repo(&repository_path);
-    match repo.find_remote("origin") {
+    let branch = current_branch(&repo)
+        .unwrap_or_else(|| print_exit!("Could not determine current branch."));
+    config_builder.branch(branch);
+    config_builder.pull_request(ci::environment().pull_request);
+    if !args.is_present("branch") && file_config.branches.is_empty() {
+        match git::default_branch(&repo, remote_name) {
+            Some(default_branch) => {
+                logger::stdout(format!("Releasing from '{}', the default branch of {}", default_branch, remote_name));
+                config_builder.release_branches(vec![default_branch]);
+            },
+            None => logger::debug(format!("Could not detect the default branch of {}, releasing from master", remote_name)),
+        }
+    }
+    match repo.find_remote(remote_name) {
         Ok(r) => config_builder.remote(Ok(r.name().unwrap().to_string())),
+        Err(ref err) if args.is_present("remote") || file_config.remote.is_some() => {
+            print_exit!("The remote '{}' does not exist: {}", remote_name, err.description())
+        },
         Err(err) => config_builder.remote(Err(err.description().to_string()))
     };
 
@@ -305,8 +551,6 @@ fn assemble_configuration(args: ArgMatches) -> config::Config {
 
 fn main() {
     env_logger::init().expect("Can't instantiate env logger");
-    println!("semantic.rs 🚀");
-
 
     let clap_args =  App::new("semantic-rs")
         .version(VERSION)
@@ -318,6 +562,9 @@ fn main() {
              .help("Write changes to files (default: yes if CI is set, otherwise no).")
              .value_name("WRITE_MODE")
              .takes_value(true))
+        .arg(Arg::with_name("dry-run")
+             .long("dry-run")
+             .help("Only print what would happen, never write anything (overrides --write and CI)."))
         .arg(Arg::with_name("release")
             .short("r")
             .long("release")
@@ -327,9 +574,342 @@ fn main() {
         .arg(Arg::with_name("branch")
              .short("b")
              .long("branch")
-             .help("The branch on which releases should happen. [default: master].")
+             .help("The branches on which releases should happen, may contain patterns like 'release/*'. [default: the remote's default branch, or master].")
              .value_name("BRANCH")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1))
+        .arg(Arg::with_name("tag-prefix")
+             .long("tag-prefix")
+             .help("Prefix for release tags, may be empty. [default: v]")
+             .value_name("PREFIX")
+             .takes_value(true)
+             .empty_values(true))
+        .arg(Arg::with_name("tag-format")
+             .long("tag-format")
+             .help("Format of release tags, e.g. `{name}-v{version}` to release crates of a workspace independently. {name} is the package name, {version} has to come last.")
+             .value_name("FORMAT")
+             .takes_value(true)
+             .conflicts_with("tag-prefix"))
+        .arg(Arg::with_name("package")
+             .long("package")
+             .help("Release only this member of the workspace.")
+             .value_name("NAME")
+             .takes_value(true))
+        .arg(Arg::with_name("committer-name")
+             .long("committer-name")
+             .help("Name used for the release commit and tag (overrides GIT_COMMITTER_NAME and git config).")
+             .value_name("NAME")
+             .takes_value(true))
+        .arg(Arg::with_name("committer-email")
+             .long("committer-email")
+             .help("Email used for the release commit and tag (overrides GIT_COMMITTER_EMAIL and git config).")
+             .value_name("EMAIL")
+             .takes_value(true))
+        .arg(Arg::with_name("sign-tags")
+             .long("sign-tags")
+             .help("GPG-sign the release tag with the key configured in user.signingkey."))
+        .arg(Arg::with_name("sign-commits")
+             .long("sign-commits")
+             .help("GPG-sign the release commit with the key configured in user.signingkey."))
+        .arg(Arg::with_name("commit-message")
+             .long("commit-message")
+             .help("Template for the release commit message, {version} and {tag} are replaced. [default: Bump version to {version}]")
+             .value_name("TEMPLATE")
+             .takes_value(true))
+        .arg(Arg::with_name("tag-message")
+             .long("tag-message")
+             .help("Template for the message of annotated release tags, {version}, {tag}, {date} and {changelog} are replaced. [default: {changelog}]")
+             .value_name("TEMPLATE")
+             .takes_value(true))
+        .arg(Arg::with_name("remote")
+             .long("remote")
+             .help("The git remote to push the release to. [default: origin]")
+             .value_name("REMOTE")
+             .takes_value(true))
+        .arg(Arg::with_name("include-path")
+             .long("include-path")
+             .help("Only commits changing files below this path or matching this pattern count, e.g. the directory of a workspace member. Can be given multiple times.")
+             .value_name("PATH")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1))
+        .arg(Arg::with_name("exclude-path")
+             .long("exclude-path")
+             .help("Commits only changing files below this path or matching this pattern don't trigger a release, can be given multiple times.")
+             .value_name("PATH")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1))
+        .arg(Arg::with_name("asset")
+             .long("asset")
+             .help("File to attach to the GitHub release, can be given multiple times.")
+             .value_name("FILE")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1))
+        .arg(Arg::with_name("draft")
+             .long("draft")
+             .help("Create the GitHub release as a draft."))
+        .arg(Arg::with_name("github-generate-notes")
+             .long("github-generate-notes")
+             .help("Let GitHub add release notes generated from merged pull requests, below the changelog."))
+        .arg(Arg::with_name("github-prerelease")
+             .long("github-prerelease")
+             .help("Mark the GitHub release as a pre-release (yes/no). [default: yes for pre-release versions]")
+             .value_name("PRERELEASE")
+             .takes_value(true))
+        .arg(Arg::with_name("provider")
+             .long("provider")
+             .help("Where to create the release: github, gitlab or bitbucket. With none the release commit and tag are only pushed. [default: detected from the remote URL]")
+             .value_name("PROVIDER")
+             .possible_values(&["github", "gitlab", "bitbucket", "none"])
+             .takes_value(true))
+        .arg(Arg::with_name("registry")
+             .long("registry")
+             .help("Publish to this registry from .cargo/config instead of crates.io. The token is read from CARGO_REGISTRIES_<NAME>_TOKEN.")
+             .value_name("REGISTRY")
+             .takes_value(true))
+        .arg(Arg::with_name("features")
+             .long("features")
+             .help("Features to activate when packaging and publishing the crate.")
+             .value_name("FEATURES")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1))
+        .arg(Arg::with_name("all-features")
+             .long("all-features")
+             .help("Activate all features when packaging and publishing the crate."))
+        .arg(Arg::with_name("no-default-features")
+             .long("no-default-features")
+             .help("Do not activate the default features when packaging and publishing the crate."))
+        .arg(Arg::with_name("gh-token-file")
+             .long("gh-token-file")
+             .value_name("PATH")
+             .help("Read the GitHub token from PATH instead of GH_TOKEN. Can also be set with GH_TOKEN_FILE.")
+             .takes_value(true))
+        .arg(Arg::with_name("cargo-token-file")
+             .long("cargo-token-file")
+             .value_name("PATH")
+             .help("Read the registry token from PATH instead of CARGO_TOKEN. Can also be set with CARGO_TOKEN_FILE.")
+             .takes_value(true))
+        .arg(Arg::with_name("no-push")
+             .long("no-push")
+             .help("Commit and tag the release locally, but don't push it, create releases or publish the crate."))
+        .arg(Arg::with_name("lightweight-tags")
+             .long("lightweight-tags")
+             .help("Create lightweight release tags instead of annotated ones. The release notes still come from the changelog.")
+             .conflicts_with("sign-tags"))
+        .arg(Arg::with_name("no-ci-wait")
+             .long("no-ci-wait")
+             .help("Release right away on Travis instead of waiting for the other jobs of the build."))
+        .arg(Arg::with_name("no-publish")
+             .long("no-publish")
+             .help("Don't package the crate or publish it to the registry. The tag and GitHub release are still created."))
+        .arg(Arg::with_name("notify-webhook")
+             .long("notify-webhook")
+             .help("POST a JSON payload with the repository, version, tag and changelog to this URL after a GitHub, GitLab or Bitbucket release was created.")
+             .value_name("URL")
+             .takes_value(true))
+        .arg(Arg::with_name("slack-webhook")
+             .long("slack-webhook")
+             .help("Announce the release on Slack through this incoming webhook. [default: $SLACK_WEBHOOK]")
+             .value_name("URL")
+             .takes_value(true))
+        .arg(Arg::with_name("skip-release-token")
+             .long("skip-release-token")
+             .help("Don't release if the latest commit message contains this marker, an empty value disables it. [default: [skip release]]")
+             .value_name("TOKEN")
+             .takes_value(true)
+             .empty_values(true))
+        .arg(Arg::with_name("force-publish")
+             .long("force-publish")
+             .help("Publish the crate even if it only has binary targets.")
+             .conflicts_with("no-publish"))
+        .arg(Arg::with_name("no-github-release")
+             .long("no-github-release")
+             .help("Don't create a GitHub release, only push the tag."))
+        .arg(Arg::with_name("allow-dirty")
+             .long("allow-dirty")
+             .help("Release even though the working tree has uncommitted changes. They are left out of the release commit."))
+        .arg(Arg::with_name("no-verify")
+             .long("no-verify")
+             .help("Pass --no-verify to cargo package and publish, skipping the build-check of the packaged crate."))
+        .arg(Arg::with_name("pre-release-hook")
+             .long("pre-release-hook")
+             .help("Shell command to run before the release commit, can be given multiple times. A failing hook aborts the release.")
+             .value_name("COMMAND")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1))
+        .arg(Arg::with_name("post-release-hook")
+             .long("post-release-hook")
+             .help("Shell command to run after the release was published, can be given multiple times.")
+             .value_name("COMMAND")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1))
+        .arg(Arg::with_name("config")
+             .long("config")
+             .help("Read settings from this file instead of .semantic-rs.toml in the repository. Command line flags take precedence.")
+             .value_name("PATH")
+             .takes_value(true))
+        .arg(Arg::with_name("config-print")
+             .long("config-print")
+             .alias("show-config")
+             .help("Print the effective configuration as JSON, with secrets redacted, and exit."))
+        .arg(Arg::with_name("print-version")
+             .long("print-version")
+             .help("Only print the next version to stdout, or the current one if nothing would be released, and exit. Nothing is written, all other messages go to stderr.")
+             .conflicts_with_all(&["write", "output", "config-print", "package-only", "release-only", "regenerate-changelog", "output-changelog"]))
+        .arg(Arg::with_name("output")
+             .long("output")
+             .help("How to report the result. With 'json' a single JSON object is printed to stdout and all other messages go to stderr. [default: human]")
+             .value_name("FORMAT")
+             .possible_values(&["human", "json"])
              .takes_value(true))
+        .arg(Arg::with_name("changelog")
+             .long("changelog")
+             .help("Path of the changelog file, relative to the repository. [default: Changelog.md]")
+             .value_name("PATH")
+             .takes_value(true))
+        .arg(Arg::with_name("output-changelog")
+             .long("output-changelog")
+             .help("Also write the notes of the new release alone to PATH, even in dry-run mode.")
+             .value_name("PATH")
+             .takes_value(true))
+        .arg(Arg::with_name("issue-url")
+             .long("issue-url")
+             .help("Link ticket references in the changelog to this issue tracker URL, {id} is replaced with the reference. E.g. https://jira.example.com/browse/{id}")
+             .value_name("TEMPLATE")
+             .takes_value(true))
+        .arg(Arg::with_name("issue-pattern")
+             .long("issue-pattern")
+             .help("Regex matching the ticket references linked with --issue-url. [default: [A-Z][A-Z0-9]+-[0-9]+]")
+             .value_name("REGEX")
+             .takes_value(true))
+        .arg(Arg::with_name("changelog-template")
+             .long("changelog-template")
+             .help("Render the changelog with this template file instead of the default format. Supports {tag}, {version}, {date}, {breaking}, {features} and {fixes}.")
+             .value_name("PATH")
+             .takes_value(true))
+        .arg(Arg::with_name("changelog-authors")
+             .long("changelog-authors")
+             .help("Credit the author of each commit and list all contributors in the changelog."))
+        .arg(Arg::with_name("changelog-section")
+             .long("changelog-section")
+             .help("List commits of a type under their own changelog section, e.g. `perf=Performance`. Can be given multiple times, sections appear in that order.")
+             .value_name("TYPE=TITLE")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1))
+        .arg(Arg::with_name("changelog-hide-other")
+             .long("changelog-hide-other")
+             .help("Leave commit types without a changelog section out instead of listing them under \"Other\"."))
+        .arg(Arg::with_name("no-changelog")
+             .long("no-changelog")
+             .help("Don't write a changelog, the tag message will only name the release.")
+             .conflicts_with("changelog"))
+        .arg(Arg::with_name("set-version")
+             .long("set-version")
+             .help("Release this version instead of computing it from the commits. Must be greater than the current version.")
+             .value_name("VERSION")
+             .takes_value(true))
+        .arg(Arg::with_name("max-attempts")
+             .long("max-attempts")
+             .help("How often to try creating the GitHub release and publishing the crate when the service fails temporarily. [default: 3]")
+             .value_name("N")
+             .takes_value(true))
+        .arg(Arg::with_name("publish-timeout")
+             .long("publish-timeout")
+             .help("Seconds to wait for a published workspace member to show up on crates.io before publishing the crates depending on it. [default: 300]")
+             .value_name("SECONDS")
+             .takes_value(true))
+        .arg(Arg::with_name("tag-wait-timeout")
+             .long("tag-wait-timeout")
+             .help("Seconds to wait for the pushed tag to show up on GitHub before creating the release. [default: 30]")
+             .value_name("SECONDS")
+             .takes_value(true))
+        .arg(Arg::with_name("regenerate-changelog")
+             .long("regenerate-changelog")
+             .help("Rebuild the whole changelog with a section per release tag. Nothing is bumped, committed or released.")
+             .conflicts_with_all(&["release-only", "package-only", "since"]))
+        .arg(Arg::with_name("since")
+             .long("since")
+             .value_name("REF")
+             .help("Compute the bump and changelog from the commits since REF, a tag or commit, instead of the latest release tag")
+             .takes_value(true)
+             .conflicts_with_all(&["release-only", "first-release"]))
+        .arg(Arg::with_name("zero-ver-semantics")
+             .long("zero-ver-semantics")
+             .help("Below 1.0.0, bump the minor version for breaking changes and the patch version for features."))
+        .arg(Arg::with_name("version-from-tags")
+             .long("version-from-tags")
+             .help("Use the latest release tag as the current version if it is higher than the one in Cargo.toml."))
+        .arg(Arg::with_name("force-bump")
+             .long("force-bump")
+             .help("Release with at least this bump, even if the commits don't ask for one.")
+             .value_name("LEVEL")
+             .possible_values(&["patch", "minor", "major"])
+             .conflicts_with("set-version")
+             .takes_value(true))
+        .arg(Arg::with_name("prerelease")
+             .long("prerelease")
+             .help("Release a pre-release on this channel, e.g. `rc` releases 1.2.0-rc.1. Further runs increment the counter.")
+             .value_name("LABEL")
+             .conflicts_with("set-version")
+             .takes_value(true))
+        .arg(Arg::with_name("release-final")
+             .long("release-final")
+             .help("Promote the current pre-release to its final version, e.g. 1.2.0-rc.2 becomes 1.2.0.")
+             .conflicts_with_all(&["prerelease", "set-version"]))
+        .arg(Arg::with_name("first-release")
+             .long("first-release")
+             .help("Release the version in Cargo.toml as is, with a changelog of the whole history. Fails if that version is already tagged.")
+             .conflicts_with_all(&["set-version", "force-bump", "prerelease", "release-final"]))
+        .arg(Arg::with_name("version-file")
+             .long("version-file")
+             .value_name("PATH")
+             .help("Read the version from PATH instead of Cargo.toml and bump it there: a package.json or a file only holding the version. Cargo.toml is kept in step if it exists.")
+             .takes_value(true))
+        .arg(Arg::with_name("max-package-size")
+             .long("max-package-size")
+             .value_name("KIB")
+             .help("Warn if the packaged crate is bigger than this, uncompressed. [default: 10240]")
+             .takes_value(true))
+        .arg(Arg::with_name("strict")
+             .long("strict")
+             .help("Abort instead of warning if the packaged crate is too big or contains build artifacts."))
+        .arg(Arg::with_name("no-release-exit-code")
+             .long("no-release-exit-code")
+             .help("Exit with 10 instead of 0 if there is nothing to release."))
+        .arg(Arg::with_name("package-only")
+             .long("package-only")
+             .help("Only build the .crate of the current state and print its path. Nothing is bumped, committed, tagged or published.")
+             .conflicts_with_all(&["release-only", "set-version", "force-bump", "first-release"]))
+        .arg(Arg::with_name("release-only")
+             .long("release-only")
+             .value_name("TAG")
+             .help("Only create the GitHub release for TAG, which must already be pushed. Skips the version bump, changelog, commit, tag and push.")
+             .takes_value(true)
+             .conflicts_with_all(&["set-version", "force-bump", "prerelease", "release-final", "first-release", "build-metadata"]))
+        .arg(Arg::with_name("strict-history")
+             .long("strict-history")
+             .help("Abort if HEAD doesn't descend from the latest release tag, instead of only warning."))
+        .arg(Arg::with_name("build-metadata")
+             .long("build-metadata")
+             .help("Append build metadata to the new version, e.g. `sha.{sha}` releases 1.2.0+sha.1a2b3c4. {sha} is the short id of the released commit.")
+             .value_name("METADATA")
+             .takes_value(true))
+        .arg(Arg::with_name("quiet")
+             .short("q")
+             .long("quiet")
+             .help("Only print warnings and errors.")
+             .conflicts_with("verbose"))
+        .arg(Arg::with_name("verbose")
+             .short("v")
+             .long("verbose")
+             .help("Print debug details, like how each commit was classified."))
         .arg(Arg::with_name("path")
              .short("p")
              .long("path")
@@ -338,106 +918,35 @@ fn main() {
              .takes_value(true))
         .get_matches();
 
-    let config = assemble_configuration(clap_args);
-
-    let branch = current_branch(&config.repository)
-        .unwrap_or_else(|| print_exit!("Could not determine current branch."));
-
-    if !is_release_branch(&branch, &config.branch) {
-        println!("Current branch is '{}', releases are only done from branch '{}'", branch, config.branch);
-        println!("No release done from a pull request either.");
-        process::exit(0);
-    }
-
-    //Before we actually start, we do perform some preflight checks
-    //Here we check if everything is in place to do a GitHub release and a
-    //release on crates.io.
-    //The important bit is, if something's missing, we do not abort since the user can still do all
-    //other things except publishing
-
-    logger::stdout("Performing preflight checks now");
-    let warnings = preflight::check(&config);
-
-    if warnings.is_empty() {
-        logger::stdout("Checks done. Everything is ok");
-    }
-
-    for warning in warnings {
-        logger::warn(format!(">> {}", warning));
+    let print_version = clap_args.is_present("print-version");
+    logger::set_structured_output(clap_args.value_of("output") == Some("json") || print_version);
+    if clap_args.is_present("quiet") {
+        logger::set_verbosity(Verbosity::Quiet);
+    } else if clap_args.is_present("verbose") {
+        logger::set_verbosity(Verbosity::Verbose);
     }
+    logger::stdout("semantic.rs 🚀");
 
-    if config.release_mode && ci_env_set() {
-        let build_run = Build::from_env()
-            .unwrap_or_else(|e| print_exit!("CI mode, but can't check other builds. Error: {:?}", e));
 
-        if !build_run.is_leader() {
-            println!("Not the build leader. Nothing to do. Bye.");
-            process::exit(0);
-        }
-
-        println!("I am the build leader. Waiting for other jobs to finish.");
-        match build_run.wait_for_others() {
-            Ok(()) => println!("Other jobs finished and succeeded. Doing my work now."),
-            Err(travis_after_all::Error::FailedBuilds) => {
-                print_exit!("Some builds failed. Stopping here.");
-            },
-            Err(e) => print_exit!("Waiting for other builds failed. Reason: {:?}", e),
-        }
-    }
-
-    let version = toml_file::read_from_file(&config.repository_path)
-        .unwrap_or_else(|err| print_exit!("Reading `Cargo.toml` failed: {:?}", err));
-
-    let version = Version::parse(&version).expect("Not a valid version");
-    logger::stdout(format!("Current version: {}", version.to_string()));
-
-    logger::stdout("Analyzing commits");
-
-    let bump = git::version_bump_since_latest(&config.repository);
-    if config.write_mode {
-        logger::stdout(format!("Commits analyzed. Bump will be {:?}", bump));
-    } else {
-        logger::stdout(format!("Commits analyzed. Bump would be {:?}", bump));
+    let distinguish_no_release = clap_args.is_present("no-release-exit-code");
+    let print_config = clap_args.is_present("config-print");
+    let config = assemble_configuration(clap_args);
+    if print_config {
+        logger::result(config.to_json().pretty().to_string());
+        process::exit(exit_code::SUCCESS);
     }
-    let new_version = match version_bump(&version, bump) {
-        Some(new_version) => new_version.to_string(),
-            None => {
-                logger::stdout("No version bump. Nothing to do.");
-                process::exit(0);
+    match semantic_rs::run(&config) {
+        Ok(outcome) => {
+            if print_version {
+                print_next_version(&outcome, distinguish_no_release);
+            } else {
+                print_report(&outcome);
             }
-    };
-
-    if !config.write_mode {
-        let changelog = generate_changelog(&config.repository_path, &version, &new_version);
-        print_changelog(&changelog);
-    } else {
-        logger::stdout(format!("New version: {}", new_version));
-
-        toml_file::write_new_version(&config.repository_path, &new_version)
-            .unwrap_or_else(|err| print_exit!("Writing `Cargo.toml` failed: {:?}", err));
-
-        write_changelog(&config.repository_path, &version, &new_version);
-        package_crate(&config, &config.repository_path, &new_version);
-
-        logger::stdout("Creating annotated git tag");
-        let tag_message = changelog::generate(&config.repository_path, &version.to_string(), &new_version)
-            .unwrap_or_else(|err| print_exit!("Can't generate changelog: {:?}", err));
-
-        let tag_name = format!("v{}", new_version);
-        git::tag(&config, &tag_name, &tag_message)
-            .unwrap_or_else(|err| print_exit!("Failed to create git tag: {:?}", err));
-
-        if config.release_mode && config.can_push() {
-            push_to_github(&config, &tag_name);
-        }
-
-        if config.release_mode && config.can_release_to_github() {
-            release_on_github(&config, &tag_message, &tag_name);
-        }
-
-        if config.release_mode && config.can_release_to_cratesio() {
-            release_on_cratesio(&config);
-            println!("{} v{} is released. 🚀🚀🚀", config.repository_name.unwrap(), new_version);
-        }
+            process::exit(exit_code::for_outcome(&outcome, distinguish_no_release));
+        },
+        Err(err) => {
+            logger::stderr(format!("{}", err));
+            process::exit(exit_code::for_error(&err));
+        },
     }
 }
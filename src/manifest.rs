@@ -0,0 +1,198 @@
+use regex::Regex;
+use rustc_serialize::json::Json;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use toml_file::{self, TomlError};
+
+/// Where the version of a release is read from and written back to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VersionSource {
+    /// `Cargo.toml` in the given directory, including its workspace members.
+    Cargo(PathBuf),
+    /// A file holding nothing but the version, like `version.txt`.
+    Plain(PathBuf),
+    /// The top-level `version` of a `package.json`.
+    PackageJson(PathBuf),
+}
+
+impl VersionSource {
+    /// Picks the source by the name of `path`: `Cargo.toml` and `package.json`
+    /// are read as such, every other file is expected to only contain the version.
+    pub fn from_path(path: &Path) -> VersionSource {
+        match path.file_name().and_then(|name| name.to_str()) {
+            Some("Cargo.toml") => VersionSource::Cargo(path.parent().map(|dir| dir.to_path_buf()).unwrap_or(PathBuf::new())),
+            Some("package.json") => VersionSource::PackageJson(path.to_path_buf()),
+            _ => VersionSource::Plain(path.to_path_buf()),
+        }
+    }
+
+    /// The file the version is kept in, for messages and the release commit.
+    pub fn file(&self) -> PathBuf {
+        match *self {
+            VersionSource::Cargo(ref dir) => dir.join("Cargo.toml"),
+            VersionSource::Plain(ref path) | VersionSource::PackageJson(ref path) => path.clone(),
+        }
+    }
+
+    pub fn read(&self) -> Result<String, TomlError> {
+        match *self {
+            VersionSource::Cargo(ref dir) => toml_file::read_from_file(&dir.to_string_lossy()),
+            VersionSource::Plain(ref path) => {
+                let content = try!(read_file(path));
+                let version = content.trim();
+                if version.is_empty() {
+                    Err(TomlError::Parse("The version file is empty"))
+                } else {
+                    Ok(version.to_owned())
+                }
+            },
+            VersionSource::PackageJson(ref path) => {
+                let content = try!(read_file(path));
+                package_json_version(&content).ok_or(TomlError::Parse("No version field found"))
+            },
+        }
+    }
+
+    pub fn write(&self, new_version: &str) -> Result<(), TomlError> {
+        match *self {
+            VersionSource::Cargo(ref dir) => toml_file::write_new_version(&dir.to_string_lossy(), new_version),
+            VersionSource::Plain(ref path) => write_file(path, &format!("{}\n", new_version)),
+            VersionSource::PackageJson(ref path) => {
+                let content = try!(read_file(path));
+                match package_json_with_new_version(&content, new_version) {
+                    Some(updated) => write_file(path, &updated),
+                    None => Err(TomlError::Parse("No version field found")),
+                }
+            },
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, TomlError> {
+    let mut content = String::new();
+    let mut file = try!(File::open(path).map_err(TomlError::Io));
+    try!(file.read_to_string(&mut content).map_err(TomlError::Io));
+    Ok(content)
+}
+
+fn write_file(path: &Path, content: &str) -> Result<(), TomlError> {
+    let mut file = try!(File::create(path).map_err(TomlError::Io));
+    file.write_all(content.as_bytes()).map_err(TomlError::Io)
+}
+
+pub fn package_json_version(content: &str) -> Option<String> {
+    Json::from_str(content).ok()
+        .and_then(|json| json.find("version").and_then(|version| version.as_string()).map(|v| v.to_owned()))
+}
+
+/// How deeply nested in JSON objects and arrays `position` is, ignoring brackets in strings.
+fn nesting_at(content: &str, position: usize) -> usize {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for c in content[..position].chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => depth -= 1,
+            _ => {}
+        }
+    }
+    depth
+}
+
+/// Only the top-level `version` is replaced, the formatting of the file is kept.
+pub fn package_json_with_new_version(content: &str, new_version: &str) -> Option<String> {
+    if package_json_version(content).is_none() {
+        return None;
+    }
+
+    // Dependencies may have a `version` of their own
+    let re = Regex::new(r#""version"\s*:\s*"([^"]*)""#).unwrap();
+    re.captures_iter(content)
+        .filter(|caps| caps.pos(0).map(|(start, _)| nesting_at(content, start) == 1).unwrap_or(false))
+        .filter_map(|caps| caps.pos(1))
+        .next()
+        .map(|(start, end)| format!("{}{}{}", &content[..start], new_version, &content[end..]))
+}
+
+/// Replaces what the first capture group of `pattern` matches with `new_version`, everywhere.
+/// `None` if `pattern` doesn't match at all.
+pub fn replace_versions(content: &str, pattern: &Regex, new_version: &str) -> Option<String> {
+    if !pattern.is_match(content) {
+        return None;
+    }
+
+    let mut updated = String::new();
+    let mut last = 0;
+    for (start, end) in pattern.captures_iter(content).filter_map(|caps| caps.pos(1)) {
+        updated.push_str(&content[last..start]);
+        updated.push_str(new_version);
+        last = end;
+    }
+    updated.push_str(&content[last..]);
+    Some(updated)
+}
+
+/// Rewrites the versions `pattern` captures in the file at `path`.
+/// Fails with `TomlError::Parse` if the pattern doesn't match.
+pub fn update_versions_in(path: &Path, pattern: &Regex, new_version: &str) -> Result<(), TomlError> {
+    let content = try!(read_file(path));
+    match replace_versions(&content, pattern, new_version) {
+        Some(updated) => write_file(path, &updated),
+        None => Err(TomlError::Parse("The pattern doesn't match")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn replaces_captured_versions() {
+        let pattern = Regex::new(r#"semantic-rs = "([0-9.]+)""#).unwrap();
+        let readme = "```toml\n[dependencies]\nsemantic-rs = \"1.0.0\"\n```\n\nOr `semantic-rs = \"1.0\"`.\n";
+
+        assert_eq!(Some("```toml\n[dependencies]\nsemantic-rs = \"1.1.0\"\n```\n\nOr `semantic-rs = \"1.1.0\"`.\n".to_owned()),
+                   replace_versions(readme, &pattern, "1.1.0"));
+        assert_eq!(None, replace_versions("No version here", &pattern, "1.1.0"));
+    }
+
+    #[test]
+    fn source_by_file_name() {
+        assert_eq!(VersionSource::Cargo(PathBuf::from("crates/cli")), VersionSource::from_path(Path::new("crates/cli/Cargo.toml")));
+        assert_eq!(VersionSource::PackageJson(PathBuf::from("web/package.json")), VersionSource::from_path(Path::new("web/package.json")));
+        assert_eq!(VersionSource::Plain(PathBuf::from("version.txt")), VersionSource::from_path(Path::new("version.txt")));
+    }
+
+    #[test]
+    fn package_json() {
+        let content = r#"{
+  "name": "fixture",
+  "engines": { "node": ">=8" },
+  "dependencies": { "left-pad": { "version": "1.0.0" } },
+  "version": "1.0.0"
+}
+"#;
+        assert_eq!(Some("1.0.0".to_owned()), package_json_version(content));
+
+        let updated = package_json_with_new_version(content, "1.1.0").unwrap();
+        assert_eq!(Some("1.1.0".to_owned()), package_json_version(&updated));
+        assert_eq!(content.replace("\"version\": \"1.0.0\"\n}", "\"version\": \"1.1.0\"\n}"), updated);
+        assert!(updated.contains(r#"{ "version": "1.0.0" }"#));
+
+        assert_eq!(None, package_json_with_new_version(r#"{ "name": "fixture" }"#, "1.1.0"));
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use hyper::header::{ContentType, UserAgent};
+use rustc_serialize::json::Json;
+use error::Error;
+use super::USERAGENT;
+use utils::https_client;
+
+/// Longest changelog excerpt posted to Slack, longer ones link to the release instead.
+const SLACK_EXCERPT_LENGTH: usize = 1500;
+
+/// Builds the payload sent to the webhook after a release:
+///
+/// ```json
+/// {
+///   "repository": "user/repo",
+///   "version": "1.2.0",
+///   "tag": "v1.2.0",
+///   "changelog": "## v1.2.0 (2017-03-01)\n..."
+/// }
+/// ```
+///
+/// `repository` is `null` if it is unknown, `changelog` is an empty string
+/// if no changelog was generated. New keys may be added, existing ones won't change.
+pub fn webhook_payload(repository: Option<&str>, version: &str, tag: &str, changelog: &str) -> Json {
+    let mut payload = BTreeMap::new();
+    payload.insert("repository".to_owned(), match repository {
+        Some(repository) => Json::String(repository.to_owned()),
+        None => Json::Null,
+    });
+    payload.insert("version".to_owned(), Json::String(version.to_owned()));
+    payload.insert("tag".to_owned(), Json::String(tag.to_owned()));
+    payload.insert("changelog".to_owned(), Json::String(changelog.to_owned()));
+    Json::Object(payload)
+}
+
+/// Slack doesn't render markdown headers, so they are turned into bold lines.
+/// Long changelogs are cut at a line break and end in "…".
+fn slack_excerpt(changelog: &str) -> String {
+    let lines = changelog.lines()
+        .filter(|line| !line.starts_with("<a name="))
+        .map(|line| {
+            if line.starts_with('#') {
+                format!("*{}*", line.trim_left_matches('#').trim())
+            } else {
+                line.to_owned()
+            }
+        });
+
+    let mut excerpt = String::new();
+    for line in lines {
+        if excerpt.len() + line.len() + 1 > SLACK_EXCERPT_LENGTH {
+            excerpt.push_str("…\n");
+            break;
+        }
+        excerpt.push_str(&line);
+        excerpt.push('\n');
+    }
+    excerpt.trim().to_owned()
+}
+
+/// The message for a Slack incoming webhook, announcing `tag` with an excerpt of the changelog.
+pub fn slack_payload(repository: Option<&str>, tag: &str, changelog: &str, release_url: Option<&str>) -> Json {
+    let mut text = match repository {
+        Some(repository) => format!("*{} {}* has been released", repository, tag),
+        None => format!("*{}* has been released", tag),
+    };
+
+    let excerpt = slack_excerpt(changelog);
+    if !excerpt.is_empty() {
+        text.push_str("\n\n");
+        text.push_str(&excerpt);
+    }
+    if let Some(url) = release_url {
+        text.push_str(&format!("\n\n<{}|Full release notes>", url));
+    }
+
+    let mut payload = BTreeMap::new();
+    payload.insert("text".to_owned(), Json::String(text));
+    Json::Object(payload)
+}
+
+/// POSTs `payload` as JSON to `url`.
+pub fn send(url: &str, payload: &Json) -> Result<(), Error> {
+    let body = payload.to_string();
+
+    let client = https_client();
+    let mut response = try!(client.post(url)
+        .header(ContentType::json())
+        .header(UserAgent(USERAGENT.to_owned()))
+        .body(&body[..])
+        .send());
+
+    if response.status.is_success() {
+        Ok(())
+    } else {
+        let mut message = String::new();
+        let _ = response.read_to_string(&mut message);
+        Err(Error::Api(format!("Webhook responded with {}: {}", response.status, message)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn payload_schema() {
+        let payload = webhook_payload(Some("user/repo"), "1.2.0", "v1.2.0", "* Fix a crash");
+        assert_eq!(r#"{"changelog":"* Fix a crash","repository":"user/repo","tag":"v1.2.0","version":"1.2.0"}"#,
+                   payload.to_string());
+
+        let payload = webhook_payload(None, "1.2.0", "v1.2.0", "");
+        assert_eq!(r#"{"changelog":"","repository":null,"tag":"v1.2.0","version":"1.2.0"}"#,
+                   payload.to_string());
+    }
+
+    #[test]
+    fn slack_message() {
+        let changelog = "<a name=\"v1.2.0\"></a>\n## v1.2.0 (2017-03-01)\n\n### Bug Fixes\n\n* Fix a crash (1111111)\n";
+        let payload = slack_payload(Some("user/repo"), "v1.2.0", changelog,
+                                    Some("https://github.com/user/repo/releases/tag/v1.2.0"));
+
+        let expected = "*user/repo v1.2.0* has been released\n\n*v1.2.0 (2017-03-01)*\n\n*Bug Fixes*\n\n* Fix a crash (1111111)\n\n\
+                        <https://github.com/user/repo/releases/tag/v1.2.0|Full release notes>";
+        assert_eq!(Some(expected), payload.find("text").and_then(|text| text.as_string()));
+    }
+
+    #[test]
+    fn long_changelogs_are_truncated() {
+        let changelog = (0..500).map(|i| format!("* Change number {}", i)).collect::<Vec<_>>().join("\n");
+        let excerpt = slack_excerpt(&changelog);
+
+        assert!(excerpt.len() <= SLACK_EXCERPT_LENGTH + "…".len());
+        assert!(excerpt.starts_with("* Change number 0\n"));
+        assert!(excerpt.ends_with("…"));
+    }
+}
@@ -1,18 +1,33 @@
-use config::Config;
+use cargo;
+use config::{Config, Provider};
 
 pub fn check(config: &Config) -> Vec<String> {
     let mut warnings = vec!();
 
-    if config.gh_token.is_none() {
-        warnings.push("The GH_TOKEN environment variable is not configured".into());
+    match config.provider {
+        Provider::GitHub if config.github_release && config.gh_token.is_none() => {
+            warnings.push("The GH_TOKEN environment variable is not configured".into());
+        },
+        Provider::GitLab if config.gitlab_token.is_none() => {
+            warnings.push("The GITLAB_TOKEN environment variable is not configured. Cannot create release on GitLab".into());
+        },
+        Provider::Bitbucket if config.bitbucket_token.is_none() => {
+            warnings.push("The BITBUCKET_TOKEN environment variable is not configured. Cannot upload release notes to Bitbucket".into());
+        },
+        Provider::None if !config.assets.is_empty() => {
+            warnings.push("Assets can only be uploaded to GitHub releases, they are skipped with --provider none".into());
+        },
+        _ => {}
     }
 
-    if config.cargo_token.is_none() {
-        warnings.push("The CARGO_TOKEN environment variable is not configured. Cannot create release on crates.io".into());
+    if config.should_publish() && config.cargo_token.is_none() {
+        let registry = config.registry.as_ref().map(|r| &r[..]);
+        warnings.push(format!("The {} environment variable is not configured. Cannot create release on {}",
+                              cargo::token_env_var(registry), config.registry_name()));
     }
 
     if let Err(ref err) = config.remote {
-        warnings.push(format!("Could not determine the origin remote url: {:?}", err));
+        warnings.push(format!("Could not determine the remote url: {:?}", err));
         warnings.push("semantic-rs can't push changes or create a release on GitHub".into());
     }
 
@@ -0,0 +1,983 @@
+use regex::Regex;
+use semver::{Identifier, Version};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Duration;
+use travis_after_all::{self, Build};
+
+use bitbucket;
+use cargo;
+use changelog::{self, Entry};
+use ci;
+use commit_analyzer::{self, CommitType};
+use config::Config;
+use error::Error;
+use git;
+use github;
+use gitlab;
+use hooks;
+use logger;
+use manifest;
+use notify;
+use preflight;
+use report::Outcome;
+use retry;
+use toml_file::{self, TomlError};
+use utils::{self, glob_to_regex, render_template};
+
+/// The next version for `bump`. With a pre-release label the result is a pre-release
+/// of the next version, e.g. `1.2.0-rc.1`. Further pre-releases of the same version
+/// only increment the counter, unless the commits ask for a bigger bump.
+/// With `zero_ver` a 0.x version is bumped one level lower: breaking changes bump the minor,
+/// features the patch version.
+fn version_bump(version: &Version, bump: CommitType, prerelease: Option<&str>, zero_ver: bool) -> Option<Version> {
+    if bump == CommitType::Unknown || bump == CommitType::None {
+        return None;
+    }
+
+    let bump = match bump {
+        CommitType::Major if zero_ver && version.major == 0 => CommitType::Minor,
+        CommitType::Minor if zero_ver && version.major == 0 => CommitType::Patch,
+        bump => bump,
+    };
+
+    let mut next = version.clone();
+    next.build = vec![];
+
+    // A pre-release of 1.2.0 already stands for a minor bump, so a fix doesn't bump it again
+    let already_bumped = !version.pre.is_empty() && bump <= released_bump(version);
+    if already_bumped {
+        next.pre = vec![];
+    } else {
+        match bump {
+            CommitType::Patch => next.increment_patch(),
+            CommitType::Minor => next.increment_minor(),
+            _ => next.increment_major(),
+        }
+    }
+
+    if let Some(label) = prerelease {
+        let counter = match (already_bumped, prerelease_counter(version, label)) {
+            (true, Some(counter)) => counter + 1,
+            _ => 1,
+        };
+        next.pre = vec![Identifier::AlphaNumeric(label.to_owned()), Identifier::Numeric(counter)];
+    }
+
+    Some(next)
+}
+
+/// The bump a version stands for compared to the release before it.
+fn released_bump(version: &Version) -> CommitType {
+    if version.patch > 0 {
+        CommitType::Patch
+    } else if version.minor > 0 {
+        CommitType::Minor
+    } else {
+        CommitType::Major
+    }
+}
+
+/// The counter of a pre-release like `1.2.0-rc.3`, if it is on the `label` channel.
+fn prerelease_counter(version: &Version, label: &str) -> Option<u64> {
+    match (version.pre.get(0), version.pre.get(1), version.pre.len()) {
+        (Some(&Identifier::AlphaNumeric(ref l)), Some(&Identifier::Numeric(counter)), 2) if l == label => Some(counter),
+        _ => None,
+    }
+}
+
+/// Appends the build metadata rendered from `template` to `version`, e.g. `1.2.0+sha.1a2b3c4`.
+/// Semver ignores build metadata when ordering versions, so it never affects the bump.
+fn with_build_metadata(version: &str, template: &str, sha: &str) -> Result<String, Error> {
+    let metadata = render_template(template, &[("sha", sha)]);
+    let valid = metadata.split('.').all(|identifier| {
+        !identifier.is_empty() && identifier.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    });
+
+    if valid {
+        Ok(format!("{}+{}", version, metadata))
+    } else {
+        Err(Error::Release(format!("`{}` is not valid build metadata, use dot-separated letters, digits and hyphens", metadata)))
+    }
+}
+
+/// The final release of a pre-release version, `1.2.0-rc.2` becomes `1.2.0`.
+fn final_version(version: &Version) -> Version {
+    let mut version = version.clone();
+    version.pre = vec![];
+    version.build = vec![];
+    version
+}
+
+/// Picks the higher of the manifest version and the latest release tag.
+fn reconcile_with_tags(config: &Config, manifest_version: Version) -> Version {
+    let tag_version = match git::latest_version_tag(&config.repository, &config.tag_prefix) {
+        Some(tag_version) => tag_version,
+        None => return manifest_version,
+    };
+
+    if tag_version == manifest_version {
+        return manifest_version;
+    }
+
+    logger::warn(format!("Cargo.toml is at version {}, but the latest release tag is {}",
+                         manifest_version, config.tag_name(&tag_version.to_string())));
+    if tag_version > manifest_version {
+        logger::warn(format!("Continuing from the tagged version {}", tag_version));
+        tag_version
+    } else {
+        manifest_version
+    }
+}
+
+/// The version released last, from the version source and, if asked to, the release tags.
+fn current_version(config: &Config) -> Result<Version, Error> {
+    let version_source = config.version_source();
+    let version_file = version_source.file();
+    let version = try!(version_source.read()
+        .map_err(|err| Error::Manifest(format!("Reading `{}` failed: {:?}", version_file.display(), err))));
+
+    let version = try!(Version::parse(&version)
+        .map_err(|err| Error::Manifest(format!("`{}` has an invalid version '{}': {:?}", version_file.display(), version, err))));
+    if config.version_from_tags {
+        Ok(reconcile_with_tags(&config, version))
+    } else {
+        Ok(version)
+    }
+}
+
+fn is_release_branch(config: &Config) -> bool {
+    if config.pull_request {
+        return false;
+    }
+
+    config.release_branches.iter().any(|pattern| glob_to_regex(pattern).is_match(&config.branch))
+}
+
+/// Releasing on top of rewritten history makes for a confusing version and changelog.
+fn check_history(config: &Config, latest_tag: &str) -> Result<(), Error> {
+    let history = try!(git::history_since(&config.repository, latest_tag)
+        .map_err(|err| Error::Repository(format!("Checking the history since {} failed: {}", latest_tag, err))));
+
+    let problem = match history {
+        git::History::Linear => return Ok(()),
+        git::History::Diverged(base) => format!("HEAD doesn't descend from the latest release tag {}, the history diverged at {:.7}",
+                                                latest_tag, base),
+        git::History::Unrelated => format!("HEAD and the latest release tag {} have no history in common", latest_tag),
+    };
+
+    if config.strict_history {
+        Err(Error::Release(format!("{}. Stopping because of --strict-history.", problem)))
+    } else {
+        logger::warn(format!("{}. Was the history rewritten?", problem));
+        Ok(())
+    }
+}
+
+/// A manually edited manifest could make us release a version that exists already,
+/// which would only fail once the tag is pushed or the GitHub release is created.
+fn check_new_version(config: &Config, new_version: &str) -> Result<(), Error> {
+    let tag_name = config.tag_name(new_version);
+    if git::tag_exists(&config.repository, &tag_name) {
+        return Err(Error::Release(format!("The tag {} exists already, not releasing {} again", tag_name, new_version)));
+    }
+
+    let new_version = try!(Version::parse(new_version)
+        .map_err(|err| Error::Release(format!("{} is not a valid version: {:?}", new_version, err))));
+    match git::latest_version_tag(&config.repository, &config.tag_prefix) {
+        Some(ref latest) if new_version <= *latest => {
+            Err(Error::Release(format!("The new version {} is not greater than the latest release {}",
+                                       new_version, config.tag_name(&latest.to_string()))))
+        },
+        _ => Ok(()),
+    }
+}
+
+fn push_to_github(config: &Config, tag_name: &str) -> Result<(), Error> {
+    let remote = config.remote.as_ref().unwrap();
+    let progress = logger::progress(format!("Pushing new commit and tag to {}", remote));
+    try!(git::push(&config, &tag_name)
+        .map_err(|err| Error::Repository(format!("Failed to push git: {}", err))));
+    progress.finish(format!("Pushed new commit and tag to {}", remote));
+
+    if config.release_mode && config.can_release_to_github() && github::can_release(config) {
+        try!(wait_for_github_tag(config, tag_name));
+    }
+    Ok(())
+}
+
+fn wait_for_github_tag(config: &Config, tag_name: &str) -> Result<(), Error> {
+    if try!(github::tag_exists(config, tag_name)) {
+        return Ok(());
+    }
+
+    let progress = logger::progress(format!("Waiting for GitHub to store the tag {}", tag_name));
+    let timeout = Duration::from_secs(config.tag_wait_timeout);
+    if try!(github::wait_for_tag(config, tag_name, timeout)) {
+        progress.finish(format!("GitHub stored the tag {}", tag_name));
+        Ok(())
+    } else {
+        Err(Error::Api(format!("The tag {} didn't show up on GitHub within {} seconds", tag_name, config.tag_wait_timeout)))
+    }
+}
+
+/// Returns whether the release was created, the step is skipped without a GitHub repository.
+fn release_on_github(config: &Config, tag_message: &str, tag_name: &str, new_version: &Version) -> Result<bool, Error> {
+    if github::can_release(&config) {
+        logger::stdout("Creating GitHub release");
+        let prerelease = config.is_github_prerelease(new_version);
+        // Without a changelog of our own the generated notes make up the whole body
+        let body = if config.github_generate_notes && !config.generate_changelog { "" } else { tag_message };
+        let release_id = try!(retry::with_backoff("Creating the GitHub release", config.max_attempts, Error::is_transient, || {
+            github::release(&config, &tag_name, body, prerelease)
+        }).map_err(|err| Error::Api(format!("Failed to create GitHub release: {}", err))));
+        upload_assets(config, release_id);
+        Ok(true)
+    } else {
+        logger::stdout("Project not hosted on GitHub. Skipping release step");
+        Ok(false)
+    }
+}
+
+/// Creates the GitHub release for a tag pushed by someone else,
+/// with the tag message or the tag's changelog section as release notes.
+fn release_existing_tag(config: &Config, tag_name: &str) -> Result<Outcome, Error> {
+    let mut outcome = Outcome::default();
+    outcome.tag_name = Some(tag_name.to_owned());
+
+    let version_name = if tag_name.starts_with(&config.tag_prefix[..]) {
+        &tag_name[config.tag_prefix.len()..]
+    } else {
+        tag_name
+    };
+    let version = try!(Version::parse(version_name)
+        .map_err(|_| Error::Release(format!("The tag {} doesn't name a version", tag_name))));
+    outcome.new_version = Some(version.to_string());
+
+    let tag_message = try!(git::tag_message(&config.repository, tag_name)
+        .map_err(|err| Error::Repository(format!("Can't read the tag {}: {}", tag_name, err))));
+    let notes = match tag_message {
+        Some(ref message) if !message.trim().is_empty() => message.trim().to_owned(),
+        _ => {
+            let changelog_path = Path::new(&config.repository_path).join(&config.changelog_path);
+            let mut existing = String::new();
+            if let Ok(mut file) = File::open(&changelog_path) {
+                let _ = file.read_to_string(&mut existing);
+            }
+            changelog::section_for(&existing, &version.to_string())
+                .unwrap_or_else(|| format!("Release {}", tag_name))
+        }
+    };
+    outcome.changelog = Some(notes.clone());
+
+    if !config.release_mode {
+        logger::stdout(format!("Would create the GitHub release for {}", tag_name));
+        print_changelog(config, &notes);
+        return Ok(outcome);
+    }
+
+    if !config.can_release_to_github() || !github::can_release(config) {
+        return Err(Error::Release("--release-only needs a GitHub remote and the GH_TOKEN environment variable".into()));
+    }
+    let pushed = try!(github::tag_exists(config, tag_name));
+    if !pushed {
+        return Err(Error::Release(format!("The tag {} doesn't exist on GitHub, push it first", tag_name)));
+    }
+
+    outcome.released = try!(release_on_github(config, &notes, tag_name, &version));
+    Ok(outcome)
+}
+
+fn release_on_gitlab(config: &Config, tag_message: &str, tag_name: &str) -> Result<bool, Error> {
+    if gitlab::can_release(&config) {
+        logger::stdout("Creating GitLab release");
+        try!(gitlab::release(&config, &tag_name, &tag_message)
+            .map_err(|err| Error::Api(format!("Failed to create GitLab release: {}", err))));
+        if !config.assets.is_empty() {
+            logger::warn("Uploading assets is only supported for GitHub releases. Skipping assets.");
+        }
+        Ok(true)
+    } else {
+        logger::stdout("Could not determine the GitLab project. Skipping release step");
+        Ok(false)
+    }
+}
+
+fn release_on_bitbucket(config: &Config, tag_message: &str, tag_name: &str) -> Result<(), Error> {
+    logger::stdout("Uploading release notes to Bitbucket");
+    try!(bitbucket::release(&config, &tag_name, &tag_message)
+        .map_err(|err| Error::Api(format!("Failed to upload release notes to Bitbucket: {}", err))));
+    if !config.assets.is_empty() {
+        logger::warn("Uploading assets is only supported for GitHub releases. Skipping assets.");
+    }
+    Ok(())
+}
+
+/// A failing webhook doesn't undo the release, so it is only reported.
+fn notify_webhook(config: &Config, url: &str, new_version: &str, tag_name: &str, changelog: &str) {
+    logger::stdout("Notifying the webhook");
+    let repository = match (config.user.as_ref(), config.repository_name.as_ref()) {
+        (Some(user), Some(repo)) => Some(format!("{}/{}", user, repo)),
+        _ => None,
+    };
+    let payload = notify::webhook_payload(repository.as_ref().map(|r| &r[..]), new_version, tag_name, changelog);
+
+    let result = retry::with_backoff("Notifying the webhook", config.max_attempts, |_| true, || {
+        notify::send(url, &payload)
+    });
+    if let Err(err) = result {
+        logger::warn(format!("Notifying the webhook failed: {}", err));
+    }
+}
+
+/// `release_url` links the release, if one was created on GitHub.
+fn notify_slack(config: &Config, url: &str, tag_name: &str, changelog: &str, release_url: Option<String>) {
+    logger::stdout("Announcing the release on Slack");
+    let repository = match (config.user.as_ref(), config.repository_name.as_ref()) {
+        (Some(user), Some(repo)) => Some(format!("{}/{}", user, repo)),
+        _ => None,
+    };
+    let payload = notify::slack_payload(repository.as_ref().map(|r| &r[..]), tag_name, changelog,
+                                        release_url.as_ref().map(|u| &u[..]));
+
+    let result = retry::with_backoff("Announcing the release on Slack", config.max_attempts, |_| true, || {
+        notify::send(url, &payload)
+    });
+    if let Err(err) = result {
+        logger::warn(format!("Announcing the release on Slack failed: {}", err));
+    }
+}
+
+fn upload_assets(config: &Config, release_id: u64) {
+    let mut failed = 0;
+    for asset in &config.assets {
+        let content_type = github::content_type(Path::new(asset));
+        match github::upload_asset(config, release_id, asset, content_type) {
+            Ok(()) => logger::stdout(format!("Uploaded {} ({})", asset, content_type)),
+            Err(err) => {
+                failed += 1;
+                logger::stderr(format!("Failed to upload {}: {:?}", asset, err));
+            }
+        }
+    }
+
+    if failed > 0 {
+        logger::warn(format!("{} of {} assets could not be uploaded", failed, config.assets.len()));
+    }
+}
+
+fn release_on_cratesio(config: &Config, new_version: &str) -> Result<(), Error> {
+    let packages = match config.package_dir {
+        Some(_) => vec![],
+        None => try!(toml_file::workspace_packages(&config.repository_path)
+            .map_err(|err| Error::Manifest(format!("Reading the workspace members failed: {:?}", err)))),
+    };
+
+    if packages.len() <= 1 {
+        logger::stdout(format!("Publishing crate on {}", config.registry_name()));
+        return publish_crate(config, &config.manifest_dir());
+    }
+
+    let order = try!(cargo::publish_order(&packages));
+    let order = order.into_iter().filter(|package| package.publish).collect::<Vec<_>>();
+
+    for (i, package) in order.iter().enumerate() {
+        logger::stdout(format!("Publishing {} on {} ({}/{})", package.name, config.registry_name(), i + 1, order.len()));
+        let manifest_dir = Path::new(&config.repository_path).join(&package.dir);
+        try!(publish_crate(config, &manifest_dir.to_string_lossy()));
+
+        // Crates published next may depend on this one, they'd fail until crates.io knows about it.
+        if config.registry.is_none() && i + 1 < order.len() {
+            let progress = logger::progress(format!("Waiting for {} {} to show up on crates.io", package.name, new_version));
+            let timeout = Duration::from_secs(config.publish_timeout);
+            match cargo::wait_for_version_published(&package.name, new_version, timeout) {
+                Ok(true) => progress.finish(format!("{} {} is available on crates.io", package.name, new_version)),
+                Ok(false) => {
+                    drop(progress);
+                    logger::warn(format!("{} {} is not visible on crates.io after {} seconds, publishing the next crate anyway",
+                                         package.name, new_version, config.publish_timeout));
+                },
+                Err(err) => {
+                    drop(progress);
+                    logger::warn(format!("Can't check whether {} {} is visible on crates.io: {}", package.name, new_version, err));
+                },
+            }
+        }
+    }
+    Ok(())
+}
+
+fn publish_crate(config: &Config, manifest_dir: &str) -> Result<(), Error> {
+    let registry = config.registry.as_ref().map(|r| &r[..]);
+    let what = format!("Publishing on {}", config.registry_name());
+    retry::with_backoff(&what, config.max_attempts, Error::is_transient, || {
+        cargo::publish(manifest_dir, &config.cargo_token.as_ref().unwrap(), registry, &config.cargo_options())
+    }).map_err(|err| Error::Publish(format!("Failed to publish on {}: {}", config.registry_name(), err)))
+}
+
+fn verify_github_token(config: &Config) -> Result<(), Error> {
+    logger::stdout("Checking the GitHub token before releasing");
+    github::check_token(config)
+        .map_err(|err| Error::Api(format!("Can't create the GitHub release, nothing was changed: {}", err)))
+}
+
+fn verify_crates_io(config: &Config, new_version: &str) -> Result<(), Error> {
+    logger::stdout("Checking crates.io before releasing");
+    let crates = match toml_file::read_package_name(&config.manifest_dir()) {
+        Ok(Some(name)) => vec![name],
+        Ok(None) => try!(toml_file::workspace_package_names(&config.manifest_dir())
+            .map_err(|err| Error::Manifest(format!("Reading the workspace members failed: {:?}", err)))),
+        Err(err) => return Err(Error::Manifest(format!("Reading `Cargo.toml` failed: {:?}", err))),
+    };
+
+    cargo::check_crates_io(&crates, new_version)
+        .map_err(|err| Error::Publish(format!("Can't publish to crates.io, nothing was changed: {}", err)))
+}
+
+fn generate_changelog(config: &Config, new_version: &String, entries: &[Entry]) -> Result<String, Error> {
+    logger::stdout(format!("New version would be: {}", new_version));
+    logger::stdout("Would write the following Changelog:");
+    let new_tag = config.tag_name(new_version);
+    changelog::generate(&config.repository_path, &new_tag, entries, &config.changelog_options())
+        .map_err(|err| Error::Changelog(format!("Generating Changelog failed: {}", err)))
+}
+
+fn write_changelog(config: &Config, new_version: &str, entries: &[Entry]) -> Result<(), Error> {
+    logger::stdout(format!("Writing {}", config.changelog_path));
+    let new_tag = config.tag_name(new_version);
+    changelog::write(&config.repository_path, &config.changelog_path, &new_tag, entries, &config.changelog_options())
+        .map_err(|err| Error::Changelog(format!("Writing Changelog failed: {}", err)))
+}
+
+/// Writes the notes of the new release alone to `--output-changelog`, e.g. to attach them elsewhere.
+fn write_release_notes(config: &Config, new_version: &str, entries: &[Entry]) -> Result<(), Error> {
+    let path = match config.changelog_output {
+        Some(ref path) => path,
+        None => return Ok(()),
+    };
+
+    let notes = try!(changelog::generate(&config.repository_path, &config.tag_name(new_version), entries, &config.changelog_options())
+        .map_err(|err| Error::Changelog(format!("Generating the release notes failed: {}", err))));
+    logger::stdout(format!("Writing the release notes to {}", path));
+    File::create(path)
+        .and_then(|mut file| file.write_all(notes.as_bytes()))
+        .map_err(|err| Error::Changelog(format!("Writing the release notes to {} failed: {}", path, err)))
+}
+
+fn print_changelog(config: &Config, changelog: &str) {
+    logger::stdout("====================================");
+    logger::stdout(changelog);
+    logger::stdout("====================================");
+    logger::stdout(format!("Would create {} git tag", config.tag_kind()));
+}
+
+fn update_version_replacements(config: &Config, new_version: &str) -> Result<(), Error> {
+    for &(ref file, ref pattern) in &config.version_replacements {
+        let re = try!(Regex::new(pattern)
+            .map_err(|err| Error::Manifest(format!("The version pattern `{}` is invalid: {}", pattern, err))));
+        let path = Path::new(&config.repository_path).join(file);
+
+        match manifest::update_versions_in(&path, &re, new_version) {
+            Ok(()) => logger::stdout(format!("Updated the version in {}", file)),
+            Err(TomlError::Parse(_)) => {
+                return Err(Error::Manifest(format!("The version pattern `{}` doesn't match anything in {}", pattern, file)));
+            },
+            Err(err) => return Err(Error::Manifest(format!("Updating the version in {} failed: {:?}", file, err))),
+        }
+    }
+    Ok(())
+}
+
+/// Reports what ends up in the crate, to catch accidentally packaged files before they are published.
+fn check_package(config: &Config, repository_path: &str) -> Result<(), Error> {
+    let files = match cargo::package_list(repository_path) {
+        Ok(files) => files,
+        Err(err) => {
+            logger::warn(format!("Could not list the packaged files: {}", err));
+            return Ok(());
+        }
+    };
+
+    let total = files.iter().map(|file| file.size).sum::<u64>();
+    logger::stdout(format!("The crate contains {} files, {} uncompressed. Largest files:", files.len(), cargo::format_size(total)));
+    for file in cargo::largest_files(&files, 5) {
+        logger::stdout(format!("  {:>10}  {}", cargo::format_size(file.size), file.path));
+    }
+
+    let warnings = cargo::package_warnings(&files, config.max_package_size);
+    for warning in &warnings {
+        logger::warn(format!(">> {}", warning));
+    }
+
+    if config.strict && !warnings.is_empty() {
+        return Err(Error::Cargo("The packaged crate doesn't look right, aborting because of --strict".into()));
+    }
+    Ok(())
+}
+
+/// Builds the `.crate` of the working tree as it is, for review before anything is released.
+fn package_current_state(config: &Config) -> Result<Outcome, Error> {
+    let mut outcome = Outcome::default();
+    let manifest_dir = config.manifest_dir();
+
+    let name = match toml_file::read_package_name(&manifest_dir) {
+        Ok(Some(name)) => name,
+        Ok(None) => return Err(Error::Manifest("`Cargo.toml` is a virtual manifest, select a crate with --package".into())),
+        Err(err) => return Err(Error::Manifest(format!("Reading `Cargo.toml` failed: {:?}", err))),
+    };
+    let version = try!(toml_file::read_from_file(&manifest_dir)
+        .map_err(|err| Error::Manifest(format!("Reading `Cargo.toml` failed: {:?}", err))));
+    outcome.current_version = Some(version.clone());
+
+    try!(check_package(config, &manifest_dir));
+
+    let progress = logger::progress(format!("Packaging {} {}", name, version));
+    try!(cargo::package(&manifest_dir, &config.cargo_options())
+        .map_err(|output| Error::Cargo(format!("`cargo package` failed:\n{}", output.trim_right()))));
+
+    let package = cargo::package_path(&config.repository_path, &name, &version);
+    progress.finish(format!("Packaged {}", package.display()));
+    outcome.package = Some(package.to_string_lossy().into_owned());
+    Ok(outcome)
+}
+
+/// Rebuilds the changelog with a section for every release tag. Nothing is committed.
+fn regenerate_changelog(config: &Config) -> Result<Outcome, Error> {
+    let mut outcome = Outcome::default();
+    let tags = git::version_tags(&config.repository, &config.tag_prefix).iter()
+        .map(|version| config.tag_name(&version.to_string()))
+        .collect::<Vec<_>>();
+
+    if tags.is_empty() {
+        logger::stdout("No release tags found, there is no changelog to regenerate");
+        return Ok(outcome);
+    }
+
+    if config.write_mode {
+        logger::stdout(format!("Regenerating {} from {} releases", config.changelog_path, tags.len()));
+        try!(changelog::regenerate(&config.repository_path, &config.changelog_path, &tags, &config.changelog_options())
+            .map_err(|err| Error::Changelog(format!("Regenerating the changelog failed: {}", err))));
+    } else {
+        let history = try!(changelog::render_history(&config.repository_path, &tags, &config.changelog_options())
+            .map_err(|err| Error::Changelog(format!("Regenerating the changelog failed: {}", err))));
+        logger::stdout(format!("Would write the following releases to {}:", config.changelog_path));
+        logger::stdout("====================================");
+        logger::stdout(history.trim_right());
+        logger::stdout("====================================");
+        outcome.changelog = Some(history);
+    }
+    Ok(outcome)
+}
+
+fn package_crate(config: &Config, repository_path: &str, new_version: &str) -> Result<(), Error> {
+    if config.release_mode {
+        logger::stdout("Updating lockfile");
+        try!(cargo::update_lockfile(repository_path)
+            .map_err(|output| Error::Cargo(format!("`cargo fetch` failed:\n{}", output.trim_right()))));
+    }
+
+    try!(git::commit_files(&config, &new_version)
+        .map_err(|err| Error::Repository(format!("Committing files failed: {}", err))));
+
+    if !config.should_publish() {
+        logger::stdout("Publishing is disabled, not packaging the crate");
+        return Ok(());
+    }
+
+    try!(check_package(config, repository_path));
+
+    let progress = logger::progress("Packaging crate");
+    try!(cargo::package(repository_path, &config.cargo_options())
+        .map_err(|output| Error::Cargo(format!("`cargo package` failed:\n{}", output.trim_right()))));
+    progress.finish("Crate packaged");
+    Ok(())
+}
+
+/// Runs a release as described by `config`: analyzes the commits since the latest release,
+/// and, depending on the write and release mode, bumps the version, writes the changelog,
+/// tags, pushes and publishes.
+pub fn run(config: &Config) -> Result<Outcome, Error> {
+    for secret in config.secrets() {
+        logger::add_secret(secret);
+    }
+
+    if let Some(ref tag_name) = config.release_only {
+        return release_existing_tag(config, tag_name);
+    }
+    if config.package_only {
+        return package_current_state(config);
+    }
+    if config.regenerate_changelog {
+        return regenerate_changelog(config);
+    }
+
+    let mut outcome = Outcome::default();
+
+    if !is_release_branch(config) {
+        logger::stdout(format!("Current branch is '{}', releases are only done from branches matching '{}'",
+                               config.branch, config.release_branches.join("', '")));
+        logger::stdout("No release done from a pull request either.");
+        // Still reported, so `--print-version` prints it
+        outcome.current_version = current_version(config).ok().map(|version| version.to_string());
+        return Ok(outcome);
+    }
+
+    let head_message = try!(git::head_message(&config.repository)
+        .map_err(|err| Error::Repository(format!("Reading the latest commit failed: {}", err))));
+    if commit_analyzer::has_skip_marker(&head_message, &config.skip_release_token) {
+        logger::stdout("Release skipped by commit marker");
+        outcome.current_version = current_version(config).ok().map(|version| version.to_string());
+        return Ok(outcome);
+    }
+
+    //Before we actually start, we do perform some preflight checks
+    //Here we check if everything is in place to do a GitHub release and a
+    //release on crates.io.
+    //The important bit is, if something's missing, we do not abort since the user can still do all
+    //other things except publishing
+
+    if config.write_mode && config.sign_tags && !config.tag_annotated {
+        return Err(Error::Signing("Only annotated tags can be signed, lightweight tags were requested".into()));
+    }
+
+    if config.write_mode && (config.sign_tags || config.sign_commits) && git::signing_key(&config.repository).is_none() {
+        return Err(Error::Signing("Signing requested, but no signing key is configured. Set one with `git config user.signingkey <key>`.".into()));
+    }
+
+    if config.write_mode && !config.allow_dirty {
+        let dirty = try!(git::dirty_files(&config.repository)
+            .map_err(|err| Error::Repository(format!("Could not determine the status of the working tree: {}", err))));
+        if !dirty.is_empty() {
+            return Err(Error::Release(format!("The working tree has uncommitted changes, commit or stash them first (or pass --allow-dirty):\n  {}",
+                                              dirty.join("\n  "))));
+        }
+    }
+
+    if config.publish && !config.should_publish() {
+        logger::stdout("The crate only has binary targets, skipping the crates.io publish. Use --force-publish to publish it anyway.");
+    }
+
+    logger::stdout("Performing preflight checks now");
+    let warnings = preflight::check(&config);
+
+    if warnings.is_empty() {
+        logger::stdout("Checks done. Everything is ok");
+    }
+
+    for warning in warnings {
+        logger::warn(format!(">> {}", warning));
+    }
+
+    if config.release_mode && utils::ci_env_set() && !config.ci_wait {
+        logger::debug("Not waiting for other jobs of the build (--no-ci-wait)");
+    } else if config.release_mode && utils::ci_env_set() && ci::environment().waits_for_other_jobs() {
+        let build_run = try!(Build::from_env()
+            .map_err(|e| Error::Release(format!("CI mode, but can't check other builds. Error: {:?}", e))));
+
+        if !build_run.is_leader() {
+            logger::stdout("Not the build leader. Nothing to do. Bye.");
+            return Ok(outcome);
+        }
+
+        let progress = logger::progress("I am the build leader. Waiting for other jobs to finish.");
+        match build_run.wait_for_others() {
+            Ok(()) => progress.finish("Other jobs finished and succeeded. Doing my work now."),
+            Err(travis_after_all::Error::FailedBuilds) => {
+                return Err(Error::Release("Some builds failed. Stopping here.".into()));
+            },
+            Err(e) => return Err(Error::Release(format!("Waiting for other builds failed. Reason: {:?}", e))),
+        }
+    }
+
+    if config.tag_prefix.contains("{name}") {
+        return Err(Error::Release("The tag format uses {name}, but the package name is unknown. Select a crate with --package.".into()));
+    }
+
+    let version = try!(current_version(config));
+    logger::stdout(format!("Current version: {}", version.to_string()));
+    outcome.current_version = Some(version.to_string());
+
+    let latest_tag = git::latest_version_tag(&config.repository, &config.tag_prefix)
+        .map(|version| config.tag_name(&version.to_string()));
+    if let Some(ref since) = config.since {
+        if !git::commit_exists(&config.repository, since) {
+            return Err(Error::Release(format!("--since {} is neither a tag nor a commit", since)));
+        }
+        logger::stdout(format!("Analyzing commits since {} instead of the latest release tag", since));
+    } else if let Some(ref tag) = latest_tag {
+        try!(check_history(&config, tag));
+    }
+    let first_release = latest_tag.is_none() && config.since.is_none();
+
+    // The history is read once, for the bump as well as the changelog
+    let since_tag = match config.since {
+        Some(ref since) => Some(&since[..]),
+        None if config.first_release => None,
+        None => latest_tag.as_ref().map(|tag| &tag[..]),
+    };
+    let entries = try!(git::changelog_entries(&config, since_tag)
+        .map_err(|err| Error::Repository(format!("Reading the commit history failed: {}", err))));
+
+    let new_version = if let Some(ref new_version) = config.version_override {
+        if *new_version <= version {
+            return Err(Error::Release(format!("The requested version {} is not greater than the current version {}", new_version, version)));
+        }
+        logger::stdout(format!("Version set manually to {}, skipping commit analysis", new_version));
+        new_version.to_string()
+    } else if config.first_release {
+        let tag_name = config.tag_name(&version.to_string());
+        if git::tag_exists(&config.repository, &tag_name) {
+            return Err(Error::Release(format!("{} is already tagged as {}, --first-release is only for unreleased versions", version, tag_name)));
+        }
+        logger::stdout(format!("First release, releasing the current version {}", version));
+        version.to_string()
+    } else if config.release_final && !version.pre.is_empty() {
+        let new_version = final_version(&version);
+        logger::stdout(format!("Promoting {} to the final release {}", version, new_version));
+        new_version.to_string()
+    } else if first_release && version.major == 0 && config.forced_bump.is_none() {
+        // A crate in initial development is released with the version it already has.
+        logger::stdout(format!("No release tag found, releasing the current version {} as the first release", version));
+        version.to_string()
+    } else {
+        logger::stdout("Analyzing commits");
+
+        let bump = git::analyze_entries(&entries);
+        let counts = commit_analyzer::count_types(entries.iter().map(|entry| entry.commit.commit_type));
+        logger::stdout(format!("Found {}", commit_analyzer::describe_counts(&counts)));
+        let bump = match config.forced_bump {
+            Some(forced) if forced < bump => {
+                logger::warn(format!("Commits indicate a {:?} bump, which is higher than the forced {:?} bump. Keeping {:?}.", bump, forced, bump));
+                bump
+            },
+            Some(forced) => forced,
+            None => bump,
+        };
+        if config.write_mode {
+            logger::stdout(format!("Commits analyzed. Bump will be {:?}", bump));
+        } else {
+            logger::stdout(format!("Commits analyzed. Bump would be {:?}", bump));
+        }
+        outcome.bump = Some(bump);
+        let prerelease = config.prerelease.as_ref().map(|label| &label[..]);
+        match version_bump(&version, bump, prerelease, config.zero_ver_semantics) {
+            Some(new_version) => new_version.to_string(),
+            None => {
+                logger::stdout("No version bump. Nothing to do.");
+                return Ok(outcome);
+            }
+        }
+    };
+    let new_version = match config.build_metadata {
+        Some(ref template) => {
+            let sha = try!(git::head_short_id(&config.repository)
+                .map_err(|err| Error::Repository(format!("Reading the latest commit failed: {}", err))));
+            // The current version may carry metadata of its own, which is replaced
+            let without_metadata = new_version.split('+').next().unwrap_or("");
+            try!(with_build_metadata(without_metadata, template, &sha))
+        },
+        None => new_version,
+    };
+    try!(check_new_version(&config, &new_version));
+    outcome.new_version = Some(new_version.clone());
+    outcome.tag_name = Some(config.tag_name(&new_version));
+
+    if !config.write_mode && !config.generate_changelog {
+        logger::stdout(format!("New version would be: {}", new_version));
+        logger::stdout("Would not write a changelog (disabled with --no-changelog)");
+        logger::stdout(format!("Would create {} git tag", config.tag_kind()));
+        try!(write_release_notes(&config, &new_version, &entries));
+    } else if !config.write_mode {
+        let changelog = try!(generate_changelog(&config, &new_version, &entries));
+        print_changelog(&config, &changelog);
+        outcome.changelog = Some(changelog);
+        try!(write_release_notes(&config, &new_version, &entries));
+    } else {
+        logger::stdout(format!("New version: {}", new_version));
+
+        if config.releases_remotely() && config.can_release_to_cratesio() && config.registry.is_none() {
+            try!(verify_crates_io(&config, &new_version));
+        }
+        if config.releases_remotely() && config.can_release_to_github() && github::can_release(&config) {
+            try!(verify_github_token(&config));
+        }
+
+        let version_source = config.version_source();
+        let version_file = version_source.file();
+        try!(version_source.write(&new_version)
+            .map_err(|err| Error::Manifest(format!("Writing `{}` failed: {:?}", version_file.display(), err))));
+        try!(update_version_replacements(&config, &new_version));
+        // A crate next to another version file still has to be published in the new version
+        let cargo_toml = Path::new(&config.manifest_dir()).join("Cargo.toml");
+        if version_file != cargo_toml && toml_file::read_from_file(&config.manifest_dir()).is_ok() {
+            try!(toml_file::write_new_version(&config.manifest_dir(), &new_version)
+                .map_err(|err| Error::Manifest(format!("Writing `Cargo.toml` failed: {:?}", err))));
+        }
+
+        if config.generate_changelog {
+            try!(write_changelog(&config, &new_version, &entries));
+        }
+        try!(write_release_notes(&config, &new_version, &entries));
+
+        if !config.pre_release_hooks.is_empty() {
+            logger::stdout("Running pre-release hooks");
+            try!(hooks::run(&config.pre_release_hooks, &config.repository_path, &new_version)
+                .map_err(|err| Error::Hook(format!("Pre-release hook failed, aborting release: {}", err))));
+        }
+
+        try!(package_crate(&config, &config.manifest_dir(), &new_version));
+
+        logger::stdout(format!("Creating {} git tag", config.tag_kind()));
+        let tag_name = config.tag_name(&new_version);
+        let tag_message = if config.generate_changelog {
+            try!(changelog::generate(&config.repository_path, &tag_name, &entries, &config.changelog_options())
+                .map_err(|err| Error::Changelog(format!("Can't generate changelog: {}", err))))
+        } else {
+            format!("Release {}", tag_name)
+        };
+
+        try!(git::tag(&config, &new_version, &tag_message)
+            .map_err(|err| Error::Repository(format!("Failed to create git tag: {}", err))));
+
+        if config.releases_remotely() && config.can_push() {
+            try!(push_to_github(&config, &tag_name));
+        }
+
+        let mut github_release = false;
+        let mut hosted_release = false;
+
+        if config.releases_remotely() && config.can_release_to_github() {
+            let new_version = try!(Version::parse(&new_version)
+                .map_err(|err| Error::Release(format!("{} is not a valid version: {:?}", new_version, err))));
+            github_release = try!(release_on_github(&config, &tag_message, &tag_name, &new_version));
+            hosted_release = github_release;
+        }
+
+        if config.releases_remotely() && config.can_release_to_gitlab() {
+            hosted_release = try!(release_on_gitlab(&config, &tag_message, &tag_name));
+        }
+
+        if config.releases_remotely() && config.can_release_to_bitbucket() {
+            try!(release_on_bitbucket(&config, &tag_message, &tag_name));
+            hosted_release = true;
+        }
+
+        if config.releases_remotely() && config.can_release_to_cratesio() {
+            try!(release_on_cratesio(&config, &new_version));
+            // Not every remote names a repository, e.g. Azure DevOps or `--provider none`
+            let released = match config.repository_name.as_ref().or(config.package_name.as_ref()) {
+                Some(name) => format!("{} {}", name, tag_name),
+                None => tag_name.clone(),
+            };
+            if config.registry.is_none() {
+                logger::stdout(format!("{} is released. 🚀🚀🚀", released));
+            } else {
+                logger::stdout(format!("{} is published to {}. 🚀🚀🚀", released, config.registry_name()));
+            }
+        }
+
+        if config.releases_remotely() && !config.post_release_hooks.is_empty() {
+            logger::stdout("Running post-release hooks");
+            try!(hooks::run(&config.post_release_hooks, &config.repository_path, &new_version)
+                .map_err(|err| Error::Hook(format!("Post-release hook failed: {}", err))));
+        }
+
+        // Only announce releases people can actually look at
+        if hosted_release {
+            if let Some(ref url) = config.notify_webhook {
+                let changelog = if config.generate_changelog { &tag_message[..] } else { "" };
+                notify_webhook(&config, url, &new_version, &tag_name, changelog);
+            }
+            if let Some(ref url) = config.slack_webhook {
+                let changelog = if config.generate_changelog { &tag_message[..] } else { "" };
+                let release_url = if github_release { config.release_url(&tag_name) } else { None };
+                notify_slack(&config, url, &tag_name, changelog, release_url);
+            }
+        }
+
+        if config.release_mode && !config.push {
+            let remote = config.remote.as_ref().map(|remote| &remote[..]).unwrap_or("origin");
+            logger::stdout(format!("Committed and tagged {} locally, nothing was pushed or published (--no-push).", tag_name));
+            logger::stdout(format!("After reviewing it, push it with `git push {} {} {}`", remote, config.branch, tag_name));
+        }
+
+        outcome.released = config.releases_remotely();
+        if config.generate_changelog {
+            outcome.changelog = Some(tag_message);
+        }
+    }
+
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bump(version: &str, bump: CommitType, prerelease: Option<&str>) -> Option<String> {
+        version_bump(&Version::parse(version).unwrap(), bump, prerelease, false).map(|v| v.to_string())
+    }
+
+    fn zero_ver_bump(version: &str, bump: CommitType) -> Option<String> {
+        version_bump(&Version::parse(version).unwrap(), bump, None, true).map(|v| v.to_string())
+    }
+
+    #[test]
+    fn regular_bumps() {
+        assert_eq!(Some("1.0.1".to_owned()), bump("1.0.0", CommitType::Patch, None));
+        assert_eq!(Some("1.1.0".to_owned()), bump("1.0.3", CommitType::Minor, None));
+        assert_eq!(Some("2.0.0".to_owned()), bump("1.4.3", CommitType::Major, None));
+        assert_eq!(None, bump("1.0.0", CommitType::None, None));
+        assert_eq!(None, bump("1.0.0", CommitType::Unknown, Some("rc")));
+    }
+
+    #[test]
+    fn zero_ver_major_bumps_the_minor() {
+        assert_eq!(Some("0.4.0".to_owned()), zero_ver_bump("0.3.2", CommitType::Major));
+        assert_eq!(Some("1.0.0".to_owned()), bump("0.3.2", CommitType::Major, None));
+        assert_eq!(Some("2.0.0".to_owned()), zero_ver_bump("1.3.2", CommitType::Major));
+    }
+
+    #[test]
+    fn zero_ver_minor_bumps_the_patch() {
+        assert_eq!(Some("0.3.3".to_owned()), zero_ver_bump("0.3.2", CommitType::Minor));
+        assert_eq!(Some("0.3.3".to_owned()), zero_ver_bump("0.3.2", CommitType::Patch));
+        assert_eq!(Some("1.4.0".to_owned()), zero_ver_bump("1.3.2", CommitType::Minor));
+        assert_eq!(None, zero_ver_bump("0.3.2", CommitType::None));
+    }
+
+    #[test]
+    fn prerelease_bumps() {
+        assert_eq!(Some("1.1.0-rc.1".to_owned()), bump("1.0.0", CommitType::Minor, Some("rc")));
+        assert_eq!(Some("1.1.0-rc.2".to_owned()), bump("1.1.0-rc.1", CommitType::Patch, Some("rc")));
+        assert_eq!(Some("1.1.0-rc.3".to_owned()), bump("1.1.0-rc.2", CommitType::Minor, Some("rc")));
+
+        // A bigger bump than the pre-release stands for starts a new version
+        assert_eq!(Some("2.0.0-rc.1".to_owned()), bump("1.1.0-rc.2", CommitType::Major, Some("rc")));
+        assert_eq!(Some("1.1.0-rc.1".to_owned()), bump("1.0.1-rc.4", CommitType::Minor, Some("rc")));
+
+        // Switching channels restarts the counter
+        assert_eq!(Some("1.1.0-rc.1".to_owned()), bump("1.1.0-beta.3", CommitType::Patch, Some("rc")));
+    }
+
+    #[test]
+    fn prerelease_without_label_is_released() {
+        assert_eq!(Some("1.1.0".to_owned()), bump("1.1.0-rc.2", CommitType::Patch, None));
+        assert_eq!(Some("2.0.0".to_owned()), bump("1.1.0-rc.2", CommitType::Major, None));
+        assert_eq!("1.1.0", final_version(&Version::parse("1.1.0-rc.2").unwrap()).to_string());
+    }
+
+    #[test]
+    fn build_metadata() {
+        assert_eq!("1.2.0+sha.1a2b3c4", with_build_metadata("1.2.0", "sha.{sha}", "1a2b3c4").unwrap());
+        assert_eq!("1.2.0-rc.1+build.5", with_build_metadata("1.2.0-rc.1", "build.5", "1a2b3c4").unwrap());
+        assert!(with_build_metadata("1.2.0", "", "1a2b3c4").is_err());
+        assert!(with_build_metadata("1.2.0", "sha_{sha}", "1a2b3c4").is_err());
+
+        // Metadata doesn't make a version newer
+        let plain = Version::parse("1.2.0").unwrap();
+        let with_metadata = Version::parse("1.2.0+sha.1a2b3c4").unwrap();
+        assert!(!(with_metadata > plain));
+    }
+}
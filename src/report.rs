@@ -0,0 +1,85 @@
+use commit_analyzer::CommitType;
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+
+/// What a run did, printed as a single JSON object with `--output json`.
+#[derive(Debug, Default)]
+pub struct Outcome {
+    pub current_version: Option<String>,
+    pub new_version: Option<String>,
+    pub bump: Option<CommitType>,
+    pub released: bool,
+    pub tag_name: Option<String>,
+    pub changelog: Option<String>,
+    /// The `.crate` file built with `--package-only`.
+    pub package: Option<String>,
+}
+
+fn optional(value: &Option<String>) -> Json {
+    match *value {
+        Some(ref value) => Json::String(value.clone()),
+        None => Json::Null,
+    }
+}
+
+pub fn bump_name(bump: CommitType) -> &'static str {
+    match bump {
+        CommitType::Major => "major",
+        CommitType::Minor => "minor",
+        CommitType::Patch => "patch",
+        CommitType::None | CommitType::Unknown => "none",
+    }
+}
+
+impl Outcome {
+    pub fn to_json(&self) -> Json {
+        let mut object = BTreeMap::new();
+        object.insert("current_version".to_owned(), optional(&self.current_version));
+        object.insert("new_version".to_owned(), optional(&self.new_version));
+        object.insert("bump".to_owned(), match self.bump {
+            Some(bump) => Json::String(bump_name(bump).to_owned()),
+            None => Json::Null,
+        });
+        object.insert("released".to_owned(), Json::Boolean(self.released));
+        object.insert("tag_name".to_owned(), optional(&self.tag_name));
+        object.insert("changelog".to_owned(), optional(&self.changelog));
+        object.insert("package".to_owned(), optional(&self.package));
+        Json::Object(object)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use commit_analyzer::CommitType;
+
+    #[test]
+    fn serializes_missing_values_as_null() {
+        let outcome = Outcome {
+            current_version: Some("1.0.0".into()),
+            bump: Some(CommitType::Unknown),
+            ..Outcome::default()
+        };
+
+        assert_eq!(r#"{"bump":"none","changelog":null,"current_version":"1.0.0","new_version":null,"package":null,"released":false,"tag_name":null}"#,
+                   outcome.to_json().to_string());
+    }
+
+    #[test]
+    fn serializes_release() {
+        let outcome = Outcome {
+            current_version: Some("1.0.0".into()),
+            new_version: Some("1.1.0".into()),
+            bump: Some(CommitType::Minor),
+            released: true,
+            tag_name: Some("v1.1.0".into()),
+            changelog: Some("## v1.1.0".into()),
+            package: None,
+        };
+        let json = outcome.to_json();
+
+        assert_eq!(Some("1.1.0"), json.find("new_version").and_then(|v| v.as_string()));
+        assert_eq!(Some("minor"), json.find("bump").and_then(|v| v.as_string()));
+        assert_eq!(Some(true), json.find("released").and_then(|v| v.as_boolean()));
+    }
+}
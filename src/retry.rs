@@ -0,0 +1,83 @@
+use std::fmt::Display;
+use std::thread;
+use std::time::Duration;
+
+use logger;
+
+/// How long to wait before the given retry, doubling each time: 1s, 2s, 4s, ...
+pub fn backoff(retry: u32) -> Duration {
+    Duration::from_secs(1 << retry.saturating_sub(1).min(6))
+}
+
+/// Runs `operation` up to `max_attempts` times, waiting a little longer after each failure.
+/// Only errors for which `is_transient` returns true are retried.
+pub fn with_backoff<T, E, F, P>(what: &str, max_attempts: u32, is_transient: P, mut operation: F) -> Result<T, E>
+    where F: FnMut() -> Result<T, E>,
+          P: Fn(&E) -> bool,
+          E: Display
+{
+    let mut attempt = 1;
+    loop {
+        match operation() {
+            Ok(value) => return Ok(value),
+            Err(ref err) if attempt < max_attempts && is_transient(err) => {
+                let delay = backoff(attempt);
+                logger::warn(format!("{} failed ({}), retrying in {}s (attempt {} of {})",
+                                     what, err, delay.as_secs(), attempt + 1, max_attempts));
+                thread::sleep(delay);
+                attempt += 1;
+            },
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    #[test]
+    fn backoff_doubles() {
+        assert_eq!(Duration::from_secs(1), backoff(1));
+        assert_eq!(Duration::from_secs(2), backoff(2));
+        assert_eq!(Duration::from_secs(4), backoff(3));
+    }
+
+    #[test]
+    fn permanent_errors_are_not_retried() {
+        let calls = Cell::new(0);
+        let result: Result<(), String> = with_backoff("Test", 3, |_| false, || {
+            calls.set(calls.get() + 1);
+            Err("unauthorized".into())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(1, calls.get());
+    }
+
+    #[test]
+    fn succeeds_without_retry() {
+        let calls = Cell::new(0);
+        let result: Result<u32, String> = with_backoff("Test", 3, |_| true, || {
+            calls.set(calls.get() + 1);
+            Ok(42)
+        });
+
+        assert_eq!(Ok(42), result);
+        assert_eq!(1, calls.get());
+    }
+
+    #[test]
+    fn transient_errors_are_retried() {
+        let calls = Cell::new(0);
+        let result: Result<u32, String> = with_backoff("Test", 2, |_| true, || {
+            calls.set(calls.get() + 1);
+            if calls.get() < 2 { Err("timeout".into()) } else { Ok(42) }
+        });
+
+        assert_eq!(Ok(42), result);
+        assert_eq!(2, calls.get());
+    }
+}
@@ -1,10 +1,13 @@
 use toml::Parser;
 use regex::Regex;
+use semver::Version;
 use std::io::prelude::*;
 use std::fs::File;
 use std::io::Error;
-use std::fs::OpenOptions;
-use std::path::Path;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use utils::glob_to_regex;
 
 #[derive(Debug)]
 pub enum TomlError {
@@ -27,10 +30,13 @@ pub fn read_version(file: String) -> Option<String> {
     }
 }
 
+/// Only the `version` value of the `[package]` section is touched,
+/// comments, ordering and formatting of everything else are kept as they are.
 pub fn file_with_new_version(file: String, new_version: &str) -> String {
-    let re = Regex::new(r#"version\s=\s"\d+\.\d+\.\d+""#).unwrap();
-    let new_version = format!("version = \"{}\"", new_version);
-    re.replace(&file, &new_version[..])
+    match replace_in_section(&file, "package", "version", new_version) {
+        Some(updated) => updated,
+        None => file,
+    }
 }
 
 pub fn read_from_file(repository_path: &str) -> Result<String, TomlError> {
@@ -40,18 +46,486 @@ pub fn read_from_file(repository_path: &str) -> Result<String, TomlError> {
         Err(err) => return Err(TomlError::Io(err))
     };
 
+    let is_workspace = !workspace_members(&cargo_file).is_empty();
     match read_version(cargo_file) {
         Some(version) => Ok(version),
+        None if is_workspace => {
+            // A virtual manifest has no version of its own,
+            // the workspace is as far along as its most recent member.
+            let versions = try!(read_workspace_versions(repository_path));
+            versions.into_iter()
+                .filter_map(|(_, version)| Version::parse(&version).ok())
+                .max()
+                .map(|version| version.to_string())
+                .ok_or(TomlError::Parse("No version field found"))
+        }
         None => Err(TomlError::Parse("No version field found"))
     }
 }
 
-pub fn write_new_version(repository_path: &str, new_version: &str) -> Result<(), Error> {
+/// The `[package]` name, `None` for a virtual manifest.
+pub fn read_package_name(repository_path: &str) -> Result<Option<String>, TomlError> {
+    let file_path = Path::new(&repository_path).join("Cargo.toml");
+    let cargo_file = try!(read_cargo_toml(&file_path).map_err(TomlError::Io));
+    Ok(value_in_section(&cargo_file, "package", "name"))
+}
+
+pub fn write_new_version(repository_path: &str, new_version: &str) -> Result<(), TomlError> {
     let file_path = Path::new(&repository_path).join("Cargo.toml");
-    let cargo_toml = try!(read_cargo_toml(&file_path));
-    let new_cargo_toml = file_with_new_version(cargo_toml, new_version);
-    let mut handle = try!(OpenOptions::new().read(true).write(true).open(file_path));
-    handle.write_all(new_cargo_toml.as_bytes())
+    let cargo_toml = try!(read_cargo_toml(&file_path).map_err(TomlError::Io));
+    let is_workspace = !workspace_members(&cargo_toml).is_empty();
+
+    if !inherits_workspace_version(&cargo_toml) && read_version(cargo_toml.clone()).is_some() {
+        match replace_in_section(&cargo_toml, "package", "version", new_version) {
+            Some(new_cargo_toml) => try!(write_cargo_toml(&file_path, &new_cargo_toml).map_err(TomlError::Io)),
+            None => return Err(TomlError::Parse("Could not replace the version field")),
+        }
+    }
+
+    if is_workspace {
+        try!(write_workspace_versions(repository_path, new_version));
+        try!(write_dependency_versions(repository_path, new_version));
+    }
+
+    Ok(())
+}
+
+/// Splits a manifest into its lines, each paired with the `[section]` it belongs to.
+/// Top-level keys belong to the empty section.
+fn lines_with_section(file: &str) -> Vec<(String, &str)> {
+    let header = Regex::new(r"^\s*\[\[?\s*([^\[\]]+?)\s*\]\]?\s*(#.*)?$").unwrap();
+    let mut section = String::new();
+
+    file.lines().map(|line| {
+        if let Some(caps) = header.captures(line) {
+            section = caps.at(1).unwrap_or("").to_owned();
+        }
+        (section.clone(), line)
+    }).collect()
+}
+
+fn value_in_section(file: &str, section: &str, key: &str) -> Option<String> {
+    let re = Regex::new(&format!(r#"^\s*{}\s*=\s*(?:"([^"]*)"|'([^']*)')"#, key)).unwrap();
+
+    lines_with_section(file).into_iter()
+        .filter(|&(ref s, _)| s == section)
+        .filter_map(|(_, line)| re.captures(line).and_then(|caps| caps.at(1).or(caps.at(2))).map(|v| v.to_owned()))
+        .next()
+}
+
+/// Whether the `[package]` section uses `version.workspace = true`
+/// (or the equivalent inline table) to inherit its version.
+pub fn inherits_workspace_version(file: &str) -> bool {
+    let re = Regex::new(r"^\s*version\s*(\.\s*workspace\s*=\s*true|=\s*\{\s*workspace\s*=\s*true\s*\})").unwrap();
+
+    lines_with_section(file).into_iter()
+        .any(|(section, line)| section == "package" && re.is_match(line))
+}
+
+/// The `members` of a `[workspace]`, exactly as written in the manifest.
+pub fn workspace_members(file: &str) -> Vec<String> {
+    let workspace = lines_with_section(file).into_iter()
+        .filter(|&(ref section, _)| section == "workspace")
+        .map(|(_, line)| line)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let members = Regex::new(r"(?s)members\s*=\s*\[(.*?)\]").unwrap();
+    let entry = Regex::new(r#""([^"]*)""#).unwrap();
+
+    match members.captures(&workspace).and_then(|caps| caps.at(1)) {
+        Some(list) => entry.captures_iter(list)
+            .filter_map(|caps| caps.at(1).map(|m| m.to_owned()))
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// Expands a workspace member pattern like `crates/*` relative to `root`
+/// into all matching directories that contain a `Cargo.toml`.
+fn expand_member(root: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut candidates = vec![root.to_path_buf()];
+
+    for segment in pattern.split('/').filter(|s| !s.is_empty() && *s != ".") {
+        if !segment.contains('*') && !segment.contains('?') {
+            candidates = candidates.into_iter().map(|dir| dir.join(segment)).collect();
+            continue;
+        }
+
+        let re = glob_to_regex(segment);
+        let mut expanded = vec![];
+        for dir in candidates {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.filter_map(|e| e.ok()) {
+                let matches = entry.file_name().to_str().map(|name| re.is_match(name)).unwrap_or(false);
+                if matches && entry.path().is_dir() {
+                    expanded.push(entry.path());
+                }
+            }
+        }
+        expanded.sort();
+        candidates = expanded;
+    }
+
+    candidates.into_iter().filter(|dir| dir.join("Cargo.toml").is_file()).collect()
+}
+
+/// Paths to the manifests of all workspace members, relative to the repository.
+pub fn workspace_manifests(repository_path: &str) -> Result<Vec<PathBuf>, TomlError> {
+    let root = Path::new(repository_path);
+    let root_toml = try!(read_cargo_toml(&root.join("Cargo.toml")).map_err(TomlError::Io));
+
+    let mut manifests = vec![];
+    for member in workspace_members(&root_toml) {
+        for dir in expand_member(root, &member) {
+            let manifest = dir.join("Cargo.toml");
+            let relative = manifest.strip_prefix(root).map(|p| p.to_path_buf()).unwrap_or(manifest.clone());
+            if !manifests.contains(&relative) {
+                manifests.push(relative);
+            }
+        }
+    }
+
+    Ok(manifests)
+}
+
+/// Reads the version of every workspace member.
+/// Members using `version.workspace = true` report the `[workspace.package]` version.
+pub fn read_workspace_versions(repository_path: &str) -> Result<Vec<(PathBuf, String)>, TomlError> {
+    let root = Path::new(repository_path);
+    let root_toml = try!(read_cargo_toml(&root.join("Cargo.toml")).map_err(TomlError::Io));
+    let workspace_version = value_in_section(&root_toml, "workspace.package", "version");
+
+    let mut versions = vec![];
+    for manifest in try!(workspace_manifests(repository_path)) {
+        let member_toml = try!(read_cargo_toml(&root.join(&manifest)).map_err(TomlError::Io));
+        let version = if inherits_workspace_version(&member_toml) {
+            workspace_version.clone()
+        } else {
+            value_in_section(&member_toml, "package", "version")
+        };
+
+        match version {
+            Some(version) => versions.push((manifest, version)),
+            None => return Err(TomlError::Parse("Workspace member without a version field found")),
+        }
+    }
+
+    Ok(versions)
+}
+
+/// Bumps every workspace member to `new_version`.
+/// Members inheriting their version are covered by bumping `[workspace.package]` once.
+pub fn write_workspace_versions(repository_path: &str, new_version: &str) -> Result<(), TomlError> {
+    let root = Path::new(repository_path);
+    let root_path = root.join("Cargo.toml");
+    let root_toml = try!(read_cargo_toml(&root_path).map_err(TomlError::Io));
+
+    if let Some(updated) = replace_in_section(&root_toml, "workspace.package", "version", new_version) {
+        try!(write_cargo_toml(&root_path, &updated).map_err(TomlError::Io));
+    }
+
+    for manifest in try!(workspace_manifests(repository_path)) {
+        let manifest = root.join(manifest);
+        let member_toml = try!(read_cargo_toml(&manifest).map_err(TomlError::Io));
+        if inherits_workspace_version(&member_toml) {
+            continue;
+        }
+
+        match replace_in_section(&member_toml, "package", "version", new_version) {
+            Some(updated) => try!(write_cargo_toml(&manifest, &updated).map_err(TomlError::Io)),
+            None => return Err(TomlError::Parse("Workspace member without a version field found")),
+        }
+    }
+
+    Ok(())
+}
+
+/// The `[package]` names of all workspace members.
+pub fn workspace_package_names(repository_path: &str) -> Result<Vec<String>, TomlError> {
+    let root = Path::new(repository_path);
+    let mut names = vec![];
+
+    for manifest in try!(workspace_manifests(repository_path)) {
+        let member_toml = try!(read_cargo_toml(&root.join(&manifest)).map_err(TomlError::Io));
+        match value_in_section(&member_toml, "package", "name") {
+            Some(name) => names.push(name),
+            None => return Err(TomlError::Parse("Workspace member without a name field found")),
+        }
+    }
+
+    Ok(names)
+}
+
+/// The directory of the workspace member called `name`, relative to the repository.
+pub fn package_dir(repository_path: &str, name: &str) -> Result<Option<PathBuf>, TomlError> {
+    let root = Path::new(repository_path);
+
+    for manifest in try!(workspace_manifests(repository_path)) {
+        let member_toml = try!(read_cargo_toml(&root.join(&manifest)).map_err(TomlError::Io));
+        if value_in_section(&member_toml, "package", "name").as_ref().map(|n| &n[..]) == Some(name) {
+            return Ok(Some(manifest.parent().map(|dir| dir.to_path_buf()).unwrap_or(PathBuf::new())));
+        }
+    }
+
+    Ok(None)
+}
+
+/// A crate of the workspace, as far as publishing it is concerned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkspacePackage {
+    pub name: String,
+    /// Directory of the manifest, relative to the repository.
+    pub dir: PathBuf,
+    pub dependencies: Vec<String>,
+    pub publish: bool,
+}
+
+/// The root package, if there is one, followed by all workspace members.
+pub fn workspace_packages(repository_path: &str) -> Result<Vec<WorkspacePackage>, TomlError> {
+    let root = Path::new(repository_path);
+    let mut manifests = try!(workspace_manifests(repository_path));
+    manifests.insert(0, PathBuf::from("Cargo.toml"));
+
+    let mut packages = vec![];
+    for manifest in manifests {
+        let cargo_toml = try!(read_cargo_toml(&root.join(&manifest)).map_err(TomlError::Io));
+        if let Some(name) = value_in_section(&cargo_toml, "package", "name") {
+            packages.push(WorkspacePackage {
+                name: name,
+                dir: manifest.parent().map(|dir| dir.to_path_buf()).unwrap_or(PathBuf::new()),
+                dependencies: dependency_names(&cargo_toml),
+                publish: is_publishable(&cargo_toml),
+            });
+        }
+    }
+
+    Ok(packages)
+}
+
+/// Whether the crate only builds binaries: it has no `[lib]` target,
+/// neither declared nor in `src/lib.rs`, but a `[[bin]]` or `src/main.rs`.
+pub fn is_binary_only(manifest_dir: &str) -> Result<bool, TomlError> {
+    let dir = Path::new(manifest_dir);
+    let cargo_toml = try!(read_cargo_toml(&dir.join("Cargo.toml")).map_err(TomlError::Io));
+
+    let has_lib = has_section(&cargo_toml, "lib") || dir.join("src").join("lib.rs").is_file();
+    let has_bin = has_section(&cargo_toml, "bin") || dir.join("src").join("main.rs").is_file();
+    Ok(has_bin && !has_lib)
+}
+
+fn has_section(file: &str, section: &str) -> bool {
+    lines_with_section(file).into_iter().any(|(s, _)| s == section)
+}
+
+/// `publish = false` (or an empty list of registries) marks a crate as private.
+pub fn is_publishable(file: &str) -> bool {
+    let re = Regex::new(r"^\s*publish\s*=\s*(false|\[\s*\])").unwrap();
+
+    !lines_with_section(file).into_iter()
+        .any(|(section, line)| section == "package" && re.is_match(line))
+}
+
+/// The packages a manifest needs to build, in order of appearance.
+/// Renamed dependencies are resolved through their `package` key.
+/// Dev-dependencies are left out, they don't have to be published first.
+pub fn dependency_names(file: &str) -> Vec<String> {
+    let key = Regex::new(r"^\s*([A-Za-z0-9_-]+)\s*[=.]").unwrap();
+    let inline_package = Regex::new(r#"\bpackage\s*=\s*"([^"]*)""#).unwrap();
+    let package = Regex::new(r#"^\s*package\s*=\s*"([^"]*)""#).unwrap();
+
+    // `[dependencies.foo]` tables, with the package they name
+    let mut tables: Vec<(String, String)> = vec![];
+    let mut names: Vec<String> = vec![];
+
+    for (section, line) in lines_with_section(file) {
+        if section.contains("dev-dependencies") {
+            continue;
+        }
+
+        if is_dependency_section(&section) {
+            if let Some(caps) = key.captures(line) {
+                let name = inline_package.captures(line).and_then(|c| c.at(1)).or(caps.at(1)).unwrap_or("");
+                names.push(name.to_owned());
+            }
+        } else if let Some(name) = dependency_table_name(&section) {
+            if !tables.iter().any(|&(ref s, _)| *s == section) {
+                tables.push((section.clone(), name.to_owned()));
+            }
+            if let Some(caps) = package.captures(line) {
+                if let Some(table) = tables.iter_mut().find(|table| table.0 == section) {
+                    table.1 = caps.at(1).unwrap_or("").to_owned();
+                }
+            }
+        }
+    }
+
+    names.extend(tables.into_iter().map(|(_, name)| name));
+    let mut unique: Vec<String> = vec![];
+    for name in names {
+        if !unique.contains(&name) {
+            unique.push(name);
+        }
+    }
+    unique
+}
+
+fn is_dependency_section(section: &str) -> bool {
+    let last = section.rsplit('.').next().unwrap_or("");
+    last == "dependencies" || last == "dev-dependencies" || last == "build-dependencies"
+}
+
+/// For `[dependencies.foo]`-style sections, the dependency name they describe.
+fn dependency_table_name(section: &str) -> Option<&str> {
+    ["dependencies.", "dev-dependencies.", "build-dependencies."].iter()
+        .filter_map(|prefix| section.rfind(prefix).map(|pos| &section[pos + prefix.len()..]))
+        .next()
+        .map(|name| name.trim_matches('"'))
+}
+
+/// Rewrites a version requirement like `0.3` or `^0.3.1` to point at `new_version`,
+/// keeping its operator and precision.
+/// Pre-releases are only matched by a requirement naming them, so those are kept in full.
+fn requirement_for(old_requirement: &str, new_version: &str) -> String {
+    let operator_len = old_requirement.find(|c: char| c.is_digit(10)).unwrap_or(old_requirement.len());
+    let (operator, version) = old_requirement.split_at(operator_len);
+    let precision = version.split('.').count();
+
+    let new_version = new_version.split('+').next().unwrap_or(new_version);
+    if new_version.contains('-') {
+        return format!("{}{}", operator, new_version);
+    }
+    let new_version = new_version.split('.').take(precision).collect::<Vec<_>>().join(".");
+    format!("{}{}", operator, new_version)
+}
+
+/// Points dependencies on any of the given packages at their new versions.
+///
+/// Only dependencies with an explicit version requirement are changed.
+/// Renamed dependencies are resolved through their `package` key,
+/// git and alternate registry dependencies are left alone.
+pub fn file_with_new_dependency_versions(file: &str, new_versions: &HashMap<String, String>) -> String {
+    let simple = Regex::new(r#"^(\s*)([A-Za-z0-9_-]+)(\s*=\s*")([^"]*)(".*)$"#).unwrap();
+    let inline = Regex::new(r#"^\s*([A-Za-z0-9_-]+)\s*=\s*\{(.*)\}"#).unwrap();
+    let package = Regex::new(r#"^\s*package\s*=\s*"([^"]*)""#).unwrap();
+    let inline_package = Regex::new(r#"\bpackage\s*=\s*"([^"]*)""#).unwrap();
+    let external = Regex::new(r"^\s*(git|registry)\s*=").unwrap();
+    let inline_external = Regex::new(r"\b(git|registry)\s*=").unwrap();
+    let version = Regex::new(r#"^(.*\bversion\s*=\s*")([^"]*)(".*)$"#).unwrap();
+    let inline_version = Regex::new(r#"^(.*[{,]\s*version\s*=\s*")([^"]*)(".*)$"#).unwrap();
+
+    let lines = lines_with_section(file);
+
+    // `[dependencies.foo]` tables can name their package or source on any line,
+    // so figure those out before rewriting anything.
+    let mut tables: HashMap<String, (String, bool)> = HashMap::new();
+    for &(ref section, line) in &lines {
+        let name = match dependency_table_name(section) {
+            Some(name) => name,
+            None => continue,
+        };
+        let entry = tables.entry(section.clone()).or_insert((name.to_owned(), false));
+        if let Some(caps) = package.captures(line) {
+            entry.0 = caps.at(1).unwrap_or("").to_owned();
+        }
+        if external.is_match(line) {
+            entry.1 = true;
+        }
+    }
+
+    let updated = lines.iter().map(|&(ref section, line)| {
+        if is_dependency_section(section) {
+            if let Some(caps) = simple.captures(line) {
+                if let Some(new_version) = new_versions.get(caps.at(2).unwrap_or("")) {
+                    return format!("{}{}{}{}{}", caps.at(1).unwrap_or(""), caps.at(2).unwrap_or(""),
+                                   caps.at(3).unwrap_or(""), requirement_for(caps.at(4).unwrap_or(""), new_version),
+                                   caps.at(5).unwrap_or(""));
+                }
+            } else if let Some(caps) = inline.captures(line) {
+                let table = caps.at(2).unwrap_or("");
+                let name = inline_package.captures(table).and_then(|c| c.at(1)).unwrap_or(caps.at(1).unwrap_or(""));
+                if !inline_external.is_match(table) {
+                    if let (Some(new_version), Some(v)) = (new_versions.get(name), inline_version.captures(line)) {
+                        return format!("{}{}{}", v.at(1).unwrap_or(""),
+                                       requirement_for(v.at(2).unwrap_or(""), new_version), v.at(3).unwrap_or(""));
+                    }
+                }
+            }
+        } else if let Some(&(ref name, is_external)) = tables.get(section) {
+            if !is_external && line.trim_left().starts_with("version") {
+                if let (Some(new_version), Some(v)) = (new_versions.get(name), version.captures(line)) {
+                    return format!("{}{}{}", v.at(1).unwrap_or(""),
+                                   requirement_for(v.at(2).unwrap_or(""), new_version), v.at(3).unwrap_or(""));
+                }
+            }
+        }
+        line.to_owned()
+    }).collect::<Vec<_>>();
+
+    let mut updated = updated.join("\n");
+    if file.ends_with('\n') {
+        updated.push('\n');
+    }
+    updated
+}
+
+/// Updates the dependencies between workspace members (and from the root manifest)
+/// after all members were bumped to `new_version`.
+pub fn write_dependency_versions(repository_path: &str, new_version: &str) -> Result<(), TomlError> {
+    let root = Path::new(repository_path);
+    let new_versions = try!(workspace_package_names(repository_path)).into_iter()
+        .map(|name| (name, new_version.to_owned()))
+        .collect::<HashMap<_, _>>();
+
+    let mut manifests = try!(workspace_manifests(repository_path));
+    manifests.insert(0, PathBuf::from("Cargo.toml"));
+
+    for manifest in manifests {
+        let manifest = root.join(manifest);
+        let cargo_toml = try!(read_cargo_toml(&manifest).map_err(TomlError::Io));
+        let updated = file_with_new_dependency_versions(&cargo_toml, &new_versions);
+        if updated != cargo_toml {
+            try!(write_cargo_toml(&manifest, &updated).map_err(TomlError::Io));
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces the string value of `key` in `section`, leaving every other byte untouched.
+/// Basic and literal strings keep their quotes.
+fn replace_in_section(file: &str, section: &str, key: &str, value: &str) -> Option<String> {
+    let re = Regex::new(&format!(r#"^(\s*{}\s*=\s*)("[^"]*"|'[^']*')(.*)$"#, key)).unwrap();
+    let mut replaced = false;
+
+    let lines = lines_with_section(file).into_iter().map(|(s, line)| {
+        if !replaced && s == section {
+            if let Some(caps) = re.captures(line) {
+                replaced = true;
+                let quote = &caps.at(2).unwrap_or("\"")[..1];
+                return format!("{}{}{}{}{}", caps.at(1).unwrap_or(""), quote, value, quote, caps.at(3).unwrap_or(""));
+            }
+        }
+        line.to_owned()
+    }).collect::<Vec<_>>();
+
+    if !replaced {
+        return None;
+    }
+
+    let mut updated = lines.join("\n");
+    if file.ends_with('\n') {
+        updated.push('\n');
+    }
+    Some(updated)
+}
+
+fn write_cargo_toml(file_path: &Path, content: &str) -> Result<(), Error> {
+    let mut handle = try!(OpenOptions::new().write(true).truncate(true).open(file_path));
+    handle.write_all(content.as_bytes())
 }
 
 fn read_cargo_toml(file_path: &Path) -> Result<String, Error> {
@@ -119,4 +593,163 @@ mod tests {
     toml = \"0.1\"".to_string();
         assert_eq!(new_toml_file, expected_file);
     }
+
+    fn example_workspace() -> String {
+        "[workspace]
+members = [
+    \"crates/*\",
+    \"tools/cli\", # the binary
+]
+
+[workspace.package]
+version = \"0.3.0\"
+".to_string()
+    }
+
+    #[test]
+    fn read_workspace_members() {
+        assert_eq!(workspace_members(&example_workspace()), vec!["crates/*".to_owned(), "tools/cli".to_owned()]);
+        assert!(workspace_members(&example_file()).is_empty());
+    }
+
+    #[test]
+    fn detect_inherited_version() {
+        assert!(inherits_workspace_version("[package]\nname = \"a\"\nversion.workspace = true\n"));
+        assert!(inherits_workspace_version("[package]\nname = \"a\"\nversion = { workspace = true }\n"));
+        assert!(!inherits_workspace_version(&example_file()));
+    }
+
+    #[test]
+    fn replace_workspace_package_version() {
+        let updated = replace_in_section(&example_workspace(), "workspace.package", "version", "0.4.0").unwrap();
+        assert!(updated.contains("version = \"0.4.0\""));
+        assert!(updated.contains("\"tools/cli\", # the binary"));
+        assert!(replace_in_section(&example_workspace(), "package", "version", "0.4.0").is_none());
+    }
+
+    #[test]
+    fn write_new_version_keeps_comments_and_formatting() {
+        let file = "# The semantic-rs manifest
+[package]
+name    = \"semantic-rs\" # aligned on purpose
+version = \"0.1.0-beta.1\" # bumped by semantic-rs
+keywords = [
+    \"semver\",   \"release\",
+]
+
+[dependencies]
+# keep in sync with clog
+regex = { version = \"0.1.0\" }
+".to_string();
+
+        let expected = file.replace("0.1.0-beta.1", "0.2.0");
+        assert_eq!(file_with_new_version(file, "0.2.0"), expected);
+    }
+
+    #[test]
+    fn write_new_version_ignores_dependency_versions() {
+        let file = "[dependencies]
+foo = { version = \"1.0.0\" }
+
+[package]
+name = \"bar\"
+version = \"0.1.0\"
+".to_string();
+
+        let new_file = file_with_new_version(file, "0.2.0");
+        assert!(new_file.contains("foo = { version = \"1.0.0\" }"));
+        assert!(new_file.contains("version = \"0.2.0\""));
+    }
+
+    #[test]
+    fn write_new_version_into_literal_string() {
+        let file = "[package]\nname = 'semantic-rs'\nversion = '1.0.0' # literal\n".to_string();
+        assert_eq!(value_in_section(&file, "package", "version"), Some("1.0.0".into()));
+        assert_eq!(file_with_new_version(file, "1.1.0"), "[package]\nname = 'semantic-rs'\nversion = '1.1.0' # literal\n");
+    }
+
+    #[test]
+    fn update_dependency_versions_between_members() {
+        let file = "[package]
+name = \"crate-b\"
+version = \"0.3.0\"
+
+[dependencies]
+crate-a = \"0.3\"
+aliased = { package = \"crate-c\", version = \"^0.3.1\", path = \"../c\" }
+from-git = { git = \"https://example.com/crate-a\", package = \"crate-a\", version = \"0.3\" }
+regex = \"0.1\"
+
+[dev-dependencies.crate-a]
+version = \"=0.3.0\"
+path = \"../a\"
+
+[build-dependencies.other]
+package = \"crate-a\"
+registry = \"internal\"
+version = \"0.3\"
+".to_string();
+
+        let mut new_versions = HashMap::new();
+        new_versions.insert("crate-a".to_owned(), "0.4.0".to_owned());
+        new_versions.insert("crate-c".to_owned(), "0.4.0".to_owned());
+
+        let expected = "[package]
+name = \"crate-b\"
+version = \"0.3.0\"
+
+[dependencies]
+crate-a = \"0.4\"
+aliased = { package = \"crate-c\", version = \"^0.4.0\", path = \"../c\" }
+from-git = { git = \"https://example.com/crate-a\", package = \"crate-a\", version = \"0.3\" }
+regex = \"0.1\"
+
+[dev-dependencies.crate-a]
+version = \"=0.4.0\"
+path = \"../a\"
+
+[build-dependencies.other]
+package = \"crate-a\"
+registry = \"internal\"
+version = \"0.3\"
+".to_string();
+
+        assert_eq!(file_with_new_dependency_versions(&file, &new_versions), expected);
+
+        new_versions.insert("crate-a".to_owned(), "0.4.0-rc.1".to_owned());
+        new_versions.insert("crate-c".to_owned(), "0.4.0-rc.1+build.5".to_owned());
+        let expected = expected
+            .replace("crate-a = \"0.4\"", "crate-a = \"0.4.0-rc.1\"")
+            .replace("^0.4.0", "^0.4.0-rc.1")
+            .replace("=0.4.0", "=0.4.0-rc.1");
+
+        assert_eq!(file_with_new_dependency_versions(&file, &new_versions), expected);
+    }
+
+    #[test]
+    fn dependency_names_skip_dev_dependencies() {
+        let file = "[package]
+name = \"crate-b\"
+publish = false
+
+[dependencies]
+crate-a = \"0.3\"
+aliased = { package = \"crate-c\", path = \"../c\" }
+serde.workspace = true
+
+[dev-dependencies]
+crate-d = \"0.3\"
+
+[build-dependencies.other]
+package = \"crate-e\"
+version = \"0.3\"
+
+[target.'cfg(unix)'.dependencies]
+crate-a = \"0.3\"
+";
+
+        assert_eq!(vec!["crate-a", "crate-c", "serde", "crate-e"], dependency_names(file));
+        assert!(!is_publishable(file));
+        assert!(is_publishable("[package]\nname = \"crate-a\"\n"));
+    }
 }
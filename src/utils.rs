@@ -1,53 +1,163 @@
 use url::{Url, ParseError};
+use hyper::Client;
+use hyper::net::HttpsConnector;
+use hyper_native_tls::NativeTlsClient;
+use regex::Regex;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub fn user_repo_from_url(url: &str) -> Result<(String, String), String> {
-    let path = match Url::parse(url) {
+/// Whether we are running on a CI server.
+pub fn ci_env_set() -> bool {
+    env::var("CI").is_ok()
+}
+
+/// Reads a token from a file, like a mounted Docker or Kubernetes secret.
+/// A missing or empty file is an error.
+pub fn read_token_file(path: &Path) -> Result<String, String> {
+    let mut content = String::new();
+    try!(File::open(path)
+         .and_then(|mut file| file.read_to_string(&mut content))
+         .map_err(|e| format!("Can't read the token file {}: {}", path.display(), e)));
+
+    token_from_file_content(&content)
+        .ok_or(format!("The token file {} is empty", path.display()))
+}
+
+/// Files usually end with a newline, which isn't part of the token.
+fn token_from_file_content(content: &str) -> Option<String> {
+    let token = content.trim_right();
+    if token.is_empty() { None } else { Some(token.to_owned()) }
+}
+
+pub fn https_client() -> Client {
+    Client::with_connector(
+        HttpsConnector::new(
+            NativeTlsClient::new().unwrap()
+        )
+    )
+}
+
+/// Splits a remote URL into its host and repository path (without a `.git` suffix).
+/// Handles regular URLs as well as the scp-like `git@host:path` syntax.
+pub fn host_and_path_from_url(url: &str) -> Result<(String, String), String> {
+    let (host, path) = match Url::parse(url) {
         Err(ParseError::RelativeUrlWithoutBase) => {
-            match url.rfind(":") {
-                None => return Err("Can't parse path from remote URL".into()),
-                Some(colon_pos) => {
-                    Some(url[colon_pos+1..].split('/')
-                                           .map(|s| s.to_owned())
-                                           .collect::<Vec<_>>())
-                }
-            }
+            let colon_pos = match url.find(':') {
+                None => return Err(format!("Can't parse remote URL '{}', expected something like https://host/user/repo or git@host:user/repo", url)),
+                Some(colon_pos) => colon_pos,
+            };
+            let host = &url[..colon_pos];
+            let host = host.rsplit('@').next().unwrap_or(host);
+            (host.to_owned(), url[colon_pos+1..].to_owned())
         }
-        Err(_) => return Err("Can't parse remote URL".into()),
-        Ok(url) => {
-            url.path_segments()
-                .map(|path| {
-                    path.map(|seg| seg.to_owned())
-                        .collect::<Vec<_>>()
-                })
+        Err(err) => return Err(format!("Can't parse remote URL '{}': {}", url, err)),
+        Ok(parsed) => {
+            match parsed.host_str() {
+                Some(host) => (host.to_owned(), parsed.path().to_owned()),
+                None => return Err(format!("Remote URL '{}' has no host", url)),
+            }
         }
     };
 
-    let path = match path {
-        Some(ref path) if path.len() == 2 => path,
-        _ => return Err("URL should contain user and repository".into()),
-    };
+    let path = path.trim_matches('/');
+    let path = if path.ends_with(".git") { &path[..path.len() - 4] } else { path };
 
-    let user = path[0].clone();
-    let repo = match path[1].rfind(".git") {
-        None => path[1].clone(),
-        Some(suffix_pos) => {
-            let valid_pos = path[1].len() - 4;
-            if valid_pos == suffix_pos {
-                let path = &path[1][0..suffix_pos];
-                path.into()
-            } else {
-                path[1].clone()
-            }
-        }
-    };
+    if host.is_empty() || path.is_empty() {
+        return Err(format!("Remote URL '{}' should contain a host and repository path", url));
+    }
+
+    Ok((host, path.to_owned()))
+}
+
+/// Extracts user and repository name from a remote URL.
+/// Accepts `https://host/user/repo`, `ssh://git@host/user/repo` and `git@host:user/repo`,
+/// each with an optional `.git` suffix and trailing slash.
+pub fn user_repo_from_url(url: &str) -> Result<(String, String), String> {
+    let (_, path) = try!(host_and_path_from_url(url));
+
+    let segments = path.split('/').collect::<Vec<_>>();
+    if segments.len() != 2 || segments.iter().any(|segment| segment.is_empty()) {
+        return Err(format!("Remote URL '{}' should contain exactly a user and a repository name", url));
+    }
+
+    Ok((segments[0].to_owned(), segments[1].to_owned()))
+}
+
+/// Replaces every `{name}` placeholder in `template` with its value.
+/// Unknown placeholders are left untouched.
+pub fn render_template(template: &str, values: &[(&str, &str)]) -> String {
+    values.iter().fold(template.to_owned(), |rendered, &(name, value)| {
+        rendered.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+/// Turns a shell-style pattern (`*` and `?` wildcards) into an anchored regex.
+pub fn glob_to_regex(pattern: &str) -> Regex {
+    let escaped = pattern.chars().map(|c| match c {
+        '*' => ".*".to_owned(),
+        '?' => ".".to_owned(),
+        c if c.is_alphanumeric() || c == '_' || c == '-' => c.to_string(),
+        c => format!("\\{}", c),
+    }).collect::<String>();
+
+    Regex::new(&format!("^{}$", escaped)).unwrap()
+}
 
-    Ok((user, repo))
+/// Whether the repository-relative `path` is matched by `pattern`: a glob like `*.md`,
+/// or a directory like `docs` or `docs/`, which matches everything below it.
+pub fn path_matches(pattern: &str, path: &str) -> bool {
+    let directory = pattern.trim_right_matches('/');
+    if path.starts_with(directory) && path[directory.len()..].starts_with('/') {
+        return true;
+    }
+    glob_to_regex(pattern).is_match(path)
+}
+
+/// Formats the UTC date of `time` as `YYYY-MM-DD`.
+pub fn iso_date(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+
+    // Days since 1970-01-01 to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn trims_token_files() {
+        assert_eq!(Some("ghp_secret".to_owned()), token_from_file_content("ghp_secret\n"));
+        assert_eq!(Some("ghp_secret".to_owned()), token_from_file_content("ghp_secret \r\n\n"));
+        assert_eq!(None, token_from_file_content("\n"));
+        assert_eq!(None, token_from_file_content(""));
+    }
+
+    #[test]
+    fn matches_paths() {
+        assert!(path_matches("docs", "docs/index.md"));
+        assert!(path_matches("docs/", "docs/guide/setup.md"));
+        assert!(path_matches("*.md", "README.md"));
+        assert!(path_matches("examples/*.rs", "examples/analysis_speed.rs"));
+        assert!(!path_matches("docs", "docs.rs"));
+        assert!(!path_matches("docs", "src/docs/mod.rs"));
+        assert!(!path_matches("*.md", "src/lib.rs"));
+    }
+
     #[test]
     fn parses_remote_urls() {
         let urls = [
@@ -59,6 +169,10 @@ mod test {
 
             "ssh://github.com/user/repo",
             "ssh://github.com/user/repo.git",
+            "ssh://git@github.com/user/repo.git",
+
+            "https://github.com/user/repo/",
+            "git@github.com:user/repo.git/",
         ];
 
         for url in &urls {
@@ -91,6 +205,8 @@ mod test {
             "https://github.com/user",
             "https://github.com/user/repo/issues",
             "://github.com/user/",
+            "/srv/git/repo.git",
+            "file:///srv/git/user/repo.git",
         ];
 
         for url in &urls {
@@ -98,4 +214,48 @@ mod test {
             assert!(user_repo_from_url(url).is_err());
         }
     }
+
+    #[test]
+    fn renders_templates() {
+        let values = [("version", "1.2.0"), ("tag", "v1.2.0")];
+
+        assert_eq!("Bump version to 1.2.0", render_template("Bump version to {version}", &values));
+        assert_eq!("chore(release): v1.2.0 [1.2.0]", render_template("chore(release): {tag} [{version}]", &values));
+        assert_eq!("Release {unknown}", render_template("Release {unknown}", &values));
+    }
+
+    #[test]
+    fn splits_host_and_path() {
+        let urls = [
+            ("https://gitlab.com/group/subgroup/repo.git", "gitlab.com", "group/subgroup/repo"),
+            ("git@gitlab.example.com:group/repo.git", "gitlab.example.com", "group/repo"),
+            ("ssh://git@gitlab.com/user/repo", "gitlab.com", "user/repo"),
+            ("https://jan@bitbucket.org/team/repo.git", "bitbucket.org", "team/repo"),
+            ("git@bitbucket.org:team/repo.git", "bitbucket.org", "team/repo"),
+        ];
+
+        for &(url, exp_host, exp_path) in &urls {
+            let (host, path) = host_and_path_from_url(url).unwrap();
+            assert_eq!(exp_host, host);
+            assert_eq!(exp_path, path);
+        }
+    }
+
+    #[test]
+    fn matches_branch_patterns() {
+        assert!(glob_to_regex("master").is_match("master"));
+        assert!(!glob_to_regex("master").is_match("master-old"));
+        assert!(glob_to_regex("release/*").is_match("release/1.x"));
+        assert!(!glob_to_regex("release/*").is_match("releases"));
+        assert!(glob_to_regex("v?.x").is_match("v1.x"));
+    }
+
+    #[test]
+    fn formats_iso_dates() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        assert_eq!("1970-01-01", iso_date(UNIX_EPOCH));
+        assert_eq!("2000-02-29", iso_date(UNIX_EPOCH + Duration::from_secs(951782400)));
+        assert_eq!("2023-11-14", iso_date(UNIX_EPOCH + Duration::from_secs(1700000000)));
+    }
 }
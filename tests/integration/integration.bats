@@ -21,13 +21,13 @@ setup_dirs() {
 
 @test "it runs" {
   run semantic-rs
-  [ "$status" -eq 1 ]
+  [ "$status" -eq 3 ]
 }
 
 @test "fails without Cargo.toml" {
   cd empty-dir
   run semantic-rs
-  [ "$status" -eq 1 ]
+  [ "$status" -eq 3 ]
 }
 
 @test "fails on non-git directories" {
@@ -35,7 +35,7 @@ setup_dirs() {
   setup_dirs
 
   run semantic-rs
-  [ "$status" -eq 1 ]
+  [ "$status" -eq 3 ]
 }
 
 @test "fails with broken Cargo.toml" {
@@ -43,15 +43,32 @@ setup_dirs() {
   setup_dirs
 
   run semantic-rs
-  [ "$status" -eq 1 ]
+  [ "$status" -eq 2 ]
 }
 
-@test "Initializes to v1.0.0" {
+@test "Releases the current 0.x version first" {
   cd initial-release
   setup_dirs
 
   semantic-rs --write=yes --release=no
+  grep -q 'version = "0.1.0"' Cargo.toml
+
+  run git tag
+  [ "${lines[0]}" = "v0.1.0" ]
+}
+
+@test "Analyzes all commits without a release tag" {
+  cd untagged-history
+  setup_dirs
+
   grep -q 'version = "1.0.0"' Cargo.toml
+
+  semantic-rs --write=yes --release=no
+
+  grep -q 'version = "1.1.0"' Cargo.toml
+
+  run git tag
+  [ "${lines[0]}" = "v1.1.0" ]
 }
 
 @test "Bumps to next minor" {
@@ -68,6 +85,24 @@ setup_dirs() {
   [ "${lines[0]}" = "Bump version to 1.1.0" ]
 }
 
+@test "Only commits the files it changed" {
+  cd unrelated-changes
+  setup_dirs
+
+  echo "// local edit" >> src/lib.rs
+  git add src/lib.rs
+
+  semantic-rs --write=yes --release=no --allow-dirty
+
+  run git diff-tree --no-commit-id --name-only -r HEAD
+  [ "${lines[0]}" = "Cargo.toml" ]
+  [ "${lines[1]}" = "Changelog.md" ]
+  [ "${#lines[@]}" -eq 2 ]
+
+  run git diff HEAD --name-only
+  [ "$output" = "src/lib.rs" ]
+}
+
 @test "No bump when no new commits" {
   cd no-bump
   setup_dirs
@@ -159,3 +194,13 @@ setup_dirs() {
   [ "$status" -eq 0 ]
   [[ "$output" =~ "semantic-rs can't push changes or create a release on GitHub" ]]
 }
+
+@test "Runs in dry-run-mode with CI=true and --dry-run" {
+  cd dry-run-flag
+  setup_dirs
+
+  CI=true run semantic-rs --dry-run --release=yes
+  [ "$status" -eq 0 ]
+  [[ "$output" =~ "Would write the following Changelog" ]]
+  grep -q 'version = "0.1.0"' Cargo.toml
+}
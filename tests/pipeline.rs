@@ -0,0 +1,501 @@
+extern crate semantic_rs;
+extern crate git2;
+extern crate tempdir;
+
+use git2::{ObjectType, Repository, Signature};
+use semantic_rs::commit_analyzer::CommitType;
+use semantic_rs::git;
+use semantic_rs::{ConfigBuilder, Error, Outcome};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use tempdir::TempDir;
+
+const MANIFEST: &'static str = r#"[package]
+name = "fixture"
+version = "1.0.0"
+authors = ["Jan Schulte <hello@unexpected-co.de>"]
+"#;
+
+fn signature() -> Signature<'static> {
+    Signature::now("Jan Schulte", "hello@unexpected-co.de").unwrap()
+}
+
+/// A repository with a `Cargo.toml` at 1.0.0 that was never released.
+fn unreleased_repository() -> (TempDir, Repository) {
+    let dir = TempDir::new("semantic-rs").unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+
+    commit(&repo, "Cargo.toml", MANIFEST, "chore: initial commit");
+    (dir, repo)
+}
+
+/// A repository with a `Cargo.toml` at 1.0.0, released as `v1.0.0`.
+fn released_repository() -> (TempDir, Repository) {
+    let (dir, repo) = unreleased_repository();
+    {
+        let head = repo.revparse_single("HEAD").unwrap();
+        repo.tag("v1.0.0", &head, &signature(), "Release v1.0.0", false).unwrap();
+    }
+
+    (dir, repo)
+}
+
+/// Commits `content` as `file`, each call adds a new commit on top of HEAD.
+fn commit(repo: &Repository, file: &str, content: &str, message: &str) {
+    let root = repo.workdir().unwrap().to_owned();
+    fs::create_dir_all(root.join(file).parent().unwrap()).unwrap();
+    File::create(root.join(file)).unwrap().write_all(content.as_bytes()).unwrap();
+
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new(file)).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+
+    let parent = repo.refname_to_id("HEAD").ok().map(|oid| repo.find_commit(oid).unwrap());
+    let parents = parent.iter().collect::<Vec<_>>();
+
+    let sig = signature();
+    repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents).unwrap();
+}
+
+/// Runs the pipeline in dry-run mode, as semantic-rs does without flags.
+fn dry_run(dir: &TempDir) -> Outcome {
+    dry_run_with(dir, |_| {}).unwrap()
+}
+
+fn dry_run_with<F: FnOnce(&mut ConfigBuilder)>(dir: &TempDir, configure: F) -> Result<Outcome, Error> {
+    let path = dir.path().to_str().unwrap().to_owned();
+
+    let mut builder = ConfigBuilder::new();
+    builder.repository_path(path.clone());
+    builder.repository(Repository::open(&path).unwrap());
+    builder.signature(signature());
+    builder.write(false);
+    builder.release(false);
+    configure(&mut builder);
+
+    semantic_rs::run(&builder.build())
+}
+
+/// Runs the pipeline in write mode: commits and tags, but doesn't push or publish.
+fn write_run_with<F: FnOnce(&mut ConfigBuilder)>(dir: &TempDir, configure: F) -> Outcome {
+    let branch = {
+        let repo = Repository::open(dir.path()).unwrap();
+        let head = repo.head().unwrap();
+        head.shorthand().unwrap().to_owned()
+    };
+
+    dry_run_with(dir, |builder| {
+        builder.write(true);
+        builder.publish(false);
+        builder.branch(branch.clone());
+        builder.release_branches(vec![branch]);
+        configure(builder);
+    }).unwrap()
+}
+
+#[test]
+fn fixes_bump_the_patch_version() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "README.md", "semantic-rs", "fix: handle empty input");
+    commit(&repo, "README.md", "semantic-rs!", "docs: explain the fix");
+
+    let outcome = dry_run(&dir);
+
+    assert_eq!(Some("1.0.0".to_owned()), outcome.current_version);
+    assert_eq!(Some(CommitType::Patch), outcome.bump);
+    assert_eq!(Some("1.0.1".to_owned()), outcome.new_version);
+    assert_eq!(Some("v1.0.1".to_owned()), outcome.tag_name);
+    assert!(!outcome.released);
+
+    let changelog = outcome.changelog.unwrap();
+    assert!(changelog.contains("Bug Fixes"), "{}", changelog);
+    assert!(changelog.contains("handle empty input"), "{}", changelog);
+}
+
+#[test]
+fn features_bump_the_minor_version() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "README.md", "semantic-rs", "fix: handle empty input");
+    commit(&repo, "README.md", "semantic-rs --quiet", "feat: add a quiet flag");
+
+    let outcome = dry_run(&dir);
+
+    assert_eq!(Some(CommitType::Minor), outcome.bump);
+    assert_eq!(Some("1.1.0".to_owned()), outcome.new_version);
+
+    let changelog = outcome.changelog.unwrap();
+    assert!(changelog.contains("Features"), "{}", changelog);
+    assert!(changelog.contains("add a quiet flag"), "{}", changelog);
+    assert!(changelog.contains("handle empty input"), "{}", changelog);
+}
+
+#[test]
+fn breaking_changes_bump_the_major_version() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "README.md", "semantic-rs", "feat: add a quiet flag");
+    commit(&repo, "README.md", "semantic-rs run",
+           "feat: require a subcommand\n\nBREAKING CHANGE: running without a subcommand is an error");
+
+    let outcome = dry_run(&dir);
+
+    assert_eq!(Some(CommitType::Major), outcome.bump);
+    assert_eq!(Some("2.0.0".to_owned()), outcome.new_version);
+    assert!(outcome.changelog.unwrap().contains("require a subcommand"));
+}
+
+#[test]
+fn no_relevant_commits_means_no_release() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "README.md", "semantic-rs", "docs: add a readme");
+    commit(&repo, "README.md", "semantic-rs\n", "chore: trailing newline");
+
+    let outcome = dry_run(&dir);
+
+    assert_eq!(Some("1.0.0".to_owned()), outcome.current_version);
+    assert_eq!(Some(CommitType::None), outcome.bump);
+    assert_eq!(None, outcome.new_version);
+    assert_eq!(None, outcome.changelog);
+}
+
+#[test]
+fn pull_requests_are_never_released() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "README.md", "semantic-rs", "fix: handle empty input");
+
+    let outcome = write_run_with(&dir, |builder| { builder.pull_request(true); });
+
+    assert_eq!(Some("1.0.0".to_owned()), outcome.current_version);
+    assert_eq!(None, outcome.bump);
+    assert_eq!(None, outcome.new_version);
+    assert!(repo.find_reference("refs/tags/v1.0.1").is_err());
+}
+
+#[test]
+fn skipped_releases_report_the_current_version() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "README.md", "semantic-rs", "fix: handle empty input\n\n[skip release]");
+
+    let outcome = dry_run(&dir);
+
+    assert_eq!(Some("1.0.0".to_owned()), outcome.current_version);
+    assert_eq!(None, outcome.new_version);
+}
+
+#[test]
+fn commits_in_excluded_paths_do_not_bump() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "docs/guide.md", "Quiet mode", "feat: document the quiet flag");
+    commit(&repo, "README.md", "semantic-rs", "fix: handle empty input");
+
+    assert_eq!(Some(CommitType::Minor), dry_run(&dir).bump);
+
+    let outcome = dry_run_with(&dir, |builder| { builder.exclude_path("docs".into()); }).unwrap();
+    assert_eq!(Some(CommitType::Patch), outcome.bump);
+    assert_eq!(Some("1.0.1".to_owned()), outcome.new_version);
+
+    let outcome = dry_run_with(&dir, |builder| {
+        builder.exclude_path("docs/".into());
+        builder.exclude_path("*.md".into());
+    }).unwrap();
+    assert_eq!(None, outcome.new_version);
+}
+
+#[test]
+fn only_commits_in_included_paths_count() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "crates/a/src/lib.rs", "pub fn a() {}", "feat: add a");
+    commit(&repo, "crates/b/src/lib.rs", "pub fn b() {}", "feat: add b");
+    commit(&repo, "crates/b/src/lib.rs", "pub fn b() -> u8 { 0 }", "feat!: return a value from b");
+    commit(&repo, "crates/a/src/lib.rs", "pub fn a() { }", "fix: format a");
+
+    let outcome = dry_run_with(&dir, |builder| { builder.include_path("crates/a".into()); }).unwrap();
+    assert_eq!(Some(CommitType::Minor), outcome.bump);
+    let changelog = outcome.changelog.unwrap();
+    assert!(changelog.contains("add a"), "{}", changelog);
+    assert!(!changelog.contains("add b"), "{}", changelog);
+
+    let outcome = dry_run_with(&dir, |builder| { builder.include_path("crates/b/".into()); }).unwrap();
+    assert_eq!(Some(CommitType::Major), outcome.bump);
+}
+
+#[test]
+fn written_changelog_only_lists_included_paths() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "crates/a/src/lib.rs", "pub fn a() {}", "feat: add a");
+    commit(&repo, "crates/b/src/lib.rs", "pub fn b() {}", "fix: add b");
+
+    write_run_with(&dir, |builder| { builder.include_path("crates/a".into()); });
+
+    let mut changelog = String::new();
+    File::open(dir.path().join("Changelog.md")).unwrap().read_to_string(&mut changelog).unwrap();
+    assert!(changelog.contains("add a"), "{}", changelog);
+    assert!(!changelog.contains("add b"), "{}", changelog);
+    assert!(!changelog.contains("Bug Fixes"), "{}", changelog);
+}
+
+#[test]
+fn first_release_keeps_the_manifest_version() {
+    let (dir, repo) = unreleased_repository();
+    commit(&repo, "README.md", "semantic-rs", "feat: add a quiet flag");
+
+    let outcome = dry_run_with(&dir, |builder| { builder.first_release(true); }).unwrap();
+
+    assert_eq!(Some("1.0.0".to_owned()), outcome.new_version);
+    assert_eq!(Some("v1.0.0".to_owned()), outcome.tag_name);
+    let changelog = outcome.changelog.unwrap();
+    assert!(changelog.contains("add a quiet flag"), "{}", changelog);
+}
+
+#[test]
+fn first_release_of_a_tagged_version_fails() {
+    let (dir, _repo) = released_repository();
+
+    let result = dry_run_with(&dir, |builder| { builder.first_release(true); });
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn rewritten_history_is_only_fatal_when_strict() {
+    let (dir, repo) = released_repository();
+    // Move the branch away from the tagged commit, as a force-push would
+    {
+        let released = repo.revparse_single("HEAD").unwrap().id();
+        let tree = repo.find_commit(released).unwrap().tree().unwrap();
+        let sig = signature();
+        let rewritten = repo.commit(None, &sig, &sig, "chore: rewritten initial commit", &tree, &[]).unwrap();
+        repo.reference("refs/heads/master", rewritten, true, "force-push").unwrap();
+    }
+    commit(&repo, "README.md", "semantic-rs", "fix: handle empty input");
+
+    assert_eq!(Some("1.0.1".to_owned()), dry_run(&dir).new_version);
+
+    let result = dry_run_with(&dir, |builder| { builder.strict_history(true); });
+    assert!(result.is_err());
+}
+
+#[test]
+fn existing_versions_are_not_released_again() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "README.md", "semantic-rs", "fix: handle empty input");
+    {
+        // Released without bumping Cargo.toml
+        let head = repo.revparse_single("HEAD").unwrap();
+        repo.tag("v1.0.1", &head, &signature(), "Release v1.0.1", false).unwrap();
+    }
+    commit(&repo, "README.md", "semantic-rs!", "fix: handle more empty input");
+
+    let result = dry_run_with(&dir, |_| {});
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn release_only_uses_the_tag_message() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "README.md", "semantic-rs", "fix: handle empty input");
+
+    let outcome = dry_run_with(&dir, |builder| { builder.release_only("v1.0.0".into()); }).unwrap();
+
+    assert_eq!(Some("1.0.0".to_owned()), outcome.new_version);
+    assert_eq!(Some("v1.0.0".to_owned()), outcome.tag_name);
+    assert_eq!(Some("Release v1.0.0".to_owned()), outcome.changelog);
+    assert!(!outcome.released);
+}
+
+#[test]
+fn version_is_read_from_the_version_file() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "version.txt", "1.2.0\n", "chore: track the version in version.txt");
+    commit(&repo, "README.md", "semantic-rs", "fix: handle empty input");
+
+    let outcome = dry_run_with(&dir, |builder| { builder.version_file("version.txt".into()); }).unwrap();
+
+    assert_eq!(Some("1.2.0".to_owned()), outcome.current_version);
+    assert_eq!(Some("1.2.1".to_owned()), outcome.new_version);
+}
+
+#[test]
+fn since_overrides_the_latest_release_tag() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "README.md", "semantic-rs", "feat: add a quiet flag");
+    commit(&repo, "Cargo.toml", &MANIFEST.replace("1.0.0", "1.1.0"), "chore(release): 1.1.0");
+    {
+        let head = repo.revparse_single("HEAD").unwrap();
+        repo.tag("v1.1.0", &head, &signature(), "Release v1.1.0", false).unwrap();
+    }
+    commit(&repo, "README.md", "semantic-rs!", "fix: handle empty input");
+
+    assert_eq!(Some(CommitType::Patch), dry_run(&dir).bump);
+
+    let outcome = dry_run_with(&dir, |builder| { builder.since("v1.0.0".into()); }).unwrap();
+    assert_eq!(Some(CommitType::Minor), outcome.bump);
+    assert_eq!(Some("1.2.0".to_owned()), outcome.new_version);
+    let changelog = outcome.changelog.unwrap();
+    assert!(changelog.contains("add a quiet flag"), "{}", changelog);
+    assert!(changelog.contains("handle empty input"), "{}", changelog);
+
+    let result = dry_run_with(&dir, |builder| { builder.since("v0.9.0".into()); });
+    assert!(result.is_err());
+}
+
+#[test]
+fn regenerating_the_changelog_covers_every_release() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "README.md", "semantic-rs", "feat: add a quiet flag");
+    commit(&repo, "README.md", "semantic-rs!", "fix: handle empty input");
+    {
+        let head = repo.revparse_single("HEAD").unwrap();
+        repo.tag("v1.1.0", &head, &signature(), "Release v1.1.0", false).unwrap();
+    }
+    commit(&repo, "README.md", "semantic-rs run", "feat: add a run subcommand");
+
+    let outcome = dry_run_with(&dir, |builder| { builder.regenerate_changelog(true); }).unwrap();
+    let changelog = outcome.changelog.unwrap();
+    assert_eq!(None, outcome.new_version);
+    assert!(changelog.find("## v1.1.0").unwrap() < changelog.find("## v1.0.0").unwrap(), "{}", changelog);
+    assert!(changelog.contains("add a quiet flag"), "{}", changelog);
+    assert!(changelog.contains("handle empty input"), "{}", changelog);
+    assert!(!changelog.contains("add a run subcommand"), "{}", changelog);
+
+    let regenerate = || {
+        dry_run_with(&dir, |builder| {
+            builder.regenerate_changelog(true);
+            builder.write(true);
+        }).unwrap();
+        let mut content = String::new();
+        File::open(dir.path().join("Changelog.md")).unwrap().read_to_string(&mut content).unwrap();
+        content
+    };
+    let first = regenerate();
+    assert!(first.starts_with("# Changelog\n\n"), "{}", first);
+    assert_eq!(first, regenerate());
+}
+
+#[test]
+fn default_branch_of_the_remote() {
+    let (_dir, repo) = released_repository();
+    assert_eq!(None, git::default_branch(&repo, "origin"));
+
+    let head = repo.refname_to_id("HEAD").unwrap();
+    repo.reference("refs/remotes/origin/main", head, false, "fetch").unwrap();
+    repo.reference_symbolic("refs/remotes/origin/HEAD", "refs/remotes/origin/main", false, "clone").unwrap();
+    assert_eq!(Some("main".to_owned()), git::default_branch(&repo, "origin"));
+    assert_eq!(None, git::default_branch(&repo, "upstream"));
+}
+
+#[test]
+fn printed_config_hides_tokens() {
+    let (dir, _repo) = released_repository();
+    let path = dir.path().to_str().unwrap().to_owned();
+
+    let mut builder = ConfigBuilder::new();
+    builder.repository_path(path.clone());
+    builder.repository(Repository::open(&path).unwrap());
+    builder.signature(signature());
+    builder.gh_token("ghp_secret".into());
+    let config = builder.build().to_json();
+
+    assert!(!config.to_string().contains("ghp_secret"), "{}", config);
+    assert_eq!(Some("<redacted>"), config.find("gh_token").and_then(|token| token.as_string()));
+    assert!(config.find("cargo_token").unwrap().is_null());
+    assert_eq!(Some("Jan Schulte <hello@unexpected-co.de>"), config.find("committer").and_then(|c| c.as_string()));
+}
+
+#[test]
+fn release_tags_are_annotated_by_default() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "README.md", "semantic-rs", "fix: handle empty input");
+
+    write_run_with(&dir, |_| {});
+
+    let tag = repo.revparse_single("refs/tags/v1.0.1").unwrap();
+    assert_eq!(Some(ObjectType::Tag), tag.kind());
+    assert!(tag.as_tag().unwrap().message().unwrap().contains("handle empty input"));
+}
+
+#[test]
+fn lightweight_tags_point_at_the_release_commit() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "README.md", "semantic-rs", "fix: handle empty input");
+
+    let outcome = write_run_with(&dir, |builder| { builder.tag_annotated(false); });
+
+    let tag = repo.revparse_single("refs/tags/v1.0.1").unwrap();
+    assert_eq!(Some(ObjectType::Commit), tag.kind());
+    assert_eq!(repo.revparse_single("HEAD").unwrap().id(), tag.id());
+    // The release notes don't depend on the tag message
+    assert!(outcome.changelog.unwrap().contains("handle empty input"));
+}
+
+#[test]
+fn signed_lightweight_tags_fail_before_committing() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "README.md", "semantic-rs", "fix: handle empty input");
+    let head = repo.revparse_single("HEAD").unwrap().id();
+
+    let result = dry_run_with(&dir, |builder| {
+        builder.write(true);
+        builder.tag_annotated(false);
+        builder.sign_tags(true);
+    });
+
+    assert!(result.is_err());
+    assert_eq!(head, repo.revparse_single("HEAD").unwrap().id());
+}
+
+#[test]
+fn tag_message_is_rendered_from_the_template() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "README.md", "semantic-rs", "fix: handle empty input");
+
+    let outcome = write_run_with(&dir, |builder| {
+        builder.tag_message_template("fixture {version}\n\n{changelog}".into());
+    });
+
+    let tag = repo.revparse_single("refs/tags/v1.0.1").unwrap();
+    let message = tag.as_tag().unwrap().message().unwrap().to_owned();
+    assert!(message.starts_with("fixture 1.0.1\n\n"), "{}", message);
+    assert!(message.contains("handle empty input"), "{}", message);
+    // The release notes are the changelog alone
+    assert!(!outcome.changelog.unwrap().contains("fixture 1.0.1"));
+}
+
+#[test]
+fn no_push_keeps_the_release_local() {
+    let (dir, _repo) = released_repository();
+    let path = dir.path().to_str().unwrap().to_owned();
+    let config = |push: bool| {
+        let mut builder = ConfigBuilder::new();
+        builder.repository_path(path.clone());
+        builder.repository(Repository::open(&path).unwrap());
+        builder.signature(signature());
+        builder.release(true);
+        builder.push(push);
+        builder.build()
+    };
+
+    assert!(config(true).releases_remotely());
+    assert!(!config(false).releases_remotely());
+}
+
+#[test]
+fn release_notes_are_written_to_the_output_file() {
+    let (dir, repo) = released_repository();
+    commit(&repo, "README.md", "semantic-rs", "feat: add a quiet flag");
+    let notes_dir = TempDir::new("semantic-rs-notes").unwrap();
+    let notes_path = notes_dir.path().join("notes.md");
+
+    let outcome = dry_run_with(&dir, |builder| {
+        builder.changelog_output(notes_path.to_str().unwrap().to_owned());
+    }).unwrap();
+
+    let mut notes = String::new();
+    File::open(&notes_path).unwrap().read_to_string(&mut notes).unwrap();
+    assert!(notes.contains("add a quiet flag"), "{}", notes);
+    assert!(!notes.contains("# Changelog"), "{}", notes);
+    assert!(!dir.path().join("Changelog.md").exists());
+    assert_eq!(Some("1.1.0".to_owned()), outcome.new_version);
+}